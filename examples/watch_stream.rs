@@ -0,0 +1,25 @@
+//! Prints HID devices as they are plugged in and removed, from async code.
+//!
+//! Run with `cargo run --example watch_stream --features tokio`.
+
+#[cfg(windows)]
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+  let runtime = tokio::runtime::Builder::new_current_thread().build()?;
+  runtime.block_on(async {
+    let mut events = listhid::DeviceWatcher::stream()?;
+    println!("Watching for HID devices, press Ctrl+C to stop.");
+    while let Some(event) = events.next().await {
+      match event {
+        listhid::HidEvent::Arrived(path) => println!("+ {}", path),
+        listhid::HidEvent::Removed(path) => println!("- {}", path),
+        event => println!("{:?}", event),
+      }
+    }
+    Ok(())
+  })
+}
+
+#[cfg(not(windows))]
+fn main() {
+  eprintln!("The device watcher is only available on Windows.");
+}
@@ -0,0 +1,233 @@
+use crate::open::check_output_report;
+use crate::win32::{create_event, read_file_overlapped, set_event, write_file_overlapped, Handle};
+use crate::{
+  AccessMode, DeviceWatcher, Error, HidCapabilities, HidDevice, HidEvent, OpenHidDevice,
+};
+use std::io;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use tokio::sync::mpsc::{self, UnboundedReceiver};
+
+// Handles are kernel object references, usable from any thread; the
+// overlapped I/O on them brings its own synchronization.
+struct SharedHandle(Handle);
+
+unsafe impl Send for SharedHandle {}
+unsafe impl Sync for SharedHandle {}
+
+/// Signals the cancel event of an operation when dropped, which makes the
+/// blocking task cancel the pending I/O and release its buffer.
+struct CancelOnDrop(Arc<SharedHandle>);
+
+impl Drop for CancelOnDrop {
+  fn drop(&mut self) {
+    let _ = set_event(&(self.0).0);
+  }
+}
+
+/// An `OpenHidDevice` for async code, reading and writing reports from a
+/// tokio runtime.
+///
+/// Each operation runs on tokio's blocking thread pool and owns its buffer.
+/// Dropping an operation's future cancels the pending I/O with `CancelIoEx`;
+/// the buffer is released once the cancellation has settled, so it is never
+/// written after being freed.
+pub struct AsyncOpenHidDevice {
+  handle: Arc<SharedHandle>,
+  path: String,
+  capabilities: HidCapabilities,
+}
+
+impl AsyncOpenHidDevice {
+  pub fn path(&self) -> &str {
+    &self.path
+  }
+
+  /// The capabilities read from the device when it was opened.
+  pub fn capabilities(&self) -> &HidCapabilities {
+    &self.capabilities
+  }
+
+  /// Waits for the next input report. The report starts with the report ID
+  /// byte, which is 0 for devices that don't use report IDs.
+  pub async fn read_report(&mut self) -> Result<Vec<u8>, Error> {
+    let length = self.capabilities.input_report_byte_length as usize;
+    self
+      .run(move |handle, event, cancel_event| {
+        let mut report = vec![0u8; length];
+        let bytes_read =
+          read_file_overlapped(handle, event, &mut report, None, Some(cancel_event))?;
+        report.truncate(bytes_read.unwrap_or(0));
+        Ok(report)
+      })
+      .await
+  }
+
+  /// Sends an output report and returns the number of bytes written, with
+  /// the same conventions as `OpenHidDevice::write`.
+  pub async fn write_report(&mut self, report: &[u8]) -> Result<usize, Error> {
+    check_output_report(self.capabilities.output_report_byte_length as usize, report)?;
+
+    let report = report.to_vec();
+    self
+      .run(move |handle, event, cancel_event| {
+        Ok(write_file_overlapped(handle, event, &report, Some(cancel_event))?.unwrap_or(0))
+      })
+      .await
+  }
+
+  /// Runs `operation` on the blocking thread pool with the device handle, an
+  /// event for the overlapped I/O and an event signaled on cancellation.
+  async fn run<T, F>(&mut self, operation: F) -> Result<T, Error>
+  where
+    T: Send + 'static,
+    F: FnOnce(&Handle, &Handle, &Handle) -> Result<T, io::Error> + Send + 'static,
+  {
+    let event = SharedHandle(create_event()?);
+    let cancel_event = Arc::new(SharedHandle(create_event()?));
+    let _cancel_on_drop = CancelOnDrop(cancel_event.clone());
+
+    let handle = self.handle.clone();
+    let result =
+      tokio::task::spawn_blocking(move || operation(&handle.0, &event.0, &cancel_event.0))
+        .await
+        .map_err(io::Error::other)?;
+
+    Ok(result?)
+  }
+}
+
+impl OpenHidDevice {
+  /// Converts the device for use from async code. It must have been opened
+  /// for overlapped I/O, as `HidDevice::open` and `open_with` do; otherwise
+  /// an `InvalidInput` error is returned.
+  pub fn into_async(self) -> Result<AsyncOpenHidDevice, Error> {
+    if !self.options().is_overlapped() {
+      return Err(Error::Io(io::Error::new(
+        io::ErrorKind::InvalidInput,
+        "device was not opened for overlapped I/O",
+      )));
+    }
+
+    let (handle, path, capabilities) = self.into_parts();
+    Ok(AsyncOpenHidDevice {
+      handle: Arc::new(SharedHandle(handle)),
+      path,
+      capabilities,
+    })
+  }
+}
+
+impl HidDevice {
+  /// Opens the device for reading and writing from async code.
+  pub fn open_async(&self) -> Result<AsyncOpenHidDevice, Error> {
+    self.open_with(AccessMode::ReadWrite)?.into_async()
+  }
+}
+
+/// The events of a `DeviceWatcher` for async code, see
+/// `DeviceWatcher::stream`.
+///
+/// With the `tokio` feature it is also a `futures_core::Stream`.
+pub struct HidEventStream {
+  receiver: UnboundedReceiver<HidEvent>,
+  // Dropped with the stream, which unregisters the notifications.
+  _watcher: Option<DeviceWatcher>,
+}
+
+impl HidEventStream {
+  /// A stream of the events passed to the returned callback, which ends
+  /// once the callback is dropped.
+  fn bridge() -> (impl FnMut(HidEvent) + Send + 'static, HidEventStream) {
+    let (sender, receiver) = mpsc::unbounded_channel();
+    let callback = move |event| {
+      // Fails only once the stream is being dropped.
+      let _ = sender.send(event);
+    };
+    let stream = HidEventStream {
+      receiver,
+      _watcher: None,
+    };
+    (callback, stream)
+  }
+
+  /// Waits for the next event. Returns `None` only if the watcher stopped.
+  pub async fn next(&mut self) -> Option<HidEvent> {
+    self.receiver.recv().await
+  }
+
+  /// Polls for the next event, in the shape of `Stream::poll_next`.
+  pub fn poll_next(&mut self, cx: &mut Context<'_>) -> Poll<Option<HidEvent>> {
+    self.receiver.poll_recv(cx)
+  }
+}
+
+impl futures_core::Stream for HidEventStream {
+  type Item = HidEvent;
+
+  fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<HidEvent>> {
+    HidEventStream::poll_next(self.get_mut(), cx)
+  }
+}
+
+impl DeviceWatcher {
+  /// Watches for HID interfaces being added and removed, delivering the
+  /// events to async code. Dropping the stream stops the watcher.
+  ///
+  /// ```no_run
+  /// # async fn watch() -> Result<(), listhid::Error> {
+  /// let mut events = listhid::DeviceWatcher::stream()?;
+  /// while let Some(event) = events.next().await {
+  ///   println!("{:?}", event);
+  /// }
+  /// # Ok(())
+  /// # }
+  /// ```
+  pub fn stream() -> Result<HidEventStream, Error> {
+    let (callback, mut stream) = HidEventStream::bridge();
+    stream._watcher = Some(DeviceWatcher::new(callback)?);
+    Ok(stream)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn block_on<F: std::future::Future>(future: F) -> F::Output {
+    tokio::runtime::Builder::new_current_thread()
+      .build()
+      .unwrap()
+      .block_on(future)
+  }
+
+  #[test]
+  fn bridge_delivers_events_in_order() {
+    let (mut callback, mut stream) = HidEventStream::bridge();
+    callback(HidEvent::Arrived("first".to_owned()));
+    callback(HidEvent::Removed("second".to_owned()));
+    block_on(async {
+      assert!(matches!(stream.next().await, Some(HidEvent::Arrived(path)) if path == "first"));
+      assert!(matches!(stream.next().await, Some(HidEvent::Removed(path)) if path == "second"));
+    });
+  }
+
+  #[test]
+  fn bridge_ends_once_the_callback_is_dropped() {
+    let (mut callback, mut stream) = HidEventStream::bridge();
+    callback(HidEvent::Lagged(3));
+    drop(callback);
+    block_on(async {
+      assert!(matches!(stream.next().await, Some(HidEvent::Lagged(3))));
+      assert!(stream.next().await.is_none());
+    });
+  }
+
+  #[test]
+  fn callback_outlives_the_stream() {
+    let (mut callback, stream) = HidEventStream::bridge();
+    drop(stream);
+    callback(HidEvent::Arrived("late".to_owned()));
+  }
+}
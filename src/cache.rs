@@ -0,0 +1,57 @@
+use crate::{HidDevice, HidQuery, ListHidError};
+use std::time::{Duration, Instant};
+
+/// A snapshot of the HID devices that is listed again only once it is older
+/// than the caller allows, e.g. for a UI refreshed on a timer.
+///
+/// ```no_run
+/// # use std::time::Duration;
+/// let mut cache = listhid::HidDeviceCache::new();
+/// for device in cache.get(Duration::from_secs(2))? {
+///   println!("{}", device.path);
+/// }
+/// # Ok::<(), listhid::ListHidError>(())
+/// ```
+#[derive(Debug, Default)]
+pub struct HidDeviceCache {
+  query: HidQuery,
+  snapshot: Option<(Instant, Vec<HidDevice>)>,
+}
+
+impl HidDeviceCache {
+  /// A cache of all HID devices, as listed by `list_hid_device`.
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// A cache of the devices `query` lists.
+  pub fn with_query(query: HidQuery) -> Self {
+    HidDeviceCache {
+      query,
+      snapshot: None,
+    }
+  }
+
+  /// Returns the devices, listing them again if the snapshot is older than
+  /// `max_age` or there is none yet. A failed listing keeps no snapshot, so
+  /// the next call tries again.
+  pub fn get(&mut self, max_age: Duration) -> Result<&[HidDevice], ListHidError> {
+    let is_fresh = match &self.snapshot {
+      Some((taken, _)) => taken.elapsed() <= max_age,
+      None => false,
+    };
+    if !is_fresh {
+      self.snapshot = None;
+      let devices = self.query.list()?;
+      self.snapshot = Some((Instant::now(), devices));
+    }
+
+    Ok(self.snapshot.as_ref().map_or(&[], |(_, devices)| devices))
+  }
+
+  /// Drops the snapshot, so the next `get` lists the devices again, e.g. on a
+  /// hotplug notification.
+  pub fn invalidate(&mut self) {
+    self.snapshot = None;
+  }
+}
@@ -0,0 +1,450 @@
+use crate::win32::{
+  hid_d_get_preparsed_data, hid_p_get_button_caps, hid_p_get_caps, hid_p_get_link_collection_nodes,
+  hid_p_get_value_caps, PreparsedData,
+};
+use crate::{Error, HidDevice, OpenHidDevice};
+use winapi::shared::hidpi::{
+  HidP_Feature, HidP_Input, HidP_Output, HIDP_BUTTON_CAPS, HIDP_CAPS, HIDP_LINK_COLLECTION_NODE,
+  HIDP_REPORT_TYPE, HIDP_VALUE_CAPS,
+};
+
+/// The kind of report a capability or report belongs to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum HidReportType {
+  Input,
+  Output,
+  Feature,
+}
+
+impl HidReportType {
+  pub(crate) fn to_native(self) -> HIDP_REPORT_TYPE {
+    match self {
+      HidReportType::Input => HidP_Input,
+      HidReportType::Output => HidP_Output,
+      HidReportType::Feature => HidP_Feature,
+    }
+  }
+}
+
+/// The capabilities of a HID top-level collection, from `HIDP_CAPS`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct HidCapabilities {
+  pub usage_page: u16,
+  pub usage: u16,
+  pub input_report_byte_length: u16,
+  pub output_report_byte_length: u16,
+  pub feature_report_byte_length: u16,
+  pub number_link_collection_nodes: u16,
+  pub number_input_button_caps: u16,
+  pub number_input_value_caps: u16,
+  pub number_input_data_indices: u16,
+  pub number_output_button_caps: u16,
+  pub number_output_value_caps: u16,
+  pub number_output_data_indices: u16,
+  pub number_feature_button_caps: u16,
+  pub number_feature_value_caps: u16,
+  pub number_feature_data_indices: u16,
+}
+
+impl From<HIDP_CAPS> for HidCapabilities {
+  fn from(caps: HIDP_CAPS) -> Self {
+    HidCapabilities {
+      usage_page: caps.UsagePage,
+      usage: caps.Usage,
+      input_report_byte_length: caps.InputReportByteLength,
+      output_report_byte_length: caps.OutputReportByteLength,
+      feature_report_byte_length: caps.FeatureReportByteLength,
+      number_link_collection_nodes: caps.NumberLinkCollectionNodes,
+      number_input_button_caps: caps.NumberInputButtonCaps,
+      number_input_value_caps: caps.NumberInputValueCaps,
+      number_input_data_indices: caps.NumberInputDataIndices,
+      number_output_button_caps: caps.NumberOutputButtonCaps,
+      number_output_value_caps: caps.NumberOutputValueCaps,
+      number_output_data_indices: caps.NumberOutputDataIndices,
+      number_feature_button_caps: caps.NumberFeatureButtonCaps,
+      number_feature_value_caps: caps.NumberFeatureValueCaps,
+      number_feature_data_indices: caps.NumberFeatureDataIndices,
+    }
+  }
+}
+
+impl HidCapabilities {
+  pub(crate) fn button_caps_count(&self, report_type: HidReportType) -> u16 {
+    match report_type {
+      HidReportType::Input => self.number_input_button_caps,
+      HidReportType::Output => self.number_output_button_caps,
+      HidReportType::Feature => self.number_feature_button_caps,
+    }
+  }
+
+  pub(crate) fn value_caps_count(&self, report_type: HidReportType) -> u16 {
+    match report_type {
+      HidReportType::Input => self.number_input_value_caps,
+      HidReportType::Output => self.number_output_value_caps,
+      HidReportType::Feature => self.number_feature_value_caps,
+    }
+  }
+}
+
+/// A button (one-bit control) or a range of buttons in a report, from
+/// `HIDP_BUTTON_CAPS`. For a single usage, `usage_min == usage_max`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ButtonCap {
+  pub usage_page: u16,
+  pub report_id: u8,
+  pub is_alias: bool,
+  pub bit_field: u16,
+  pub link_collection: u16,
+  pub link_usage: u16,
+  pub link_usage_page: u16,
+  pub is_range: bool,
+  pub is_absolute: bool,
+  pub usage_min: u16,
+  pub usage_max: u16,
+  pub data_index_min: u16,
+  pub data_index_max: u16,
+}
+
+impl From<HIDP_BUTTON_CAPS> for ButtonCap {
+  fn from(caps: HIDP_BUTTON_CAPS) -> Self {
+    let (usage_min, usage_max, data_index_min, data_index_max) = if caps.IsRange != 0 {
+      let range = unsafe { caps.u.Range() };
+      (
+        range.UsageMin,
+        range.UsageMax,
+        range.DataIndexMin,
+        range.DataIndexMax,
+      )
+    } else {
+      let not_range = unsafe { caps.u.NotRange() };
+      (
+        not_range.Usage,
+        not_range.Usage,
+        not_range.DataIndex,
+        not_range.DataIndex,
+      )
+    };
+
+    ButtonCap {
+      usage_page: caps.UsagePage,
+      report_id: caps.ReportID,
+      is_alias: caps.IsAlias != 0,
+      bit_field: caps.BitField,
+      link_collection: caps.LinkCollection,
+      link_usage: caps.LinkUsage,
+      link_usage_page: caps.LinkUsagePage,
+      is_range: caps.IsRange != 0,
+      is_absolute: caps.IsAbsolute != 0,
+      usage_min,
+      usage_max,
+      data_index_min,
+      data_index_max,
+    }
+  }
+}
+
+/// A value (multi-bit control) or a range of values in a report, from
+/// `HIDP_VALUE_CAPS`. For a single usage, `usage_min == usage_max`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ValueCap {
+  pub usage_page: u16,
+  pub report_id: u8,
+  pub is_alias: bool,
+  pub bit_field: u16,
+  pub link_collection: u16,
+  pub link_usage: u16,
+  pub link_usage_page: u16,
+  pub is_range: bool,
+  pub is_absolute: bool,
+  pub has_null: bool,
+  pub bit_size: u16,
+  pub report_count: u16,
+  pub units_exp: u32,
+  pub units: u32,
+  pub logical_min: i32,
+  pub logical_max: i32,
+  pub physical_min: i32,
+  pub physical_max: i32,
+  pub usage_min: u16,
+  pub usage_max: u16,
+  pub data_index_min: u16,
+  pub data_index_max: u16,
+}
+
+impl From<HIDP_VALUE_CAPS> for ValueCap {
+  fn from(caps: HIDP_VALUE_CAPS) -> Self {
+    let (usage_min, usage_max, data_index_min, data_index_max) = if caps.IsRange != 0 {
+      let range = unsafe { caps.u.Range() };
+      (
+        range.UsageMin,
+        range.UsageMax,
+        range.DataIndexMin,
+        range.DataIndexMax,
+      )
+    } else {
+      let not_range = unsafe { caps.u.NotRange() };
+      (
+        not_range.Usage,
+        not_range.Usage,
+        not_range.DataIndex,
+        not_range.DataIndex,
+      )
+    };
+
+    ValueCap {
+      usage_page: caps.UsagePage,
+      report_id: caps.ReportID,
+      is_alias: caps.IsAlias != 0,
+      bit_field: caps.BitField,
+      link_collection: caps.LinkCollection,
+      link_usage: caps.LinkUsage,
+      link_usage_page: caps.LinkUsagePage,
+      is_range: caps.IsRange != 0,
+      is_absolute: caps.IsAbsolute != 0,
+      has_null: caps.HasNull != 0,
+      bit_size: caps.BitSize,
+      report_count: caps.ReportCount,
+      units_exp: caps.UnitsExp,
+      units: caps.Units,
+      logical_min: caps.LogicalMin,
+      logical_max: caps.LogicalMax,
+      physical_min: caps.PhysicalMin,
+      physical_max: caps.PhysicalMax,
+      usage_min,
+      usage_max,
+      data_index_min,
+      data_index_max,
+    }
+  }
+}
+
+/// A collection in the device's report descriptor, from
+/// `HIDP_LINK_COLLECTION_NODE`. Collections are identified by their index in
+/// the list, which is what `ButtonCap::link_collection` and
+/// `ValueCap::link_collection` refer to; the top-level collection is 0.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LinkCollection {
+  pub usage_page: u16,
+  pub usage: u16,
+  /// The collection type, e.g. 0 for physical, 1 for application and 2 for
+  /// logical.
+  pub collection_type: u8,
+  pub is_alias: bool,
+  /// The index of the enclosing collection, `None` for the top-level one.
+  pub parent: Option<usize>,
+  /// The indexes of the collections directly inside this one, in descriptor
+  /// order.
+  pub children: Vec<usize>,
+}
+
+/// Builds the collections from the flat node array, following each node's
+/// `FirstChild` and `NextSibling` links. Out-of-range or cyclic links, which
+/// a well-formed array doesn't have, are cut off.
+fn link_collections_from_nodes(nodes: &[HIDP_LINK_COLLECTION_NODE]) -> Vec<LinkCollection> {
+  nodes
+    .iter()
+    .enumerate()
+    .map(|(index, node)| {
+      let mut children = Vec::new();
+      let mut child = node.FirstChild as usize;
+      // Index 0 is the top-level collection and can't be anyone's child.
+      while child != 0 && child < nodes.len() && !children.contains(&child) {
+        children.push(child);
+        child = nodes[child].NextSibling as usize;
+      }
+      // Windows links siblings last to first.
+      children.sort_unstable();
+
+      LinkCollection {
+        usage_page: node.LinkUsagePage,
+        usage: node.LinkUsage,
+        collection_type: node.CollectionType() as u8,
+        is_alias: node.IsAlias() != 0,
+        parent: if index == 0 {
+          None
+        } else {
+          Some(node.Parent as usize).filter(|&parent| parent < nodes.len())
+        },
+        children,
+      }
+    })
+    .collect()
+}
+
+impl OpenHidDevice {
+  /// Lists the collections in the device's report descriptor, indexed as
+  /// described on `LinkCollection`.
+  pub fn link_collections(&self) -> Result<Vec<LinkCollection>, Error> {
+    let nodes = hid_p_get_link_collection_nodes(
+      self.preparsed_data(),
+      self.capabilities().number_link_collection_nodes,
+    )?;
+    Ok(link_collections_from_nodes(&nodes))
+  }
+
+  /// Lists the buttons in the device's reports of type `report_type`.
+  pub fn button_caps(&self, report_type: HidReportType) -> Result<Vec<ButtonCap>, Error> {
+    let button_caps = hid_p_get_button_caps(
+      report_type.to_native(),
+      self.preparsed_data(),
+      self.capabilities().button_caps_count(report_type),
+    )?;
+    Ok(button_caps.into_iter().map(ButtonCap::from).collect())
+  }
+
+  /// Lists the values in the device's reports of type `report_type`.
+  pub fn value_caps(&self, report_type: HidReportType) -> Result<Vec<ValueCap>, Error> {
+    let value_caps = hid_p_get_value_caps(
+      report_type.to_native(),
+      self.preparsed_data(),
+      self.capabilities().value_caps_count(report_type),
+    )?;
+    Ok(value_caps.into_iter().map(ValueCap::from).collect())
+  }
+}
+
+impl HidDevice {
+  /// Opens the device for querying and reads its preparsed data and
+  /// capabilities, which the methods below start from.
+  fn query_caps(&self) -> Result<(PreparsedData, HIDP_CAPS), Error> {
+    let handle = crate::open_device_for_query(&self.path)?;
+    let preparsed_data = hid_d_get_preparsed_data(&handle)?;
+    let capabilities = hid_p_get_caps(&preparsed_data)?;
+    Ok((preparsed_data, capabilities))
+  }
+
+  /// Reads the device's link collection nodes, see `query_caps`.
+  fn query_link_collection_nodes(&self) -> Result<Vec<HIDP_LINK_COLLECTION_NODE>, Error> {
+    let (preparsed_data, capabilities) = self.query_caps()?;
+    Ok(hid_p_get_link_collection_nodes(
+      &preparsed_data,
+      capabilities.NumberLinkCollectionNodes,
+    )?)
+  }
+
+  /// Reads the device's capabilities. This opens the device and reads its
+  /// preparsed data, so it is a method rather than a field.
+  pub fn capabilities(&self) -> Result<HidCapabilities, Error> {
+    let (_, capabilities) = self.query_caps()?;
+    Ok(capabilities.into())
+  }
+
+  /// Lists the collections in the device's report descriptor. See
+  /// `OpenHidDevice::link_collections`.
+  pub fn link_collections(&self) -> Result<Vec<LinkCollection>, Error> {
+    Ok(link_collections_from_nodes(
+      &self.query_link_collection_nodes()?,
+    ))
+  }
+
+  /// Lists the `(usage_page, usage)` of the interface's top-level collection
+  /// and of every application collection in it, top-level first. Windows
+  /// usually gives each top-level collection of a device its own interface,
+  /// but drivers can merge several functions, e.g. a keyboard and consumer
+  /// controls, into one, which `capabilities` only shows the first of.
+  pub fn top_level_collections(&self) -> Result<Vec<(u16, u16)>, Error> {
+    Ok(
+      self
+        .query_link_collection_nodes()?
+        .iter()
+        .enumerate()
+        .filter(|(index, node)| *index == 0 || node.CollectionType() == 1)
+        .map(|(_, node)| (node.LinkUsagePage, node.LinkUsage))
+        .collect(),
+    )
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn node(
+    usage_page: u16,
+    usage: u16,
+    collection_type: u8,
+    parent: u16,
+    first_child: u16,
+    next_sibling: u16,
+  ) -> HIDP_LINK_COLLECTION_NODE {
+    let mut node: HIDP_LINK_COLLECTION_NODE = unsafe { std::mem::zeroed() };
+    node.LinkUsagePage = usage_page;
+    node.LinkUsage = usage;
+    node.Parent = parent;
+    node.FirstChild = first_child;
+    node.NextSibling = next_sibling;
+    node.set_CollectionType(collection_type as u32);
+    node
+  }
+
+  /// The nodes of a precision touchpad: the Touch Pad application
+  /// collection holding five Finger logical collections, with the children
+  /// linked last to first as Windows does.
+  fn touchpad_nodes() -> Vec<HIDP_LINK_COLLECTION_NODE> {
+    let mut nodes = vec![node(0x0d, 0x05, 1, 0, 5, 0)];
+    for index in 1..=5 {
+      nodes.push(node(0x0d, 0x22, 2, 0, 0, index - 1));
+    }
+    nodes
+  }
+
+  #[test]
+  fn builds_the_touchpad_collection_tree() {
+    let collections = link_collections_from_nodes(&touchpad_nodes());
+    assert_eq!(collections.len(), 6);
+    assert_eq!(
+      collections[0],
+      LinkCollection {
+        usage_page: 0x0d,
+        usage: 0x05,
+        collection_type: 1,
+        is_alias: false,
+        parent: None,
+        children: vec![1, 2, 3, 4, 5],
+      }
+    );
+    for finger in &collections[1..] {
+      assert_eq!((finger.usage_page, finger.usage), (0x0d, 0x22));
+      assert_eq!(finger.collection_type, 2);
+      assert_eq!(finger.parent, Some(0));
+      assert!(finger.children.is_empty());
+    }
+  }
+
+  #[test]
+  fn follows_nested_collections() {
+    // A mouse: Mouse application, Pointer physical, Vendor logical inside it.
+    let nodes = [
+      node(0x01, 0x02, 1, 0, 1, 0),
+      node(0x01, 0x01, 0, 0, 2, 0),
+      node(0xff00, 0x01, 2, 1, 0, 0),
+    ];
+    let collections = link_collections_from_nodes(&nodes);
+    assert_eq!(collections[0].children, [1]);
+    assert_eq!(collections[1].parent, Some(0));
+    assert_eq!(collections[1].children, [2]);
+    assert_eq!(collections[2].parent, Some(1));
+    assert!(collections[2].children.is_empty());
+  }
+
+  #[test]
+  fn cuts_off_bad_links() {
+    let nodes = [
+      // A first child past the end.
+      node(0x01, 0x02, 1, 0, 7, 0),
+      // Siblings linking to each other.
+      node(0x01, 0x01, 0, 0, 2, 0),
+      node(0x01, 0x30, 2, 1, 0, 3),
+      node(0x01, 0x31, 2, 9, 0, 2),
+    ];
+    let collections = link_collections_from_nodes(&nodes);
+    assert!(collections[0].children.is_empty());
+    assert_eq!(collections[1].children, [2, 3]);
+    assert_eq!(collections[3].parent, None);
+  }
+}
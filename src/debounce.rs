@@ -0,0 +1,180 @@
+use crate::HidEvent;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// The events of one path waiting out the debounce window.
+struct Pending {
+  // Whether the interface was present before the first of the events.
+  was_present: bool,
+  last: HidEvent,
+  due: Instant,
+}
+
+/// Coalesces the events of each interface path until none came for a
+/// window, then reports only the change from before the first event to
+/// after the last: repeated arrivals collapse into the last one, and an
+/// arrival followed by a removal, or the other way round, cancels out.
+///
+/// It has no clock of its own; the time is passed in with every call.
+pub(crate) struct Debouncer {
+  window: Duration,
+  pending: HashMap<String, Pending>,
+}
+
+/// The path of an arrival or removal and whether the interface is present
+/// after it. `None` for the events that aren't about one interface.
+fn presence(event: &HidEvent) -> Option<(&str, bool)> {
+  match event {
+    HidEvent::Arrived(path) | HidEvent::ArrivedUnresolved(path, _) => Some((path, true)),
+    HidEvent::ArrivedDevice(device) => Some((&device.path, true)),
+    HidEvent::Removed(path) => Some((path, false)),
+    HidEvent::Lagged(_) => None,
+  }
+}
+
+impl Debouncer {
+  pub(crate) fn new(window: Duration) -> Self {
+    Debouncer {
+      window,
+      pending: HashMap::new(),
+    }
+  }
+
+  /// Takes `event`, which happened at `now`. Returns it straight away if it
+  /// isn't about one interface.
+  pub(crate) fn push(&mut self, event: HidEvent, now: Instant) -> Option<HidEvent> {
+    let (path, is_present) = match presence(&event) {
+      Some((path, is_present)) => (path.to_owned(), is_present),
+      None => return Some(event),
+    };
+
+    let due = now + self.window;
+    match self.pending.get_mut(&path) {
+      Some(pending) => {
+        pending.last = event;
+        pending.due = due;
+      }
+      None => {
+        self.pending.insert(
+          path,
+          Pending {
+            was_present: !is_present,
+            last: event,
+            due,
+          },
+        );
+      }
+    }
+    None
+  }
+
+  /// When the next pending path is due, if any.
+  pub(crate) fn next_due(&self) -> Option<Instant> {
+    self.pending.values().map(|pending| pending.due).min()
+  }
+
+  /// Removes the paths due at `now` and returns the last event of each that
+  /// ended up changing whether its interface is present, in the order they
+  /// became due.
+  pub(crate) fn pop_due(&mut self, now: Instant) -> Vec<HidEvent> {
+    let due_paths: Vec<String> = self
+      .pending
+      .iter()
+      .filter(|(_, pending)| pending.due <= now)
+      .map(|(path, _)| path.clone())
+      .collect();
+    let mut due: Vec<Pending> = due_paths
+      .iter()
+      .filter_map(|path| self.pending.remove(path))
+      .collect();
+    due.sort_by_key(|pending| pending.due);
+
+    due
+      .into_iter()
+      .filter(|pending| match presence(&pending.last) {
+        Some((_, is_present)) => is_present != pending.was_present,
+        None => true,
+      })
+      .map(|pending| pending.last)
+      .collect()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const WINDOW: Duration = Duration::from_millis(100);
+
+  fn arrived(path: &str) -> HidEvent {
+    HidEvent::Arrived(path.to_owned())
+  }
+
+  fn removed(path: &str) -> HidEvent {
+    HidEvent::Removed(path.to_owned())
+  }
+
+  #[test]
+  fn reports_the_last_event_once_quiet() {
+    let start = Instant::now();
+    let mut debouncer = Debouncer::new(WINDOW);
+    assert!(debouncer.push(arrived("a"), start).is_none());
+    assert!(debouncer
+      .push(removed("a"), start + Duration::from_millis(10))
+      .is_none());
+    assert!(debouncer
+      .push(arrived("a"), start + Duration::from_millis(20))
+      .is_none());
+
+    let due = start + Duration::from_millis(20) + WINDOW;
+    assert_eq!(debouncer.next_due(), Some(due));
+    assert!(debouncer.pop_due(due - Duration::from_millis(1)).is_empty());
+    let events = debouncer.pop_due(due);
+    assert_eq!(events.len(), 1);
+    assert!(matches!(&events[0], HidEvent::Arrived(path) if path == "a"));
+    assert_eq!(debouncer.next_due(), None);
+  }
+
+  #[test]
+  fn arrival_then_removal_cancels_out() {
+    let start = Instant::now();
+    let mut debouncer = Debouncer::new(WINDOW);
+    debouncer.push(arrived("a"), start);
+    debouncer.push(removed("a"), start + Duration::from_millis(50));
+    assert!(debouncer.pop_due(start + Duration::from_secs(1)).is_empty());
+    assert_eq!(debouncer.next_due(), None);
+  }
+
+  #[test]
+  fn removal_then_arrival_cancels_out() {
+    let start = Instant::now();
+    let mut debouncer = Debouncer::new(WINDOW);
+    debouncer.push(removed("a"), start);
+    debouncer.push(arrived("a"), start + Duration::from_millis(50));
+    assert!(debouncer.pop_due(start + Duration::from_secs(1)).is_empty());
+  }
+
+  #[test]
+  fn paths_are_reported_in_due_order() {
+    let start = Instant::now();
+    let mut debouncer = Debouncer::new(WINDOW);
+    debouncer.push(arrived("b"), start + Duration::from_millis(30));
+    debouncer.push(removed("a"), start);
+    assert_eq!(debouncer.next_due(), Some(start + WINDOW));
+
+    let events = debouncer.pop_due(start + Duration::from_secs(1));
+    assert_eq!(events.len(), 2);
+    assert!(matches!(&events[0], HidEvent::Removed(path) if path == "a"));
+    assert!(matches!(&events[1], HidEvent::Arrived(path) if path == "b"));
+  }
+
+  #[test]
+  fn lagged_passes_through() {
+    let start = Instant::now();
+    let mut debouncer = Debouncer::new(WINDOW);
+    debouncer.push(arrived("a"), start);
+    let passed = debouncer.push(HidEvent::Lagged(3), start);
+    assert!(matches!(passed, Some(HidEvent::Lagged(3))));
+    assert_eq!(debouncer.next_due(), Some(start + WINDOW));
+  }
+}
@@ -0,0 +1,889 @@
+use crate::win32::{
+  hid_d_get_preparsed_data, hid_p_get_button_caps, hid_p_get_caps, hid_p_get_link_collection_nodes,
+  hid_p_get_value_caps, PreparsedData,
+};
+use crate::{
+  open_device_for_query, Error, HidCapabilities, HidDevice, HidReportType, OpenHidDevice,
+};
+use std::collections::{BTreeMap, HashMap};
+use std::io;
+use winapi::shared::hidpi::{HIDP_BUTTON_CAPS, HIDP_LINK_COLLECTION_NODE, HIDP_VALUE_CAPS};
+
+// Short item prefixes from the HID 1.11 specification, section 6.2.2, with
+// the size bits cleared.
+const INPUT: u8 = 0x80;
+const OUTPUT: u8 = 0x90;
+const FEATURE: u8 = 0xb0;
+const COLLECTION: u8 = 0xa0;
+const END_COLLECTION: u8 = 0xc0;
+const USAGE_PAGE: u8 = 0x04;
+const LOGICAL_MINIMUM: u8 = 0x14;
+const LOGICAL_MAXIMUM: u8 = 0x24;
+const PHYSICAL_MINIMUM: u8 = 0x34;
+const PHYSICAL_MAXIMUM: u8 = 0x44;
+const UNIT_EXPONENT: u8 = 0x54;
+const UNIT: u8 = 0x64;
+const REPORT_SIZE: u8 = 0x74;
+const REPORT_ID: u8 = 0x84;
+const REPORT_COUNT: u8 = 0x94;
+const USAGE: u8 = 0x08;
+const USAGE_MINIMUM: u8 = 0x18;
+const USAGE_MAXIMUM: u8 = 0x28;
+const DELIMITER: u8 = 0xa8;
+
+// Main item data bits.
+const CONSTANT: u32 = 0x01;
+const VARIABLE: u32 = 0x02;
+
+/// A main item to write, from a button or value capability.
+struct Field {
+  report_type: HidReportType,
+  report_id: u8,
+  data_index: u16,
+  link_collection: u16,
+  is_alias: bool,
+  usage_page: u16,
+  usage_min: u16,
+  usage_max: u16,
+  is_range: bool,
+  flags: u32,
+  logical_min: i32,
+  logical_max: i32,
+  physical_min: i32,
+  physical_max: i32,
+  unit_exponent: u32,
+  unit: u32,
+  report_size: u32,
+  report_count: u32,
+}
+
+impl Field {
+  fn from_button_caps(report_type: HidReportType, caps: &HIDP_BUTTON_CAPS) -> Field {
+    let (usage_min, usage_max, data_index) = if caps.IsRange != 0 {
+      let range = unsafe { caps.u.Range() };
+      (range.UsageMin, range.UsageMax, range.DataIndexMin)
+    } else {
+      let not_range = unsafe { caps.u.NotRange() };
+      (not_range.Usage, not_range.Usage, not_range.DataIndex)
+    };
+    // Since HID API version 2 (Windows 10), the report count is stored at
+    // the start of what the older headers call `Reserved`.
+    let report_count = (caps.Reserved[0] & 0xffff).max(1);
+    let flags = caps.BitField as u32;
+
+    let (report_size, report_count, logical_min, logical_max) = if flags & VARIABLE != 0 {
+      // One bit per button.
+      let usage_count = (usage_max - usage_min) as u32 + 1;
+      (1, usage_count.max(report_count), 0, 1)
+    } else {
+      // An array whose elements hold the indices of the pressed buttons.
+      let report_size = if usage_max > 0xff { 16 } else { 8 };
+      (
+        report_size,
+        report_count,
+        usage_min as i32,
+        usage_max as i32,
+      )
+    };
+
+    Field {
+      report_type,
+      report_id: caps.ReportID,
+      data_index,
+      link_collection: caps.LinkCollection,
+      is_alias: caps.IsAlias != 0,
+      usage_page: caps.UsagePage,
+      usage_min,
+      usage_max,
+      is_range: caps.IsRange != 0,
+      flags,
+      logical_min,
+      logical_max,
+      physical_min: 0,
+      physical_max: 0,
+      unit_exponent: 0,
+      unit: 0,
+      report_size,
+      report_count,
+    }
+  }
+
+  fn from_value_caps(report_type: HidReportType, caps: &HIDP_VALUE_CAPS) -> Field {
+    let (usage_min, usage_max, data_index) = if caps.IsRange != 0 {
+      let range = unsafe { caps.u.Range() };
+      (range.UsageMin, range.UsageMax, range.DataIndexMin)
+    } else {
+      let not_range = unsafe { caps.u.NotRange() };
+      (not_range.Usage, not_range.Usage, not_range.DataIndex)
+    };
+
+    Field {
+      report_type,
+      report_id: caps.ReportID,
+      data_index,
+      link_collection: caps.LinkCollection,
+      is_alias: caps.IsAlias != 0,
+      usage_page: caps.UsagePage,
+      usage_min,
+      usage_max,
+      is_range: caps.IsRange != 0,
+      flags: caps.BitField as u32,
+      logical_min: caps.LogicalMin,
+      logical_max: caps.LogicalMax,
+      physical_min: caps.PhysicalMin,
+      physical_max: caps.PhysicalMax,
+      unit_exponent: caps.UnitsExp,
+      unit: caps.Units,
+      report_size: caps.BitSize as u32,
+      report_count: caps.ReportCount as u32,
+    }
+  }
+
+  fn sort_key(&self) -> (u8, u8, u16) {
+    (
+      report_type_index(self.report_type),
+      self.report_id,
+      self.data_index,
+    )
+  }
+}
+
+fn report_type_index(report_type: HidReportType) -> u8 {
+  match report_type {
+    HidReportType::Input => 0,
+    HidReportType::Output => 1,
+    HidReportType::Feature => 2,
+  }
+}
+
+const REPORT_TYPES: [HidReportType; 3] = [
+  HidReportType::Input,
+  HidReportType::Output,
+  HidReportType::Feature,
+];
+
+/// Writes short items, leaving out global items that wouldn't change the
+/// current value.
+#[derive(Default)]
+struct DescriptorWriter {
+  bytes: Vec<u8>,
+  globals: HashMap<u8, i64>,
+  // Bits written so far per report type and report ID.
+  report_bits: BTreeMap<(u8, u8), u32>,
+}
+
+impl DescriptorWriter {
+  fn item(&mut self, prefix: u8, data: &[u8]) {
+    let size_code = match data.len() {
+      0 => 0,
+      1 => 1,
+      2 => 2,
+      _ => 3,
+    };
+    self.bytes.push(prefix | size_code);
+    self.bytes.extend_from_slice(data);
+  }
+
+  fn unsigned(&mut self, prefix: u8, value: u32) {
+    let data = value.to_le_bytes();
+    let size = if value <= 0xff {
+      1
+    } else if value <= 0xffff {
+      2
+    } else {
+      4
+    };
+    self.item(prefix, &data[..size]);
+  }
+
+  fn signed(&mut self, prefix: u8, value: i32) {
+    let data = value.to_le_bytes();
+    let size = if (-0x80..=0x7f).contains(&value) {
+      1
+    } else if (-0x8000..=0x7fff).contains(&value) {
+      2
+    } else {
+      4
+    };
+    self.item(prefix, &data[..size]);
+  }
+
+  fn global_unsigned(&mut self, prefix: u8, value: u32) {
+    if self.globals.insert(prefix, value as i64) != Some(value as i64) {
+      self.unsigned(prefix, value);
+    }
+  }
+
+  fn global_signed(&mut self, prefix: u8, value: i32) {
+    if self.globals.insert(prefix, value as i64) != Some(value as i64) {
+      self.signed(prefix, value);
+    }
+  }
+
+  /// Writes a usage, as an extended usage if it is on another page than the
+  /// current one.
+  fn usage(&mut self, prefix: u8, usage_page: u16, usage: u16) {
+    if self.globals.get(&USAGE_PAGE) == Some(&(usage_page as i64)) {
+      self.unsigned(prefix, usage as u32);
+    } else {
+      let extended = (usage_page as u32) << 16 | usage as u32;
+      self.item(prefix, &extended.to_le_bytes());
+    }
+  }
+
+  fn field_usages(&mut self, field: &Field) {
+    if field.is_range {
+      self.usage(USAGE_MINIMUM, field.usage_page, field.usage_min);
+      self.usage(USAGE_MAXIMUM, field.usage_page, field.usage_max);
+    } else {
+      self.usage(USAGE, field.usage_page, field.usage_min);
+    }
+  }
+
+  /// Writes a main item, preceded by the usages of `aliases` in a delimited
+  /// set if there are any.
+  fn field(&mut self, aliases: &[&Field], field: &Field) {
+    self.global_unsigned(USAGE_PAGE, field.usage_page as u32);
+    if aliases.is_empty() {
+      self.field_usages(field);
+    } else {
+      self.unsigned(DELIMITER, 1);
+      for alias in aliases {
+        self.field_usages(alias);
+      }
+      self.field_usages(field);
+      self.unsigned(DELIMITER, 0);
+    }
+
+    if field.report_id != 0 {
+      self.global_unsigned(REPORT_ID, field.report_id as u32);
+    }
+    self.global_signed(LOGICAL_MINIMUM, field.logical_min);
+    self.global_signed(LOGICAL_MAXIMUM, field.logical_max);
+    self.global_signed(PHYSICAL_MINIMUM, field.physical_min);
+    self.global_signed(PHYSICAL_MAXIMUM, field.physical_max);
+    self.global_unsigned(UNIT_EXPONENT, field.unit_exponent);
+    self.global_unsigned(UNIT, field.unit);
+    self.main(
+      field.report_type,
+      field.report_id,
+      field.flags,
+      field.report_size,
+      field.report_count,
+    );
+  }
+
+  fn main(
+    &mut self,
+    report_type: HidReportType,
+    report_id: u8,
+    flags: u32,
+    report_size: u32,
+    report_count: u32,
+  ) {
+    self.global_unsigned(REPORT_SIZE, report_size);
+    self.global_unsigned(REPORT_COUNT, report_count);
+    let prefix = match report_type {
+      HidReportType::Input => INPUT,
+      HidReportType::Output => OUTPUT,
+      HidReportType::Feature => FEATURE,
+    };
+    self.unsigned(prefix, flags);
+
+    *self
+      .report_bits
+      .entry((report_type_index(report_type), report_id))
+      .or_insert(0) += report_size * report_count;
+  }
+
+  /// Pads every report to a whole number of bytes with constant bits, and
+  /// reports of a type that has a single report to the length Windows gives
+  /// for it. The padding isn't part of the capabilities, so its position in
+  /// the original report is lost.
+  fn padding(&mut self, capabilities: &HidCapabilities) {
+    let report_bits: Vec<_> = self.report_bits.iter().map(|(&k, &v)| (k, v)).collect();
+    for report_type in REPORT_TYPES.iter().copied() {
+      let index = report_type_index(report_type);
+      let reports: Vec<_> = report_bits
+        .iter()
+        .filter(|((report_type_index, _), _)| *report_type_index == index)
+        .collect();
+      let byte_length = match report_type {
+        HidReportType::Input => capabilities.input_report_byte_length,
+        HidReportType::Output => capabilities.output_report_byte_length,
+        HidReportType::Feature => capabilities.feature_report_byte_length,
+      } as u32;
+
+      for ((_, report_id), bits) in reports.iter().copied() {
+        let mut target = bits.div_ceil(8) * 8;
+        if reports.len() == 1 {
+          // The byte length includes the report ID byte.
+          target = target.max(byte_length.saturating_sub(1) * 8);
+        }
+        if target > *bits {
+          if *report_id != 0 {
+            self.global_unsigned(REPORT_ID, *report_id as u32);
+          }
+          self.main(report_type, *report_id, CONSTANT, 1, target - bits);
+        }
+      }
+    }
+  }
+
+  fn collection(
+    &mut self,
+    nodes: &[HIDP_LINK_COLLECTION_NODE],
+    index: usize,
+    fields: &[Field],
+    capabilities: &HidCapabilities,
+  ) {
+    let node = &nodes[index];
+    self.global_unsigned(USAGE_PAGE, node.LinkUsagePage as u32);
+    self.usage(USAGE, node.LinkUsagePage, node.LinkUsage);
+    self.unsigned(COLLECTION, node.CollectionType());
+
+    let mut aliases = Vec::new();
+    for field in fields
+      .iter()
+      .filter(|field| field.link_collection as usize == index)
+    {
+      if field.is_alias {
+        aliases.push(field);
+      } else {
+        self.field(&aliases, field);
+        aliases.clear();
+      }
+    }
+    // A delimited set whose caps are all marked as aliases.
+    if let Some(field) = aliases.pop() {
+      self.field(&aliases, field);
+    }
+
+    let mut children = Vec::new();
+    let mut child = node.FirstChild as usize;
+    while child != 0 && child < nodes.len() && !children.contains(&child) {
+      children.push(child);
+      child = nodes[child].NextSibling as usize;
+    }
+    children.sort_unstable();
+    for child in children {
+      self.collection(nodes, child, fields, capabilities);
+    }
+
+    if index == 0 {
+      self.padding(capabilities);
+    }
+    self.item(END_COLLECTION, &[]);
+  }
+}
+
+/// Reads the main items of every report type from the capabilities, in
+/// descriptor order.
+fn read_fields(
+  preparsed_data: &PreparsedData,
+  capabilities: &HidCapabilities,
+) -> Result<Vec<Field>, io::Error> {
+  let mut fields = Vec::new();
+  for report_type in REPORT_TYPES.iter().copied() {
+    for caps in hid_p_get_button_caps(
+      report_type.to_native(),
+      preparsed_data,
+      capabilities.button_caps_count(report_type),
+    )? {
+      fields.push(Field::from_button_caps(report_type, &caps));
+    }
+    for caps in hid_p_get_value_caps(
+      report_type.to_native(),
+      preparsed_data,
+      capabilities.value_caps_count(report_type),
+    )? {
+      fields.push(Field::from_value_caps(report_type, &caps));
+    }
+  }
+  // Data indices follow the order of the main items in the descriptor; the
+  // sort is stable so aliases stay ahead of their main usage.
+  fields.sort_by_key(Field::sort_key);
+  Ok(fields)
+}
+
+impl OpenHidDevice {
+  /// The length of the report of `report_type` with ID `report_id`,
+  /// including the report ID byte, or `None` if the device has no such
+  /// report. Pass 0 for devices that don't use report IDs.
+  ///
+  /// The length is the sum of the report's fields, rounded up to whole
+  /// bytes. Windows doesn't report constant (padding) fields, so a report
+  /// with padding in the middle comes out shorter than it is; the result
+  /// never exceeds the maximum length from the capabilities.
+  pub fn report_size(&self, report_type: HidReportType, report_id: u8) -> Option<usize> {
+    let fields = read_fields(self.preparsed_data(), self.capabilities()).ok()?;
+    let max_length = match report_type {
+      HidReportType::Input => self.input_report_byte_length(),
+      HidReportType::Output => self.output_report_byte_length(),
+      HidReportType::Feature => self.feature_report_byte_length(),
+    };
+    report_size(&fields, report_type, report_id, max_length)
+  }
+}
+
+/// The byte length of a report, including the report ID byte, from the sum
+/// of its fields, capped at `max_length`. Aliases share their main field's
+/// bits and aren't counted.
+fn report_size(
+  fields: &[Field],
+  report_type: HidReportType,
+  report_id: u8,
+  max_length: usize,
+) -> Option<usize> {
+  let bits: u32 = fields
+    .iter()
+    .filter(|field| {
+      field.report_type == report_type && field.report_id == report_id && !field.is_alias
+    })
+    .map(|field| field.report_size * field.report_count)
+    .sum();
+  if bits == 0 {
+    return None;
+  }
+  Some((1 + (bits as usize).div_ceil(8)).min(max_length))
+}
+
+impl HidDevice {
+  /// Rebuilds the device's report descriptor from the capabilities Windows
+  /// parsed out of it, since the original isn't available.
+  ///
+  /// The result describes the same collections, usages, report IDs and
+  /// report sizes, but is not byte-for-byte the original: items may be in
+  /// another order or encoded differently, and padding is moved to the end
+  /// of each report.
+  pub fn report_descriptor(&self) -> Result<Vec<u8>, Error> {
+    let handle = open_device_for_query(&self.path)?;
+    let preparsed_data = hid_d_get_preparsed_data(&handle)?;
+    let capabilities: HidCapabilities = hid_p_get_caps(&preparsed_data)?.into();
+    Ok(rebuild_report_descriptor(&preparsed_data, &capabilities)?)
+  }
+}
+
+impl OpenHidDevice {
+  /// Rebuilds the device's report descriptor, like
+  /// `HidDevice::report_descriptor` but from the already open handle.
+  ///
+  /// The raw descriptor can't be read from user mode:
+  /// `IOCTL_HID_GET_REPORT_DESCRIPTOR` is only sent from the HID class driver
+  /// to the minidriver below it, and the class driver fails it when it comes
+  /// from an application.
+  pub fn report_descriptor(&self) -> Result<Vec<u8>, Error> {
+    Ok(rebuild_report_descriptor(
+      self.preparsed_data(),
+      self.capabilities(),
+    )?)
+  }
+}
+
+fn rebuild_report_descriptor(
+  preparsed_data: &PreparsedData,
+  capabilities: &HidCapabilities,
+) -> Result<Vec<u8>, io::Error> {
+  let fields = read_fields(preparsed_data, capabilities)?;
+
+  let nodes =
+    hid_p_get_link_collection_nodes(preparsed_data, capabilities.number_link_collection_nodes)?;
+  Ok(build_report_descriptor(&fields, &nodes, capabilities))
+}
+
+/// Writes a report descriptor for `fields`, sorted as `read_fields` returns
+/// them, inside the collections of `nodes`.
+fn build_report_descriptor(
+  fields: &[Field],
+  nodes: &[HIDP_LINK_COLLECTION_NODE],
+  capabilities: &HidCapabilities,
+) -> Vec<u8> {
+  let mut writer = DescriptorWriter::default();
+  if !nodes.is_empty() {
+    writer.collection(nodes, 0, fields, capabilities);
+  }
+  writer.bytes
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn field(report_id: u8, report_size: u32, report_count: u32, is_alias: bool) -> Field {
+    Field {
+      report_type: HidReportType::Input,
+      report_id,
+      data_index: 0,
+      link_collection: 0,
+      is_alias,
+      usage_page: 0x01,
+      usage_min: 0x30,
+      usage_max: 0x30,
+      is_range: false,
+      flags: VARIABLE,
+      logical_min: 0,
+      logical_max: 255,
+      physical_min: 0,
+      physical_max: 0,
+      unit_exponent: 0,
+      unit: 0,
+      report_size,
+      report_count,
+    }
+  }
+
+  #[test]
+  fn report_size_sums_the_fields_of_one_report() {
+    let fields = [
+      field(1, 8, 2, false),
+      field(1, 1, 3, false),
+      field(1, 8, 2, true),
+      field(2, 16, 4, false),
+    ];
+    // 19 bits round up to 3 bytes, plus the report ID byte.
+    assert_eq!(report_size(&fields, HidReportType::Input, 1, 64), Some(4));
+    assert_eq!(report_size(&fields, HidReportType::Input, 2, 64), Some(9));
+  }
+
+  #[test]
+  fn report_size_is_none_for_a_missing_report() {
+    let fields = [field(1, 8, 2, false)];
+    assert_eq!(report_size(&fields, HidReportType::Input, 3, 64), None);
+    assert_eq!(report_size(&fields, HidReportType::Output, 1, 64), None);
+  }
+
+  #[test]
+  fn report_size_never_exceeds_the_maximum() {
+    let fields = [field(0, 8, 100, false)];
+    assert_eq!(report_size(&fields, HidReportType::Input, 0, 65), Some(65));
+  }
+
+  fn capabilities(input: u16, output: u16, feature: u16, nodes: u16) -> HidCapabilities {
+    HidCapabilities {
+      usage_page: 0x01,
+      usage: 0x00,
+      input_report_byte_length: input,
+      output_report_byte_length: output,
+      feature_report_byte_length: feature,
+      number_link_collection_nodes: nodes,
+      number_input_button_caps: 0,
+      number_input_value_caps: 0,
+      number_input_data_indices: 0,
+      number_output_button_caps: 0,
+      number_output_value_caps: 0,
+      number_output_data_indices: 0,
+      number_feature_button_caps: 0,
+      number_feature_value_caps: 0,
+      number_feature_data_indices: 0,
+    }
+  }
+
+  fn node(
+    usage_page: u16,
+    usage: u16,
+    collection_type: u8,
+    parent: u16,
+    first_child: u16,
+  ) -> HIDP_LINK_COLLECTION_NODE {
+    let mut node: HIDP_LINK_COLLECTION_NODE = unsafe { std::mem::zeroed() };
+    node.LinkUsagePage = usage_page;
+    node.LinkUsage = usage;
+    node.Parent = parent;
+    node.FirstChild = first_child;
+    node.set_CollectionType(collection_type as u32);
+    node
+  }
+
+  /// A button capability for `usages`, a range unless both ends are equal.
+  fn button_caps(usage_page: u16, usages: (u16, u16), data_index: u16) -> HIDP_BUTTON_CAPS {
+    let mut caps: HIDP_BUTTON_CAPS = unsafe { std::mem::zeroed() };
+    caps.UsagePage = usage_page;
+    if usages.0 == usages.1 {
+      let not_range = unsafe { caps.u.NotRange_mut() };
+      not_range.Usage = usages.0;
+      not_range.DataIndex = data_index;
+    } else {
+      caps.IsRange = 1;
+      let range = unsafe { caps.u.Range_mut() };
+      range.UsageMin = usages.0;
+      range.UsageMax = usages.1;
+      range.DataIndexMin = data_index;
+      range.DataIndexMax = data_index + (usages.1 - usages.0);
+    }
+    caps
+  }
+
+  fn value_caps(usage_page: u16, usage: u16, data_index: u16) -> HIDP_VALUE_CAPS {
+    let mut caps: HIDP_VALUE_CAPS = unsafe { std::mem::zeroed() };
+    caps.UsagePage = usage_page;
+    let not_range = unsafe { caps.u.NotRange_mut() };
+    not_range.Usage = usage;
+    not_range.DataIndex = data_index;
+    caps
+  }
+
+  /// A data main item as a host sees it: variable items are split into one
+  /// control per element, array items keep their usage range.
+  #[derive(Debug, PartialEq)]
+  struct Control {
+    prefix: u8,
+    report_id: u8,
+    usage_min: u32,
+    usage_max: u32,
+    report_size: u32,
+    report_count: u32,
+  }
+
+  #[derive(Debug, Default)]
+  struct Parsed {
+    // Extended usage and type of each collection, in order.
+    collections: Vec<(u32, u32)>,
+    // Sorted by main item type and report ID, in descriptor order within.
+    controls: Vec<Control>,
+    // Bits per main item type and report ID, padding included.
+    report_bits: BTreeMap<(u8, u8), u32>,
+  }
+
+  /// Parses the short items the tests use.
+  fn parse(descriptor: &[u8]) -> Parsed {
+    let mut parsed = Parsed::default();
+    let (mut usage_page, mut report_id, mut report_size, mut report_count) = (0, 0, 0, 0);
+    let mut usages: Vec<(u32, u32)> = Vec::new();
+    let mut usage_minimum = 0;
+    let mut i = 0;
+    while i < descriptor.len() {
+      let prefix = descriptor[i];
+      let size = [0, 1, 2, 4][(prefix & 0x03) as usize];
+      let mut data = [0; 4];
+      data[..size].copy_from_slice(&descriptor[i + 1..i + 1 + size]);
+      i += 1 + size;
+      let value = u32::from_le_bytes(data);
+      let usage = if size == 4 {
+        value
+      } else {
+        usage_page << 16 | value
+      };
+
+      match prefix & 0xfc {
+        USAGE_PAGE => usage_page = value,
+        REPORT_ID => report_id = value as u8,
+        REPORT_SIZE => report_size = value,
+        REPORT_COUNT => report_count = value,
+        USAGE => usages.push((usage, usage)),
+        USAGE_MINIMUM => usage_minimum = usage,
+        USAGE_MAXIMUM => usages.push((usage_minimum, usage)),
+        COLLECTION => {
+          parsed.collections.push((usages[0].0, value));
+          usages.clear();
+        }
+        main @ INPUT | main @ OUTPUT | main @ FEATURE => {
+          *parsed.report_bits.entry((main, report_id)).or_insert(0) += report_size * report_count;
+          if value & CONSTANT == 0 && value & VARIABLE != 0 {
+            let expanded: Vec<u32> = usages.iter().flat_map(|&(min, max)| min..=max).collect();
+            for element in 0..report_count as usize {
+              let usage = expanded[element.min(expanded.len() - 1)];
+              parsed.controls.push(Control {
+                prefix: main,
+                report_id,
+                usage_min: usage,
+                usage_max: usage,
+                report_size,
+                report_count: 1,
+              });
+            }
+          } else if value & CONSTANT == 0 {
+            parsed.controls.push(Control {
+              prefix: main,
+              report_id,
+              usage_min: usages[0].0,
+              usage_max: usages[usages.len() - 1].1,
+              report_size,
+              report_count,
+            });
+          }
+          usages.clear();
+        }
+        _ => {}
+      }
+    }
+    parsed
+      .controls
+      .sort_by_key(|control| (control.prefix, control.report_id));
+    parsed
+  }
+
+  fn assert_same_reports(rebuilt: &[u8], original: &[u8]) {
+    let rebuilt = parse(rebuilt);
+    let original = parse(original);
+    assert_eq!(rebuilt.collections, original.collections);
+    assert_eq!(rebuilt.controls, original.controls);
+    assert_eq!(rebuilt.report_bits, original.report_bits);
+  }
+
+  /// A three-button wheel mouse, with the buttons padded to a byte.
+  const MOUSE: &[u8] = &[
+    0x05, 0x01, 0x09, 0x02, 0xa1, 0x01, 0x09, 0x01, 0xa1, 0x00, 0x05, 0x09, 0x19, 0x01, 0x29, 0x03,
+    0x15, 0x00, 0x25, 0x01, 0x95, 0x03, 0x75, 0x01, 0x81, 0x02, 0x95, 0x01, 0x75, 0x05, 0x81, 0x03,
+    0x05, 0x01, 0x09, 0x30, 0x09, 0x31, 0x09, 0x38, 0x15, 0x81, 0x25, 0x7f, 0x75, 0x08, 0x95, 0x03,
+    0x81, 0x06, 0xc0, 0xc0,
+  ];
+
+  #[test]
+  fn rebuilds_a_mouse() {
+    let nodes = [node(0x01, 0x02, 1, 0, 1), node(0x01, 0x01, 0, 0, 0)];
+    let buttons = HIDP_BUTTON_CAPS {
+      BitField: 0x02,
+      LinkCollection: 1,
+      Reserved: [3, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+      ..button_caps(0x09, (0x01, 0x03), 0)
+    };
+    let mut fields = vec![Field::from_button_caps(HidReportType::Input, &buttons)];
+    for (data_index, usage) in [0x30, 0x31, 0x38].iter().copied().enumerate() {
+      let caps = HIDP_VALUE_CAPS {
+        BitField: 0x06,
+        LinkCollection: 1,
+        BitSize: 8,
+        ReportCount: 1,
+        LogicalMin: -127,
+        LogicalMax: 127,
+        ..value_caps(0x01, usage, 3 + data_index as u16)
+      };
+      fields.push(Field::from_value_caps(HidReportType::Input, &caps));
+    }
+
+    let rebuilt = build_report_descriptor(&fields, &nodes, &capabilities(5, 0, 0, 2));
+    assert_same_reports(&rebuilt, MOUSE);
+  }
+
+  /// A keyboard with modifiers and keys in input report 1, LEDs in output
+  /// report 1 and a vendor-defined feature report 2.
+  const KEYBOARD: &[u8] = &[
+    0x05, 0x01, 0x09, 0x06, 0xa1, 0x01, 0x85, 0x01, 0x05, 0x07, 0x19, 0xe0, 0x29, 0xe7, 0x15, 0x00,
+    0x25, 0x01, 0x75, 0x01, 0x95, 0x08, 0x81, 0x02, 0x95, 0x01, 0x75, 0x08, 0x81, 0x03, 0x95, 0x05,
+    0x75, 0x01, 0x05, 0x08, 0x19, 0x01, 0x29, 0x05, 0x91, 0x02, 0x95, 0x01, 0x75, 0x03, 0x91, 0x03,
+    0x95, 0x06, 0x75, 0x08, 0x15, 0x00, 0x25, 0x65, 0x05, 0x07, 0x19, 0x00, 0x29, 0x65, 0x81, 0x00,
+    0x85, 0x02, 0x06, 0x00, 0xff, 0x09, 0x01, 0x15, 0x00, 0x26, 0xff, 0x00, 0x75, 0x08, 0x95, 0x08,
+    0xb1, 0x02, 0xc0,
+  ];
+
+  #[test]
+  fn rebuilds_a_keyboard_with_several_report_ids() {
+    let nodes = [node(0x01, 0x06, 1, 0, 0)];
+    let modifiers = HIDP_BUTTON_CAPS {
+      ReportID: 1,
+      BitField: 0x02,
+      Reserved: [8, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+      ..button_caps(0x07, (0xe0, 0xe7), 0)
+    };
+    let keys = HIDP_BUTTON_CAPS {
+      ReportID: 1,
+      BitField: 0x00,
+      Reserved: [6, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+      ..button_caps(0x07, (0x00, 0x65), 8)
+    };
+    let leds = HIDP_BUTTON_CAPS {
+      ReportID: 1,
+      BitField: 0x02,
+      Reserved: [5, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+      ..button_caps(0x08, (0x01, 0x05), 0)
+    };
+    let vendor = HIDP_VALUE_CAPS {
+      ReportID: 2,
+      BitField: 0x02,
+      BitSize: 8,
+      ReportCount: 8,
+      LogicalMin: 0,
+      LogicalMax: 255,
+      ..value_caps(0xff00, 0x01, 0)
+    };
+    let fields = [
+      Field::from_button_caps(HidReportType::Input, &modifiers),
+      Field::from_button_caps(HidReportType::Input, &keys),
+      Field::from_button_caps(HidReportType::Output, &leds),
+      Field::from_value_caps(HidReportType::Feature, &vendor),
+    ];
+
+    let rebuilt = build_report_descriptor(&fields, &nodes, &capabilities(9, 2, 9, 1));
+    assert_same_reports(&rebuilt, KEYBOARD);
+  }
+
+  #[test]
+  fn writes_nothing_without_collections() {
+    let fields = [field(0, 8, 1, false)];
+    assert!(build_report_descriptor(&fields, &[], &capabilities(2, 0, 0, 0)).is_empty());
+  }
+
+  #[test]
+  fn writes_aliases_in_a_delimited_set() {
+    let alias = Field {
+      usage_page: 0x0d,
+      ..field(0, 8, 1, true)
+    };
+    let mut writer = DescriptorWriter::default();
+    writer.field(&[&alias], &field(0, 8, 1, false));
+    assert_eq!(
+      writer.bytes,
+      [
+        0x05, 0x01, // Usage Page (Generic Desktop)
+        0xa9, 0x01, // Delimiter (Open)
+        0x0b, 0x30, 0x00, 0x0d, 0x00, // Usage (Digitizer: 0x30), extended
+        0x09, 0x30, // Usage (X)
+        0xa9, 0x00, // Delimiter (Close)
+        0x15, 0x00, 0x26, 0xff, 0x00, // Logical Minimum (0), Maximum (255)
+        0x35, 0x00, 0x45, 0x00, // Physical Minimum (0), Maximum (0)
+        0x55, 0x00, 0x65, 0x00, // Unit Exponent (0), Unit (0)
+        0x75, 0x08, 0x95, 0x01, // Report Size (8), Report Count (1)
+        0x81, 0x02, // Input (Data, Variable, Absolute)
+      ]
+    );
+  }
+
+  #[test]
+  fn closes_a_set_of_only_aliases_on_its_last_usage() {
+    let first = Field {
+      usage_min: 0x31,
+      usage_max: 0x31,
+      ..field(0, 8, 1, true)
+    };
+    let fields = [first, field(0, 8, 1, true)];
+    let nodes = [node(0x01, 0x04, 1, 0, 0)];
+    let rebuilt = build_report_descriptor(&fields, &nodes, &capabilities(2, 0, 0, 1));
+    let usages = [0xa9, 0x01, 0x09, 0x31, 0x09, 0x30, 0xa9, 0x00];
+    assert!(rebuilt.windows(usages.len()).any(|window| window == usages));
+    assert_eq!(parse(&rebuilt).report_bits[&(INPUT, 0)], 8);
+  }
+
+  #[test]
+  fn usage_is_extended_only_off_the_current_page() {
+    let mut writer = DescriptorWriter::default();
+    writer.global_unsigned(USAGE_PAGE, 0x01);
+    writer.usage(USAGE, 0x01, 0x30);
+    writer.usage(USAGE, 0x0c, 0xe9);
+    assert_eq!(
+      writer.bytes,
+      [0x05, 0x01, 0x09, 0x30, 0x0b, 0xe9, 0x00, 0x0c, 0x00]
+    );
+  }
+
+  #[test]
+  fn padding_rounds_each_of_several_reports_to_bytes() {
+    let mut writer = DescriptorWriter::default();
+    writer.main(HidReportType::Input, 1, VARIABLE, 1, 3);
+    writer.main(HidReportType::Input, 2, VARIABLE, 8, 2);
+    writer.padding(&capabilities(8, 0, 0, 1));
+    // Two input reports, so neither is padded to the maximum length.
+    let report_bits: Vec<_> = writer.report_bits.into_iter().collect();
+    assert_eq!(report_bits, [((0, 1), 8), ((0, 2), 16)]);
+  }
+
+  #[test]
+  fn padding_fills_a_single_report_to_the_byte_length() {
+    let mut writer = DescriptorWriter::default();
+    writer.main(HidReportType::Feature, 0, VARIABLE, 8, 2);
+    writer.padding(&capabilities(0, 0, 5, 1));
+    assert_eq!(writer.report_bits[&(2, 0)], 32);
+    assert!(writer
+      .bytes
+      .ends_with(&[0x75, 0x01, 0x95, 0x10, 0xb1, 0x01]));
+  }
+}
@@ -0,0 +1,221 @@
+use std::{error, fmt, io};
+
+/// Errors from opening and talking to a HID device.
+#[derive(Debug)]
+pub enum Error {
+  /// The device refused the requested access. System keyboards and mice are
+  /// opened exclusively by Windows, so opening them for reading or writing
+  /// always ends up here.
+  AccessDenied(io::Error),
+  /// The device at `path` is open in a way that conflicts with the requested
+  /// share mode, e.g. another handle has it open exclusively, or it is open
+  /// for reading or writing elsewhere and an exclusive open was requested.
+  Busy {
+    path: String,
+  },
+  /// The device was unplugged.
+  Disconnected,
+  /// The caller's buffer cannot hold a complete report of `required` bytes.
+  BufferTooSmall {
+    required: usize,
+  },
+  /// An output report is shorter than the device's `expected` report length.
+  ReportTooShort {
+    expected: usize,
+    actual: usize,
+  },
+  /// An output report is longer than the device's `expected` report length.
+  ReportTooLong {
+    expected: usize,
+    actual: usize,
+  },
+  /// The device has no output reports to write.
+  NoOutputReports,
+  /// The report being built has no such usage.
+  UsageNotFound {
+    usage_page: u16,
+    usage: u16,
+  },
+  /// The value is in its null state, i.e. outside its logical range, which
+  /// devices use to report "no value", e.g. for a centered hat switch.
+  NullValue {
+    usage_page: u16,
+    usage: u16,
+  },
+  /// The number of input buffers is outside the 2 to 512 the HID class
+  /// driver accepts.
+  InvalidInputBufferCount(u32),
+  /// The device's driver doesn't support the request, e.g. polled input
+  /// reports.
+  Unsupported,
+  /// No device with these IDs is present, e.g. for `open_by_ids`.
+  NotFound {
+    vendor_id: u16,
+    product_id: u16,
+  },
+  Io(io::Error),
+}
+
+impl fmt::Display for Error {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      Error::AccessDenied(e) => write!(f, "access denied: {}", e),
+      Error::Busy { path } => write!(f, "device {} is in use by another handle", path),
+      Error::Disconnected => write!(f, "device disconnected"),
+      Error::BufferTooSmall { required } => {
+        write!(f, "buffer too small, a report needs {} bytes", required)
+      }
+      Error::ReportTooShort { expected, actual } => write!(
+        f,
+        "report too short, got {} bytes but the device expects {}",
+        actual, expected
+      ),
+      Error::ReportTooLong { expected, actual } => write!(
+        f,
+        "report too long, got {} bytes but the device expects {}",
+        actual, expected
+      ),
+      Error::NoOutputReports => write!(f, "device has no output reports"),
+      Error::UsageNotFound { usage_page, usage } => write!(
+        f,
+        "usage {:#06x} on page {:#06x} not found in the report",
+        usage, usage_page
+      ),
+      Error::NullValue { usage_page, usage } => write!(
+        f,
+        "usage {:#06x} on page {:#06x} has no value",
+        usage, usage_page
+      ),
+      Error::InvalidInputBufferCount(count) => write!(
+        f,
+        "invalid number of input buffers {}, must be between 2 and 512",
+        count
+      ),
+      Error::Unsupported => write!(f, "operation not supported by the device"),
+      Error::NotFound {
+        vendor_id,
+        product_id,
+      } => write!(f, "no HID device {:04x}:{:04x}", vendor_id, product_id),
+      Error::Io(e) => e.fmt(f),
+    }
+  }
+}
+
+impl error::Error for Error {
+  fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+    match self {
+      Error::AccessDenied(e) | Error::Io(e) => Some(e),
+      Error::Busy { .. }
+      | Error::Disconnected
+      | Error::BufferTooSmall { .. }
+      | Error::ReportTooShort { .. }
+      | Error::ReportTooLong { .. }
+      | Error::NoOutputReports
+      | Error::UsageNotFound { .. }
+      | Error::NullValue { .. }
+      | Error::InvalidInputBufferCount(_)
+      | Error::Unsupported
+      | Error::NotFound { .. } => None,
+    }
+  }
+}
+
+impl From<io::Error> for Error {
+  fn from(e: io::Error) -> Self {
+    #[cfg(windows)]
+    {
+      use winapi::shared::winerror::ERROR_DEVICE_NOT_CONNECTED;
+      if e.raw_os_error() == Some(ERROR_DEVICE_NOT_CONNECTED as i32) {
+        return Error::Disconnected;
+      }
+    }
+
+    match e.kind() {
+      io::ErrorKind::PermissionDenied => Error::AccessDenied(e),
+      _ => Error::Io(e),
+    }
+  }
+}
+
+impl From<Error> for io::Error {
+  fn from(e: Error) -> Self {
+    match e {
+      Error::AccessDenied(e) | Error::Io(e) => e,
+      e @ Error::NotFound { .. } => io::Error::new(io::ErrorKind::NotFound, e),
+      e => io::Error::other(e),
+    }
+  }
+}
+
+/// Errors from listing HID devices, telling apart a failed enumeration from
+/// a failure with one device, and for those the device and the call.
+#[derive(Debug)]
+pub enum ListHidError {
+  /// Enumerating the devices failed, e.g. a SetupAPI call.
+  Enumeration { source: io::Error },
+  /// The device at `path` couldn't be opened to query it.
+  DeviceOpen { path: String, source: io::Error },
+  /// The HID API function `call`, e.g. `HidD_GetAttributes`, failed for the
+  /// device at `path`.
+  HidApi {
+    path: String,
+    call: &'static str,
+    source: io::Error,
+  },
+  /// Listing devices isn't supported on this platform.
+  Unsupported,
+}
+
+impl fmt::Display for ListHidError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      ListHidError::Enumeration { source } => {
+        write!(f, "enumerating HID devices failed: {}", source)
+      }
+      ListHidError::DeviceOpen { path, source } => write!(f, "opening {} failed: {}", path, source),
+      ListHidError::HidApi { path, call, source } => {
+        write!(f, "{} failed for {}: {}", call, path, source)
+      }
+      ListHidError::Unsupported => {
+        write!(f, "listing HID devices is not supported on this platform")
+      }
+    }
+  }
+}
+
+impl error::Error for ListHidError {
+  fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+    match self {
+      ListHidError::Enumeration { source }
+      | ListHidError::DeviceOpen { source, .. }
+      | ListHidError::HidApi { source, .. } => Some(source),
+      ListHidError::Unsupported => None,
+    }
+  }
+}
+
+/// Any other failure during a listing is one of the enumeration.
+impl From<io::Error> for ListHidError {
+  fn from(source: io::Error) -> Self {
+    ListHidError::Enumeration { source }
+  }
+}
+
+/// Gives back the underlying OS error, so code that checked the `io::Error`
+/// listings used to return keeps working.
+impl From<ListHidError> for io::Error {
+  fn from(e: ListHidError) -> Self {
+    match e {
+      ListHidError::Enumeration { source }
+      | ListHidError::DeviceOpen { source, .. }
+      | ListHidError::HidApi { source, .. } => source,
+      e => io::Error::new(io::ErrorKind::Unsupported, e),
+    }
+  }
+}
+
+impl From<ListHidError> for Error {
+  fn from(e: ListHidError) -> Self {
+    io::Error::from(e).into()
+  }
+}
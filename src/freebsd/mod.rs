@@ -0,0 +1,261 @@
+use crate::{DetailLevel, HidDevice};
+use std::fs::{self, File};
+use std::io;
+use std::os::raw::{c_int, c_ulong};
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::path::Path;
+
+extern "C" {
+  fn ioctl(fd: c_int, request: c_ulong, ...) -> c_int;
+}
+
+// From <sys/ioccom.h>.
+const IOCPARM_MASK: c_ulong = 0x1fff;
+const IOC_OUT: c_ulong = 0x4000_0000;
+
+const fn ioc(inout: c_ulong, group: u8, num: u8, len: usize) -> c_ulong {
+  inout | ((len as c_ulong & IOCPARM_MASK) << 16) | ((group as c_ulong) << 8) | num as c_ulong
+}
+
+/// `struct hidraw_devinfo` from <dev/hid/hidraw.h>, the same as Linux's.
+#[repr(C)]
+#[derive(Default)]
+struct HidrawDevinfo {
+  bustype: u32,
+  vendor: i16,
+  product: i16,
+}
+
+const HIDIOCGRAWINFO: c_ulong = ioc(IOC_OUT, b'H', 0x03, std::mem::size_of::<HidrawDevinfo>());
+const HIDIOCGRAWNAME: u8 = 0x04;
+const HIDIOCGRAWUNIQ: u8 = 0x08;
+
+/// The longest string the hidraw driver returns, including the NUL.
+const MAX_STRING_LEN: usize = 256;
+
+/// `struct usb_device_info` from <dev/usb/usb_ioctl.h>.
+#[repr(C)]
+struct UsbDeviceInfo {
+  product_no: u16,
+  vendor_no: u16,
+  release_no: u16,
+  power: u16,
+  bus: u8,
+  addr: u8,
+  index: u8,
+  class: u8,
+  subclass: u8,
+  protocol: u8,
+  config_no: u8,
+  config_index: u8,
+  speed: u8,
+  mode: u8,
+  nports: u8,
+  hubaddr: u8,
+  hubindex: u8,
+  hubport: u8,
+  power_mode: u8,
+  suspended: u8,
+  reserved16: u16,
+  product: [u8; 128],
+  vendor: [u8; 128],
+  serial: [u8; 64],
+  release: [u8; 8],
+}
+
+const USB_GET_DEVICEINFO: c_ulong = ioc(IOC_OUT, b'U', 112, std::mem::size_of::<UsbDeviceInfo>());
+
+// From <sys/errno.h>.
+const EPERM: i32 = 1;
+const EACCES: i32 = 13;
+const EBUSY: i32 = 16;
+
+/// Reads the IDs of the hidraw device open as `fd`, with `HIDIOCGRAWINFO`.
+fn hidraw_get_info(fd: RawFd) -> Result<HidrawDevinfo, io::Error> {
+  let mut info = HidrawDevinfo::default();
+  if unsafe { ioctl(fd, HIDIOCGRAWINFO, &mut info as *mut HidrawDevinfo) } < 0 {
+    return Err(io::Error::last_os_error());
+  }
+  Ok(info)
+}
+
+/// Reads the string `num`, `HIDIOCGRAWNAME` or `HIDIOCGRAWUNIQ`, of the
+/// hidraw device open as `fd`. `None` if it is empty.
+fn hidraw_get_string(fd: RawFd, num: u8) -> Result<Option<String>, io::Error> {
+  let mut buffer = [0u8; MAX_STRING_LEN];
+  let request = ioc(IOC_OUT, b'H', num, buffer.len());
+  if unsafe { ioctl(fd, request, buffer.as_mut_ptr()) } < 0 {
+    return Err(io::Error::last_os_error());
+  }
+  let end = buffer.iter().position(|&c| c == 0).unwrap_or(buffer.len());
+  Ok(Some(String::from_utf8_lossy(&buffer[..end]).into_owned()).filter(|s| !s.is_empty()))
+}
+
+/// Reads the USB device behind the `uhid` node open as `fd`, with
+/// `USB_GET_DEVICEINFO`.
+fn uhid_get_device_info(fd: RawFd) -> Result<UsbDeviceInfo, io::Error> {
+  let mut info: UsbDeviceInfo = unsafe { std::mem::zeroed() };
+  if unsafe { ioctl(fd, USB_GET_DEVICEINFO, &mut info as *mut UsbDeviceInfo) } < 0 {
+    return Err(io::Error::last_os_error());
+  }
+  Ok(info)
+}
+
+/// A NUL-terminated string from a `usb_device_info`. `None` if it is empty.
+fn c_string(buffer: &[u8]) -> Option<String> {
+  let end = buffer.iter().position(|&c| c == 0).unwrap_or(buffer.len());
+  Some(String::from_utf8_lossy(&buffer[..end]).into_owned()).filter(|s| !s.is_empty())
+}
+
+/// Fills in `device` from the `uhid` device open as `fd`.
+fn read_uhid(device: &mut HidDevice, fd: RawFd) -> Result<(), io::Error> {
+  let info = uhid_get_device_info(fd)?;
+  device.vendor_id = info.vendor_no;
+  device.product_id = info.product_no;
+  device.version_number = Some(info.release_no);
+  device.manufacturer_string = c_string(&info.vendor);
+  device.product_string = c_string(&info.product);
+  device.serial_number_string = c_string(&info.serial);
+  device.detail_level = DetailLevel::Ids;
+  Ok(())
+}
+
+/// Fills in `device` from the hidraw device open as `fd`.
+fn read_hidraw(device: &mut HidDevice, fd: RawFd) -> Result<(), io::Error> {
+  let info = hidraw_get_info(fd)?;
+  device.vendor_id = info.vendor as u16;
+  device.product_id = info.product as u16;
+  device.product_string = hidraw_get_string(fd, HIDIOCGRAWNAME)?;
+  device.serial_number_string = hidraw_get_string(fd, HIDIOCGRAWUNIQ)?;
+  device.detail_level = DetailLevel::Ids;
+  Ok(())
+}
+
+/// Reads the `hidraw` or `uhid` device node at `path`. Nodes that are busy,
+/// e.g. `uhid` nodes, which only allow one open at a time, or that can't be
+/// opened for lack of permissions are listed with their path only, as are
+/// nodes that can be opened but not queried. `Err` if the node can't be
+/// opened otherwise, e.g. because it is gone.
+fn read_node(path: &Path, name: &str) -> Result<HidDevice, io::Error> {
+  let device = HidDevice {
+    path: path.to_string_lossy().into_owned(),
+    detail_level: DetailLevel::PathsOnly,
+    ..HidDevice::default()
+  };
+
+  let file = match File::open(path) {
+    Ok(file) => file,
+    Err(error) => match error.raw_os_error() {
+      Some(EBUSY) | Some(EACCES) | Some(EPERM) => {
+        log::debug!("listing {} by path only: {}", device.path, error);
+        return Ok(device);
+      }
+      _ => return Err(error),
+    },
+  };
+  Ok(read_open_node(device, &file, name))
+}
+
+/// Fills in `device` from its node, open as `file`, or returns it unchanged
+/// if the node doesn't answer the ioctls of its kind.
+fn read_open_node(device: HidDevice, file: &File, name: &str) -> HidDevice {
+  let mut read = device.clone();
+  let result = if name.starts_with("uhid") {
+    read_uhid(&mut read, file.as_raw_fd())
+  } else {
+    read_hidraw(&mut read, file.as_raw_fd())
+  };
+  match result {
+    Ok(()) => read,
+    Err(error) => {
+      log::debug!("listing {} by path only: {}", device.path, error);
+      device
+    }
+  }
+}
+
+/// Lists the `hidraw` and `uhid` nodes in `dev_dir`, normally `/dev`. Nodes
+/// that can't be opened, e.g. because they were unplugged while being read,
+/// are left out.
+pub(crate) fn list_hid_nodes(dev_dir: &Path) -> Result<Vec<HidDevice>, io::Error> {
+  let mut devices = Vec::new();
+  for entry in fs::read_dir(dev_dir)? {
+    let name = entry?.file_name().to_string_lossy().into_owned();
+    if !name.starts_with("hidraw") && !name.starts_with("uhid") {
+      continue;
+    }
+    match read_node(&dev_dir.join(&name), &name) {
+      Ok(device) => devices.push(device),
+      Err(error) => log::debug!("skipping {}: {}", name, error),
+    }
+  }
+
+  devices.sort();
+  Ok(devices)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::sync::atomic::{AtomicUsize, Ordering};
+
+  /// A regular file named `name`, standing in for a device node that opens
+  /// but answers no ioctl, removed on drop.
+  struct FakeNode {
+    path: std::path::PathBuf,
+  }
+
+  impl FakeNode {
+    fn new(name: &str) -> Self {
+      static NEXT: AtomicUsize = AtomicUsize::new(0);
+      let dir = std::env::temp_dir().join(format!(
+        "listhid-freebsd-{}-{}",
+        std::process::id(),
+        NEXT.fetch_add(1, Ordering::Relaxed)
+      ));
+      fs::create_dir_all(&dir).unwrap();
+      let path = dir.join(name);
+      fs::write(&path, b"").unwrap();
+      FakeNode { path }
+    }
+  }
+
+  impl Drop for FakeNode {
+    fn drop(&mut self) {
+      let _ = fs::remove_dir_all(self.path.parent().unwrap());
+    }
+  }
+
+  #[test]
+  fn hidraw_nodes_without_ioctls_degrade_to_paths_only() {
+    let node = FakeNode::new("hidraw0");
+    let device = read_node(&node.path, "hidraw0").unwrap();
+    assert_eq!(device.detail_level, DetailLevel::PathsOnly);
+    assert_eq!(device.path, node.path.to_string_lossy());
+    assert_eq!((device.vendor_id, device.product_id), (0, 0));
+  }
+
+  #[test]
+  fn uhid_nodes_without_ioctls_degrade_to_paths_only() {
+    let node = FakeNode::new("uhid0");
+    let device = read_node(&node.path, "uhid0").unwrap();
+    assert_eq!(device.detail_level, DetailLevel::PathsOnly);
+    assert_eq!(device.product_string, None);
+  }
+
+  #[test]
+  fn missing_nodes_are_errors() {
+    let node = FakeNode::new("hidraw0");
+    let missing = node.path.with_file_name("hidraw1");
+    assert_eq!(
+      read_node(&missing, "hidraw1").unwrap_err().kind(),
+      io::ErrorKind::NotFound
+    );
+  }
+
+  #[test]
+  fn usb_strings_stop_at_the_nul() {
+    assert_eq!(c_string(b"USB Mouse\0junk").as_deref(), Some("USB Mouse"));
+    assert_eq!(c_string(&[0; 8]), None);
+  }
+}
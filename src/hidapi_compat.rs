@@ -0,0 +1,39 @@
+use crate::HidDevice;
+
+/// A mirror of the fields of `hidapi::DeviceInfo`, to ease moving code that
+/// consumes hidapi's enumeration over to `HidDevice`.
+///
+/// Fields that weren't read are left empty:
+/// - `release_number` is `HidDevice::version_number`, or 0 if it wasn't read.
+/// - `usage_page` and `usage` are 0 if they weren't read.
+/// - `interface_number` is -1 for devices without one, as in hidapi.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HidApiDeviceInfoLike {
+  pub path: String,
+  pub vendor_id: u16,
+  pub product_id: u16,
+  pub serial_number: Option<String>,
+  pub release_number: u16,
+  pub manufacturer_string: Option<String>,
+  pub product_string: Option<String>,
+  pub usage_page: u16,
+  pub usage: u16,
+  pub interface_number: i32,
+}
+
+impl From<&HidDevice> for HidApiDeviceInfoLike {
+  fn from(device: &HidDevice) -> Self {
+    HidApiDeviceInfoLike {
+      path: device.path.clone(),
+      vendor_id: device.vendor_id,
+      product_id: device.product_id,
+      serial_number: device.serial_number_string.clone(),
+      release_number: device.version_number.unwrap_or(0),
+      manufacturer_string: device.manufacturer_string.clone(),
+      product_string: device.product_string.clone(),
+      usage_page: device.usage_page.unwrap_or(0),
+      usage: device.usage.unwrap_or(0),
+      interface_number: device.interface_number().map_or(-1, i32::from),
+    }
+  }
+}
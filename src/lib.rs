@@ -1,15 +1,506 @@
+#[cfg(all(windows, feature = "tokio"))]
+mod asynchronous;
+#[cfg(windows)]
+mod cache;
+#[cfg(windows)]
+mod caps;
+#[cfg(windows)]
+mod debounce;
+#[cfg(windows)]
+mod descriptor;
+mod error;
+#[cfg(target_os = "freebsd")]
+mod freebsd;
+#[cfg(feature = "hidapi-compat")]
+mod hidapi_compat;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+mod linux;
+#[cfg(windows)]
+mod open;
+pub mod os;
+#[cfg(windows)]
+mod parser;
+#[cfg(windows)]
+mod query;
+#[cfg(windows)]
+pub mod raw;
+#[cfg(feature = "serde")]
+pub mod serde_hex;
+#[cfg(windows)]
+mod watcher;
+// Only the Windows watchers run on it, but it is plain std code.
+#[cfg_attr(not(windows), allow(dead_code))]
+mod watcher_core;
 #[cfg(windows)]
 mod win32;
 
-#[derive(Debug)]
+#[cfg(all(windows, feature = "tokio"))]
+pub use asynchronous::{AsyncOpenHidDevice, HidEventStream};
+#[cfg(windows)]
+pub use cache::HidDeviceCache;
+#[cfg(windows)]
+pub use caps::{ButtonCap, HidCapabilities, HidReportType, LinkCollection, ValueCap};
+pub use error::{Error, ListHidError};
+#[cfg(feature = "hidapi-compat")]
+pub use hidapi_compat::HidApiDeviceInfoLike;
+#[cfg(windows)]
+pub use open::{AccessMode, HidOpenOptions, InputReports, OpenHidDevice, RemovalEvent, ShareMode};
+#[cfg(windows)]
+pub use parser::{
+  translate_to_scancodes, ParsedReport, ReportBuilder, ReportParser, UsageDiff, UsageValue,
+};
+#[cfg(windows)]
+pub use query::HidQuery;
+#[cfg(windows)]
+pub use watcher::{
+  DeviceWatcher, DeviceWatcherBuilder, HidEvent, LegacyDeviceWatcher, PollEvent, PollingWatcher,
+};
+
+/// Options controlling the SetupAPI scan behind an enumeration.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EnumOptions {
+  /// Limits the scan to devices from this Plug and Play enumerator, passed
+  /// to `SetupDiGetClassDevsW`. All enumerators are scanned if `None`.
+  ///
+  /// The enumerator is the one of the device exposing the interface. HID
+  /// collections are enumerated by `HID` whatever their bus, so `USB` or
+  /// `BTHLE` only match with an `interface_class` other than HID; to narrow
+  /// HID devices down by bus, filter on `HidDevice::parent_instance_id`.
+  pub enumerator: Option<String>,
+  /// Fails the whole enumeration with the error of the first device that
+  /// cannot be opened or queried, instead of skipping it.
+  ///
+  /// Off by default: a single sleeping Bluetooth device or driver hiccup
+  /// would otherwise hide every other device, which is rarely what an
+  /// interactive tool wants. The skipped devices and their errors are
+  /// returned by `HidQuery::list_with_skipped`. Turn it on when a partial
+  /// list would be misleading, e.g. for an inventory or compliance check.
+  pub strict: bool,
+  /// The most devices opened and queried at the same time, 1 by default,
+  /// which queries them one at a time on the calling thread.
+  ///
+  /// Querying a device can take a while, e.g. for a Bluetooth device that
+  /// has to wake up, so several can be queried in parallel, on up to this
+  /// many threads; 8 is a good start, see `list_hid_device_parallel`. The
+  /// cap keeps the number of threads and open handles bounded on systems
+  /// with hundreds of (often virtual) HID devices. The SetupAPI part of the
+  /// scan is always sequential.
+  pub max_concurrency: usize,
+  /// The access devices are opened with to query them, `OpenAccess::None`
+  /// by default.
+  pub open_access: OpenAccess,
+}
+
+impl Default for EnumOptions {
+  fn default() -> Self {
+    EnumOptions {
+      enumerator: None,
+      strict: false,
+      max_concurrency: 1,
+      open_access: OpenAccess::None,
+    }
+  }
+}
+
+/// The access requested when opening devices during an enumeration.
+///
+/// No access is enough for the `HidD_*` queries on most drivers, and never
+/// conflicts with other handles to the device. Some secured devices only
+/// return their preparsed data, and so their usage, when opened for reading.
+/// Devices that refuse the access asked for, such as the keyboards and mice
+/// Windows keeps open exclusively, are opened with no access instead rather
+/// than skipped.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum OpenAccess {
+  #[default]
+  None,
+  Read,
+  ReadWrite,
+}
+
+#[cfg(windows)]
+impl OpenAccess {
+  /// The `dwDesiredAccess` to pass to `CreateFileW`.
+  fn desired_access(self) -> winapi::shared::minwindef::DWORD {
+    use winapi::um::winnt::{GENERIC_READ, GENERIC_WRITE};
+
+    match self {
+      OpenAccess::None => 0,
+      OpenAccess::Read => GENERIC_READ,
+      OpenAccess::ReadWrite => GENERIC_READ | GENERIC_WRITE,
+    }
+  }
+}
+
+/// How much data is gathered for each device.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum DetailLevel {
+  /// Only the interface path; no device is opened.
+  PathsOnly,
+  /// The path, vendor and product IDs and usage, taken from the interface
+  /// properties or the path; no device is opened.
+  Ids,
+  /// Everything SetupAPI knows, without opening any device. The product
+  /// string is then the device's friendly name or description from the
+  /// registry instead, see `HidDevice::product_string`, and the other
+  /// fields only the device can tell, such as the serial number, are `None`.
+  Registry,
+  /// Everything, which requires opening each device.
+  #[default]
+  Full,
+}
+
+/// The speed a USB device operates at.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum UsbSpeed {
+  /// USB 1.x low speed, 1.5 Mbit/s.
+  Low,
+  /// USB 1.x full speed, 12 Mbit/s.
+  Full,
+  /// USB 2.0 high speed, 480 Mbit/s.
+  High,
+  /// USB 3.x SuperSpeed, 5 Gbit/s.
+  Super,
+  /// USB 3.1 SuperSpeedPlus, 10 Gbit/s and up. Windows 7 reports these
+  /// devices as `Super`.
+  SuperPlus,
+}
+
+#[cfg(windows)]
+impl UsbSpeed {
+  #[allow(non_upper_case_globals)]
+  fn from_connection_speed(connection_speed: &win32::UsbConnectionSpeed) -> Option<Self> {
+    use winapi::shared::usbspec::{UsbFullSpeed, UsbHighSpeed, UsbLowSpeed, UsbSuperSpeed};
+
+    match u32::from(connection_speed.speed) {
+      UsbLowSpeed => Some(UsbSpeed::Low),
+      UsbFullSpeed => Some(UsbSpeed::Full),
+      UsbHighSpeed => Some(UsbSpeed::High),
+      UsbSuperSpeed if connection_speed.super_speed_plus => Some(UsbSpeed::SuperPlus),
+      UsbSuperSpeed => Some(UsbSpeed::Super),
+      _ => None,
+    }
+  }
+}
+
+// Usages from the HID Usage Tables, as in hidusage.h.
+const USAGE_PAGE_GENERIC: u16 = 0x01;
+const USAGE_GENERIC_MOUSE: u16 = 0x02;
+#[cfg(windows)]
+const USAGE_GENERIC_JOYSTICK: u16 = 0x04;
+const USAGE_GENERIC_GAMEPAD: u16 = 0x05;
+const USAGE_GENERIC_KEYBOARD: u16 = 0x06;
+
+/// A HID device interface.
+///
+/// Fields that were not gathered at the `detail_level` used for the listing
+/// are `None` (or zero / `false`), so check `detail_level` to tell "not
+/// present" apart from "not queried".
+///
+/// With the `serde` feature, it can be serialized, e.g. to ship device
+/// snapshots as JSON. Missing fields deserialize to their defaults, so
+/// snapshots from older versions still load. The IDs are numbers, or
+/// zero-padded hex strings such as `"046D"` with the `serde-hex-ids` feature;
+/// both forms deserialize either way, see `serde_hex`.
+#[derive(Debug, Default, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
 pub struct HidDevice {
   pub path: String,
+  #[cfg_attr(
+    feature = "serde-hex-ids",
+    serde(serialize_with = "serde_hex::serialize")
+  )]
+  #[cfg_attr(feature = "serde", serde(deserialize_with = "serde_hex::deserialize"))]
   pub product_id: u16,
+  #[cfg_attr(
+    feature = "serde-hex-ids",
+    serde(serialize_with = "serde_hex::serialize")
+  )]
+  #[cfg_attr(feature = "serde", serde(deserialize_with = "serde_hex::deserialize"))]
   pub vendor_id: u16,
+  /// The device's release number in binary-coded decimal, e.g. `0x0112` for
+  /// 1.12, from `HIDD_ATTRIBUTES::VersionNumber`.
+  pub version_number: Option<u16>,
+  pub manufacturer_string: Option<String>,
+  /// The product string the device reports. When listed with
+  /// `DetailLevel::Registry`, it is `friendly_name` or `device_description`
+  /// instead: those come from the driver's INF, so they are generic, e.g.
+  /// `HID-compliant mouse`, rather than the device's own name.
   pub product_string: Option<String>,
   pub serial_number_string: Option<String>,
+  #[deprecated(note = "use `os::windows::HidDeviceExt::dev_inst`")]
+  #[cfg_attr(all(windows, feature = "serde"), serde(skip))]
   pub dev_inst: Option<u32>,
+  #[deprecated(note = "use `os::windows::HidDeviceExt::pdo_name`")]
+  #[cfg_attr(all(windows, feature = "serde"), serde(skip))]
   pub pdo_name: Option<String>,
+  pub container_id: Option<String>,
+  /// The device's setup class GUID, formatted like `container_id`, e.g.
+  /// `{745A17A0-74D3-11D0-B6FE-00A0C90F57DA}` for `HIDClass`.
+  pub class_guid: Option<String>,
+  /// The device's setup class name, e.g. `HIDClass`, `Keyboard` or `Mouse`.
+  pub class_name: Option<String>,
+  pub bus_reported_description: Option<String>,
+  /// The device's friendly name from the registry (`SPDRP_FRIENDLYNAME`),
+  /// which few HID drivers set.
+  pub friendly_name: Option<String>,
+  /// The device's description from the registry (`SPDRP_DEVICEDESC`), as
+  /// shown in Device Manager, e.g. `HID-compliant mouse`.
+  pub device_description: Option<String>,
+  #[deprecated(note = "use `os::windows::HidDeviceExt::instance_id`")]
+  #[cfg_attr(all(windows, feature = "serde"), serde(skip))]
+  pub instance_id: Option<String>,
+  pub last_arrival: Option<std::time::SystemTime>,
+  pub last_removal: Option<std::time::SystemTime>,
+  pub usage_page: Option<u16>,
+  pub usage: Option<u16>,
+  pub parent_instance_id: Option<String>,
+  /// Whether the device is redirected from a Remote Desktop client rather than
+  /// attached to this machine.
+  pub is_redirected: bool,
+  /// Whether the device can wake the system, from its power data.
+  pub wake_capable: Option<bool>,
+  /// The device power state it was last put in, 1 for D0 (fully on) to 4 for
+  /// D3 (off).
+  pub current_power_state: Option<u32>,
+  /// The device interface class the device was listed under, as formatted by
+  /// `guid_to_string`; `GUID_DEVINTERFACE_HID` unless the listing asked for
+  /// another class. `None` for devices not found through SetupAPI.
+  pub interface_guid: Option<String>,
+  /// The speed the USB device operates at, as its hub reports it. `None` for
+  /// devices that aren't on USB, e.g. Bluetooth ones, and for devices that
+  /// aren't present.
+  pub usb_speed: Option<UsbSpeed>,
+  /// The problem Windows recorded for the device node, if any, as shown on
+  /// the General tab in Device Manager. The common ones are:
+  /// - 10, `CM_PROB_FAILED_START`: the driver failed to start the device.
+  /// - 22, `CM_PROB_DISABLED`: the device was disabled, e.g. in Device
+  ///   Manager.
+  /// - 28, `CM_PROB_FAILED_INSTALL`: no driver is installed.
+  /// - 29, `CM_PROB_HARDWARE_DISABLED`: the firmware disabled the device.
+  /// - 31, `CM_PROB_FAILED_ADD`: the driver failed to load.
+  /// - 43, `CM_PROB_FAILED_POST_START`: the driver reported a failure after
+  ///   starting, e.g. a USB device that stopped responding.
+  ///
+  /// Devices with a problem usually have no active interface, so they are
+  /// only listed with `HidQuery::include_non_present`.
+  pub problem_code: Option<u32>,
+  /// Whether the device is disabled, by the user or by the firmware, i.e.
+  /// `problem_code` is `CM_PROB_DISABLED` or `CM_PROB_HARDWARE_DISABLED`.
+  pub is_disabled: bool,
+  pub detail_level: DetailLevel,
+  /// Whether the device was listed without `hid.dll`, which some Server Core
+  /// and Nano Server images lack. Its `detail_level` is then
+  /// `DetailLevel::Registry` whatever was asked for; see
+  /// `os::windows::hid_dll_available`.
+  pub is_degraded: bool,
+  /// Backs `os::windows::HidDeviceExt`. Serialized in place of the
+  /// deprecated fields, under their names.
+  #[cfg(windows)]
+  #[cfg_attr(feature = "serde", serde(flatten))]
+  windows: os::windows::WindowsDeviceData,
+}
+
+impl HidDevice {
+  /// Whether the device is probably virtual, i.e. created by software rather
+  /// than plugged in: both IDs are zero and it is neither on USB nor on
+  /// Bluetooth. A heuristic only; some software devices make up IDs, and
+  /// devices whose bus is unknown (e.g. when listed with
+  /// `DetailLevel::Ids`) are judged by their IDs alone.
+  pub fn is_virtual_heuristic(&self) -> bool {
+    if self.vendor_id != 0 || self.product_id != 0 {
+      return false;
+    }
+
+    // The HID device's own enumerator is always HID; its parent's is the bus.
+    let enumerator = match &self.parent_instance_id {
+      Some(parent_instance_id) => parent_instance_id.split('\\').next().unwrap_or(""),
+      None => return true,
+    }
+    .to_ascii_uppercase();
+    enumerator != "USB" && !enumerator.starts_with("BTH")
+  }
+
+  /// The device path in the form returned by `normalize_device_path`, for
+  /// comparing devices across enumerations. `path` itself is kept as Windows
+  /// reported it.
+  pub fn normalized_path(&self) -> String {
+    normalize_device_path(&self.path)
+  }
+
+  /// The interface number of a composite USB device, from the `mi_XX` part of
+  /// the path. `None` for single-interface and non-USB devices.
+  pub fn interface_number(&self) -> Option<u8> {
+    let path = self.path.to_ascii_lowercase();
+    let start = path.find("&mi_")? + "&mi_".len();
+    u8::from_str_radix(path.get(start..start + 2)?, 16).ok()
+  }
+
+  /// Whether the top-level collection is a keyboard (Generic Desktop /
+  /// Keyboard). `false` if the usage wasn't read, see `detail_level`.
+  pub fn is_keyboard(&self) -> bool {
+    self.has_usage(USAGE_PAGE_GENERIC, USAGE_GENERIC_KEYBOARD)
+  }
+
+  /// Whether the top-level collection is a mouse (Generic Desktop / Mouse).
+  /// `false` if the usage wasn't read, see `detail_level`.
+  pub fn is_mouse(&self) -> bool {
+    self.has_usage(USAGE_PAGE_GENERIC, USAGE_GENERIC_MOUSE)
+  }
+
+  /// Whether the top-level collection is a gamepad (Generic Desktop / Game
+  /// Pad). `false` if the usage wasn't read, see `detail_level`.
+  pub fn is_gamepad(&self) -> bool {
+    self.has_usage(USAGE_PAGE_GENERIC, USAGE_GENERIC_GAMEPAD)
+  }
+
+  /// Copies the data behind `os::windows::HidDeviceExt` into the deprecated
+  /// public fields.
+  #[cfg(windows)]
+  #[allow(deprecated)]
+  fn mirror_deprecated_fields(&mut self) {
+    self.dev_inst = self.windows.dev_inst;
+    self.pdo_name = self.windows.pdo_name.clone();
+    self.instance_id = self.windows.instance_id.clone();
+  }
+
+  fn has_usage(&self, usage_page: u16, usage: u16) -> bool {
+    self.usage_page == Some(usage_page) && self.usage == Some(usage)
+  }
+
+  fn sort_key(&self) -> (u16, u16, Option<u8>, String) {
+    (
+      self.vendor_id,
+      self.product_id,
+      self.interface_number(),
+      self.normalized_path(),
+    )
+  }
+}
+
+/// Devices are equal if they are the same interface, i.e. have the same IDs
+/// and path; the other fields are not compared.
+impl PartialEq for HidDevice {
+  fn eq(&self, other: &Self) -> bool {
+    self.sort_key() == other.sort_key()
+  }
+}
+
+impl Eq for HidDevice {}
+
+impl PartialOrd for HidDevice {
+  fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+    Some(self.cmp(other))
+  }
+}
+
+/// Devices are ordered by vendor ID, product ID, interface number (devices
+/// without one first) and finally normalized path, which gives listings a
+/// stable order; SetupAPI's own order can change across reboots.
+impl Ord for HidDevice {
+  fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+    self.sort_key().cmp(&other.sort_key())
+  }
+}
+
+#[derive(Debug)]
+pub struct SkippedDevice {
+  pub path: String,
+  pub error: ListHidError,
+}
+
+/// Extracts the vendor and product IDs from a device interface path.
+///
+/// Understands both the USB form (`...#vid_046d&pid_c534...`) and the
+/// Bluetooth form (`..._vid&0002046d_pid&b023...`), where the vendor ID is
+/// prefixed with its source.
+pub fn parse_ids_from_path(path: &str) -> Option<(u16, u16)> {
+  let path = path.to_ascii_lowercase();
+  let hex_after = |tag: &str, len: usize| -> Option<u16> {
+    let start = path.find(tag)? + tag.len();
+    let digits = path.get(start..start + len)?;
+    u16::from_str_radix(&digits[len - 4..], 16).ok()
+  };
+
+  let vendor_id = hex_after("vid_", 4).or_else(|| hex_after("vid&", 8))?;
+  let product_id = hex_after("pid_", 4).or_else(|| hex_after("pid&", 4))?;
+  Some((vendor_id, product_id))
+}
+
+/// Normalizes a device interface path so that paths to the same interface
+/// compare equal: lowercases it and puts the trailing interface class GUID
+/// back in braces if they are missing, e.g.
+/// `\\?\HID#VID_046D&PID_C534#7&1a2b3c&0&0000#4D1E55B2-F16F-11CF-88CB-001111000030`
+/// becomes `...#{4d1e55b2-f16f-11cf-88cb-001111000030}`.
+pub fn normalize_device_path(path: &str) -> String {
+  let path = path.to_ascii_lowercase();
+  let (prefix, class) = match path.rfind('#') {
+    Some(index) => path.split_at(index + 1),
+    None => return path,
+  };
+
+  let guid = class.trim_start_matches('{').trim_end_matches('}');
+  let is_guid = guid.len() == 36
+    && guid.chars().enumerate().all(|(i, c)| match i {
+      8 | 13 | 18 | 23 => c == '-',
+      _ => c.is_ascii_hexdigit(),
+    });
+  if is_guid {
+    format!("{}{{{}}}", prefix, guid)
+  } else {
+    path
+  }
+}
+
+/// Whether a device whose parent has the instance ID `parent_instance_id` is
+/// redirected by terminal services, e.g. `TERMINPUT_BUS\UMB\...` for Remote
+/// Desktop input or `TS_USB_HUB_ENUMERATOR\...` for RemoteFX USB redirection.
+#[cfg(windows)]
+fn is_redirected_parent(parent_instance_id: &str) -> bool {
+  const REDIRECTING_ENUMERATORS: [&str; 2] = ["TERMINPUT_BUS\\", "TS_USB_HUB_ENUMERATOR\\"];
+
+  let parent_instance_id = parent_instance_id.to_ascii_uppercase();
+  REDIRECTING_ENUMERATORS
+    .iter()
+    .any(|enumerator| parent_instance_id.starts_with(enumerator))
+}
+
+/// Formats a GUID laid out in memory as a Windows `GUID` struct, like
+/// `guid_to_string`.
+#[cfg(windows)]
+fn format_guid(bytes: &[u8; 16]) -> String {
+  let mut data4 = [0; 8];
+  data4.copy_from_slice(&bytes[8..]);
+  guid_to_string(&winapi::shared::guiddef::GUID {
+    Data1: u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+    Data2: u16::from_le_bytes([bytes[4], bytes[5]]),
+    Data3: u16::from_le_bytes([bytes[6], bytes[7]]),
+    Data4: data4,
+  })
+}
+
+/// Formats a GUID in the registry form
+/// `{XXXXXXXX-XXXX-XXXX-XXXX-XXXXXXXXXXXX}`, e.g.
+/// `{4D1E55B2-F16F-11CF-88CB-001111000030}` for `GUID_DEVINTERFACE_HID`.
+#[cfg(windows)]
+pub fn guid_to_string(guid: &winapi::shared::guiddef::GUID) -> String {
+  format!(
+    "{{{:08X}-{:04X}-{:04X}-{:02X}{:02X}-{:02X}{:02X}{:02X}{:02X}{:02X}{:02X}}}",
+    guid.Data1,
+    guid.Data2,
+    guid.Data3,
+    guid.Data4[0],
+    guid.Data4[1],
+    guid.Data4[2],
+    guid.Data4[3],
+    guid.Data4[4],
+    guid.Data4[5],
+    guid.Data4[6],
+    guid.Data4[7],
+  )
 }
 
 #[cfg(windows)]
@@ -22,18 +513,16 @@ struct DeviceData {
 fn build_device_data_with_info(
   class_devs_info: &win32::HDevInfo,
   device_info_data_entries: std::vec::Vec<winapi::um::setupapi::SP_DEVINFO_DATA>,
+  interface_guid: &winapi::shared::guiddef::GUID,
 ) -> Result<std::vec::Vec<DeviceData>, std::io::Error> {
   use win32::setup_di_enum_device_interfaces;
-  use winapi::shared::hidclass::GUID_DEVINTERFACE_HID;
 
   let mut devices = Vec::new();
 
   for mut device_info_data in device_info_data_entries {
-    let interface_data_entries = setup_di_enum_device_interfaces(
-      &class_devs_info,
-      &mut device_info_data,
-      &GUID_DEVINTERFACE_HID,
-    )?;
+    let interface_data_entries = unsafe {
+      setup_di_enum_device_interfaces(class_devs_info, &mut device_info_data, interface_guid)
+    }?;
     for interface_data in interface_data_entries {
       devices.push(DeviceData {
         interface_data,
@@ -48,16 +537,14 @@ fn build_device_data_with_info(
 #[cfg(windows)]
 fn build_device_data_without_info(
   class_devs_info: &win32::HDevInfo,
+  interface_guid: &winapi::shared::guiddef::GUID,
 ) -> Result<std::vec::Vec<DeviceData>, std::io::Error> {
   use win32::setup_di_enum_device_interfaces;
-  use winapi::shared::hidclass::GUID_DEVINTERFACE_HID;
 
   let mut devices = Vec::new();
-  let interface_data_entries = setup_di_enum_device_interfaces(
-    &class_devs_info,
-    std::ptr::null_mut(),
-    &GUID_DEVINTERFACE_HID,
-  )?;
+  let interface_data_entries = unsafe {
+    setup_di_enum_device_interfaces(class_devs_info, std::ptr::null_mut(), interface_guid)
+  }?;
   for interface_data in interface_data_entries {
     devices.push(DeviceData {
       interface_data,
@@ -71,50 +558,58 @@ fn build_device_data_without_info(
 #[cfg(windows)]
 fn build_device_data(
   class_devs_info: &win32::HDevInfo,
+  interface_guid: &winapi::shared::guiddef::GUID,
 ) -> Result<std::vec::Vec<DeviceData>, std::io::Error> {
   use win32::setup_di_enum_device_info;
 
-  match setup_di_enum_device_info(&class_devs_info) {
+  match setup_di_enum_device_info(class_devs_info) {
     Ok(device_info_data_entries) => {
-      build_device_data_with_info(class_devs_info, device_info_data_entries)
+      build_device_data_with_info(class_devs_info, device_info_data_entries, interface_guid)
     }
-    Err(_) => build_device_data_without_info(class_devs_info),
+    // A set without device information is still enumerated by interface.
+    Err(error) => or_original_error(
+      build_device_data_without_info(class_devs_info, interface_guid)?,
+      error,
+    ),
   }
 }
 
-#[cfg(not(windows))]
-pub fn list_hid_device() -> Result<(), &'static str> {
-  Err("unsupported platform")
+/// `devices`, or `error` if there are none: finding nothing by the fallback
+/// enumeration doesn't mean there are no devices, so the original failure is
+/// kept rather than reported as an empty list.
+#[cfg(windows)]
+fn or_original_error<T>(devices: Vec<T>, error: std::io::Error) -> Result<Vec<T>, std::io::Error> {
+  if devices.is_empty() {
+    Err(error)
+  } else {
+    Ok(devices)
+  }
 }
 
+/// Opens a device with no access rights, which is enough for the `HidD_*`
+/// queries, shared with any other handle to it.
 #[cfg(windows)]
-pub fn list_hid_device() -> Result<Vec<HidDevice>, std::io::Error> {
+fn open_device_for_query(path: &str) -> Result<win32::Handle, std::io::Error> {
+  open_device_for_query_wide(&win32::string_to_lpcwstr(path), 0)
+}
+
+/// Same as `open_device_for_query`, for a NUL-terminated UTF-16 path such as
+/// `DeviceInterfaceDetail::device_path_wide`, which is opened exactly as
+/// Windows returned it, with `desired_access`.
+#[cfg(windows)]
+fn open_device_for_query_wide(
+  path_wide: &[u16],
+  desired_access: winapi::shared::minwindef::DWORD,
+) -> Result<win32::Handle, std::io::Error> {
   use std::ptr;
-  use win32::{
-    create_file, get_pdo_name, hid_d_get_attributes, hid_d_get_product_string,
-    hid_d_get_serial_number_string, setup_di_get_class_devs, setup_di_get_device_interface_detail,
-    Handle,
-  };
+  use win32::{create_file_wide, Handle};
   use winapi::um::fileapi::OPEN_EXISTING;
-  use winapi::um::setupapi::{DIGCF_ALLCLASSES, DIGCF_DEVICEINTERFACE, DIGCF_PRESENT};
   use winapi::um::winnt::{FILE_ATTRIBUTE_NORMAL, FILE_SHARE_READ, FILE_SHARE_WRITE};
 
-  let class_devs_info = setup_di_get_class_devs(
-    ptr::null(),
-    ptr::null(),
-    ptr::null_mut(),
-    DIGCF_ALLCLASSES | DIGCF_PRESENT | DIGCF_DEVICEINTERFACE,
-  )?;
-
-  let mut devices = Vec::new();
-
-  for mut device_data in build_device_data(&class_devs_info)? {
-    let device_interface_detail =
-      setup_di_get_device_interface_detail(&class_devs_info, &mut device_data.interface_data)?;
-
-    let handle = create_file(
-      &device_interface_detail.device_path,
-      0,
+  unsafe {
+    create_file_wide(
+      path_wide,
+      desired_access,
       FILE_SHARE_READ | FILE_SHARE_WRITE,
       ptr::null_mut(),
       OPEN_EXISTING,
@@ -122,20 +617,1151 @@ pub fn list_hid_device() -> Result<Vec<HidDevice>, std::io::Error> {
       Handle {
         native_handle: None,
       },
+    )
+  }
+}
+
+/// Reads a device from SetupAPI and, if its interface is present and
+/// `detail_level` asks for it, from the device itself. Non-present interfaces
+/// cannot be opened, so their vendor and product IDs are taken from the
+/// interface properties or path instead.
+///
+/// For interfaces of a class other than HID (`is_hid == false`) the `HidD_*`
+/// calls are expected to fail, and the fields they fill are left empty.
+#[cfg(windows)]
+fn read_hid_device(
+  class_devs_info: &win32::HDevInfo,
+  device_data: &DeviceData,
+  device_interface_detail: &win32::DeviceInterfaceDetail,
+  instance_id: Option<String>,
+  is_hid: bool,
+  detail_level: DetailLevel,
+  scratch: &mut Vec<u8>,
+) -> Result<HidDevice, ListHidError> {
+  let mut device = read_device_info(
+    class_devs_info,
+    device_data,
+    device_interface_detail,
+    instance_id,
+    detail_level,
+    scratch,
+  );
+  if needs_open(device_data, detail_level) {
+    read_opened_device(
+      &mut device,
+      &device_interface_detail.device_path_wide,
+      is_hid,
+      OpenAccess::None,
     )?;
+  }
+  Ok(device)
+}
+
+/// Whether `read_opened_device` has anything to add for a device: it is
+/// present and all its details are wanted.
+#[cfg(windows)]
+fn needs_open(device_data: &DeviceData, detail_level: DetailLevel) -> bool {
+  use winapi::um::setupapi::SPINT_ACTIVE;
 
-    let hidd_attributes = hid_d_get_attributes(&handle)?;
+  detail_level == DetailLevel::Full && device_data.interface_data.Flags & SPINT_ACTIVE != 0
+}
 
-    devices.push(HidDevice {
-      path: device_interface_detail.device_path,
-      product_id: hidd_attributes.ProductID,
-      vendor_id: hidd_attributes.VendorID,
-      product_string: hid_d_get_product_string(&handle),
-      serial_number_string: hid_d_get_serial_number_string(&handle),
-      dev_inst: Some(device_interface_detail.device_info_data.DevInst),
-      pdo_name: get_pdo_name(&class_devs_info, device_data.info_data),
-    });
+/// The SetupAPI half of `read_hid_device`, which doesn't open the device.
+#[cfg(windows)]
+fn read_device_info(
+  class_devs_info: &win32::HDevInfo,
+  device_data: &DeviceData,
+  device_interface_detail: &win32::DeviceInterfaceDetail,
+  instance_id: Option<String>,
+  detail_level: DetailLevel,
+  scratch: &mut Vec<u8>,
+) -> HidDevice {
+  use win32::{
+    get_bus_reported_device_desc, get_class_guid, get_class_name, get_container_id,
+    get_device_desc, get_friendly_name, get_interface_ids, get_interface_usage,
+    get_last_arrival_date, get_last_removal_date, get_parent_instance_id, get_pdo_name,
+    get_power_data, get_registry_properties, HID_DEVICE_REGISTRY_PROPERTIES,
+  };
+  use winapi::shared::cfg::{CM_PROB_DISABLED, CM_PROB_HARDWARE_DISABLED};
+
+  let path = &device_interface_detail.device_path;
+  let mut device = HidDevice {
+    path: path.clone(),
+    interface_guid: Some(guid_to_string(
+      &device_data.interface_data.InterfaceClassGuid,
+    )),
+    detail_level,
+    ..Default::default()
+  };
+  if detail_level == DetailLevel::PathsOnly {
+    return device;
+  }
+
+  let usage = get_interface_usage(class_devs_info, device_data.interface_data);
+  device.usage_page = usage.map(|(usage_page, _)| usage_page);
+  device.usage = usage.map(|(_, usage)| usage);
+  if let Some((vendor_id, product_id)) =
+    get_interface_ids(class_devs_info, device_data.interface_data)
+      .or_else(|| parse_ids_from_path(path))
+  {
+    device.vendor_id = vendor_id;
+    device.product_id = product_id;
+  }
+  if detail_level == DetailLevel::Ids {
+    return device;
+  }
+
+  let parent_instance_id = get_parent_instance_id(class_devs_info, device_data.info_data);
+  device.windows.dev_inst = Some(device_interface_detail.device_info_data.DevInst);
+  device.problem_code =
+    win32::cm_get_dev_node_problem(device_interface_detail.device_info_data.DevInst);
+  device.is_disabled = matches!(
+    device.problem_code,
+    Some(CM_PROB_DISABLED) | Some(CM_PROB_HARDWARE_DISABLED)
+  );
+  let properties = get_registry_properties(
+    class_devs_info,
+    device_data.info_data,
+    &HID_DEVICE_REGISTRY_PROPERTIES,
+    scratch,
+  );
+  device.windows.pdo_name = get_pdo_name(&properties);
+  device.container_id =
+    get_container_id(class_devs_info, device_data.info_data).map(|id| format_guid(&id));
+  device.class_guid = get_class_guid(&properties);
+  device.class_name = get_class_name(&properties);
+  device.bus_reported_description =
+    get_bus_reported_device_desc(class_devs_info, device_data.info_data);
+  device.friendly_name = get_friendly_name(&properties);
+  device.device_description = get_device_desc(&properties);
+  if detail_level == DetailLevel::Registry {
+    device.product_string = device
+      .friendly_name
+      .clone()
+      .or_else(|| device.device_description.clone());
+  }
+  device.windows.instance_id = instance_id;
+  device.last_arrival = get_last_arrival_date(class_devs_info, device_data.info_data);
+  device.last_removal = get_last_removal_date(class_devs_info, device_data.info_data);
+  device.is_redirected = parent_instance_id
+    .as_deref()
+    .is_some_and(is_redirected_parent);
+  device.parent_instance_id = parent_instance_id;
+  if let Some(power_info) = get_power_data(&properties) {
+    device.wake_capable = Some(power_info.wake_capable);
+    device.current_power_state = Some(power_info.current_power_state);
+  }
+  device.mirror_deprecated_fields();
+  device
+}
+
+/// The device half of `read_hid_device`: opens the device at `path_wide`,
+/// with `open_access` if it allows it, and fills in what only the device can
+/// tell. Doesn't touch SetupAPI, so it can run on another thread.
+#[cfg(windows)]
+fn read_opened_device(
+  device: &mut HidDevice,
+  path_wide: &[u16],
+  is_hid: bool,
+  open_access: OpenAccess,
+) -> Result<(), ListHidError> {
+  use os::windows::HidDeviceExt;
+  use win32::{
+    hid_d_get_attributes, hid_d_get_manufacturer_string, hid_d_get_preparsed_data,
+    hid_d_get_product_string, hid_d_get_serial_number_string, hid_p_get_caps,
+  };
+
+  // Asks the hub rather than the device, so it works without opening it.
+  device.usb_speed = device
+    .dev_inst()
+    .and_then(win32::get_usb_connection_speed)
+    .and_then(|connection_speed| UsbSpeed::from_connection_speed(&connection_speed));
+
+  // Without hid.dll, the device can't tell anything, so it is listed with
+  // what SetupAPI knows, as at `DetailLevel::Registry`.
+  if !win32::hid_dll_available() {
+    device.detail_level = DetailLevel::Registry;
+    device.is_degraded = true;
+    device.product_string = device
+      .friendly_name
+      .clone()
+      .or_else(|| device.device_description.clone());
+    return Ok(());
+  }
+
+  let path = &device.path;
+
+  #[cfg(feature = "tracing")]
+  let _span = tracing::info_span!("open_device", path = %path).entered();
+
+  log::debug!("opening {}", path);
+  let handle = match open_device_for_query_wide(path_wide, open_access.desired_access()) {
+    Err(error) if open_access != OpenAccess::None => {
+      log::debug!(
+        "opening {} with {:?} access failed: {}",
+        path,
+        open_access,
+        error
+      );
+      open_device_for_query_wide(path_wide, 0)
+    }
+    result => result,
+  }
+  .map_err(|source| {
+    log::warn!("CreateFileW failed for {}: {}", path, source);
+    ListHidError::DeviceOpen {
+      path: path.clone(),
+      source,
+    }
+  })?;
+
+  let hidd_attributes = match hid_d_get_attributes(&handle) {
+    Ok(hidd_attributes) => hidd_attributes,
+    Err(_) if !is_hid => return Ok(()),
+    Err(source) => {
+      log::warn!("HidD_GetAttributes failed for {}: {}", path, source);
+      return Err(ListHidError::HidApi {
+        path: path.clone(),
+        call: "HidD_GetAttributes",
+        source,
+      });
+    }
+  };
+  // The IDs from the interface or the path, read before, if any.
+  if let Some((vendor_id, product_id)) = resolve_ids(
+    Some((hidd_attributes.VendorID, hidd_attributes.ProductID)),
+    Some((device.vendor_id, device.product_id)),
+  ) {
+    device.vendor_id = vendor_id;
+    device.product_id = product_id;
+  }
+  device.version_number = Some(hidd_attributes.VersionNumber);
+  device.manufacturer_string = hid_d_get_manufacturer_string(&handle);
+  device.product_string = hid_d_get_product_string(&handle);
+  device.serial_number_string = hid_d_get_serial_number_string(&handle);
+
+  if device.usage_page.is_none() {
+    match hid_d_get_preparsed_data(&handle).and_then(|data| hid_p_get_caps(&data)) {
+      Ok(caps) => {
+        device.usage_page = Some(caps.UsagePage);
+        device.usage = Some(caps.Usage);
+      }
+      Err(error) => log::warn!("reading the caps failed for {}: {}", device.path, error),
+    }
+  }
+
+  log::debug!("finished reading {}", device.path);
+  Ok(())
+}
+
+/// The vendor and product IDs to report, from the `HidD_GetAttributes`
+/// ones if they could be read and aren't both zero, as virtual devices often
+/// report, or else from `fallback`, e.g. the IDs in the path. Zero
+/// attributes are kept when there is no fallback; `None` only if the
+/// attributes couldn't be read and there is no fallback either.
+#[cfg(windows)]
+fn resolve_ids(attributes: Option<(u16, u16)>, fallback: Option<(u16, u16)>) -> Option<(u16, u16)> {
+  match attributes {
+    Some(ids) if ids != (0, 0) => Some(ids),
+    _ => fallback.or(attributes),
+  }
+}
+
+/// The container ID Windows gives devices that are part of the computer
+/// itself, shared by all of them.
+#[cfg(windows)]
+const NULL_CONTAINER_ID: &str = "{00000000-0000-0000-FFFF-FFFFFFFFFFFF}";
+
+#[cfg(windows)]
+impl HidDevice {
+  /// Looks up the HID device with the given device instance ID, e.g.
+  /// `HID\VID_046D&PID_C077\7&1A2B3C4D&0&0000`, as found in Device Manager or
+  /// in `instance_id`. Every field is filled in, as when listing at
+  /// `DetailLevel::Full`.
+  ///
+  /// Fails with `ListHidError::Enumeration` if there is no such device, or
+  /// it has no present HID interface.
+  pub fn from_instance_id(instance_id: &str) -> Result<HidDevice, ListHidError> {
+    use std::io::{Error, ErrorKind};
+    use std::ptr;
+    use win32::{
+      setup_di_create_device_info_list, setup_di_get_device_instance_id, setup_di_open_device_info,
+    };
+
+    // A set of just the device, to check it exists and get its ID as Windows
+    // spells it. The set doesn't have the device's interfaces, which are
+    // listed instead by passing the ID as the enumerator.
+    let class_devs_info =
+      unsafe { setup_di_create_device_info_list(ptr::null(), ptr::null_mut()) }?;
+    let mut device_info_data = setup_di_open_device_info(&class_devs_info, instance_id)?;
+    let instance_id = setup_di_get_device_instance_id(&class_devs_info, &mut device_info_data)?;
+
+    HidQuery::new()
+      .options(EnumOptions {
+        enumerator: Some(instance_id.clone()),
+        ..EnumOptions::default()
+      })
+      .list()?
+      .into_iter()
+      .next()
+      .ok_or_else(|| {
+        Error::new(
+          ErrorKind::NotFound,
+          format!("{} has no HID interface", instance_id),
+        )
+        .into()
+      })
+  }
+
+  /// Reads the HID device at the interface path `path`, as
+  /// `get_hid_device` does: without SetupAPI, so only what the device itself
+  /// reports is filled in, i.e. the IDs, version number, strings and
+  /// top-level usage. Use `get_device_by_path` for the other fields.
+  pub fn from_path(path: &str) -> Result<HidDevice, ListHidError> {
+    get_hid_device(path)
+  }
+
+  /// Lists the paths of every present HID interface of the same physical
+  /// device, this one included, e.g. to find the vendor-defined collection
+  /// next to a keyboard's.
+  ///
+  /// Interfaces are matched by container ID, which Windows assigns per
+  /// physical device, so this is exact even for devices without a serial
+  /// number. Built-in devices all share one container ID; for those, vendor
+  /// ID, product ID and serial number are matched instead, which can't tell
+  /// apart identical devices without serial numbers. A device listed below
+  /// `DetailLevel::Full` is looked up again by its path first.
+  pub fn sibling_paths(&self) -> Result<Vec<String>, ListHidError> {
+    let refreshed;
+    let this = if self.detail_level == DetailLevel::Full {
+      self
+    } else {
+      refreshed = get_device_by_path(&self.path)?.ok_or_else(|| ListHidError::DeviceOpen {
+        path: self.path.clone(),
+        source: std::io::ErrorKind::NotFound.into(),
+      })?;
+      &refreshed
+    };
+
+    let container_id = this
+      .container_id
+      .as_deref()
+      .filter(|container_id| *container_id != NULL_CONTAINER_ID);
+    Ok(
+      list_hid_device()?
+        .into_iter()
+        .filter(|device| match container_id {
+          Some(container_id) => device.container_id.as_deref() == Some(container_id),
+          None => {
+            device.vendor_id == this.vendor_id
+              && device.product_id == this.product_id
+              && device.serial_number_string == this.serial_number_string
+          }
+        })
+        .map(|device| device.path)
+        .collect(),
+    )
+  }
+}
+
+/// Lists the present HID devices, sorted as by `HidDevice`'s `Ord`.
+///
+/// A machine without any HID devices, e.g. a headless VM, gets an empty list
+/// rather than an error. The signature is the same on every platform, so
+/// portable code needs no `cfg` of its own:
+///
+/// ```
+/// let result: Result<Vec<listhid::HidDevice>, listhid::ListHidError> =
+///   listhid::list_hid_device();
+/// match result {
+///   Ok(devices) => println!("{} devices", devices.len()),
+///   Err(listhid::ListHidError::Unsupported) => println!("no HID support"),
+///   Err(e) => println!("error: {}", e),
+/// }
+/// ```
+///
+/// On Windows, devices that cannot be opened or queried (e.g. a sleeping
+/// Bluetooth HID) are left out of the result; use
+/// `list_hid_device_with_skipped` to see them.
+/// Where `hid.dll` is missing, as on some Server Core and Nano Server
+/// images, the devices are still listed, at `DetailLevel::Registry` and with
+/// `is_degraded` set; see `os::windows::hid_dll_available`.
+///
+/// On Linux, everything is read from `/sys/class/hidraw` without opening the
+/// nodes, so only the path, IDs, product string and serial number are filled
+/// in, the latter two from the kernel's `HID_NAME` and `HID_UNIQ`. With the
+/// `udev` feature, the manufacturer, and the product string and serial
+/// number the kernel doesn't know, are filled in from the udev database.
+/// `path` is the `/dev/hidrawN` node either way.
+///
+/// Android is listed the same way, but apps without root usually can't read
+/// the sysfs entries. The nodes' paths are always listed; their IDs are
+/// filled in from the HID device's sysfs name where its link is readable,
+/// and the product string from `/proc/bus/input/devices` for devices that
+/// are also input devices, but the serial number needs root. Devices that
+/// only got their path have `DetailLevel::PathsOnly`.
+///
+/// On FreeBSD, the `hidraw` and `uhid` nodes are opened for their IDs and
+/// strings, which fill in the same fields as on Linux, read from the USB
+/// device for `uhid` nodes. Nodes that are busy, as `uhid` nodes open
+/// elsewhere are, or that can't be opened for lack of permissions are
+/// listed with their path only.
+///
+/// Elsewhere, it fails with `ListHidError::Unsupported`.
+///
+/// Listings used to fail with a plain `io::Error`, which `ListHidError`
+/// converts into, giving back the underlying OS error, to ease migrating.
+pub fn list_hid_device() -> Result<Vec<HidDevice>, ListHidError> {
+  #[cfg(windows)]
+  {
+    list_hid_device_with_skipped().map(|(devices, _)| devices)
+  }
+  #[cfg(any(target_os = "linux", target_os = "android"))]
+  {
+    Ok(linux::list_hidraw(&linux::Roots::default())?)
+  }
+  #[cfg(target_os = "freebsd")]
+  {
+    Ok(freebsd::list_hid_nodes(std::path::Path::new("/dev"))?)
+  }
+  #[cfg(not(any(
+    windows,
+    target_os = "linux",
+    target_os = "android",
+    target_os = "freebsd"
+  )))]
+  {
+    Err(ListHidError::Unsupported)
+  }
+}
+
+/// Counts the present HID devices without opening any of them, which is much
+/// faster than `list_hid_device()?.len()`.
+///
+/// The count is of HID interface paths, not of physical devices: a composite
+/// device such as a keyboard with media keys usually contributes several. To
+/// count physical devices, list them and group by `HidDevice::container_id`.
+#[cfg(windows)]
+pub fn count_hid_devices() -> Result<usize, ListHidError> {
+  use std::ptr;
+  use win32::setup_di_get_class_devs;
+  use winapi::shared::hidclass::GUID_DEVINTERFACE_HID;
+  use winapi::um::setupapi::{DIGCF_ALLCLASSES, DIGCF_DEVICEINTERFACE, DIGCF_PRESENT};
+
+  let class_devs_info = unsafe {
+    setup_di_get_class_devs(
+      ptr::null(),
+      ptr::null(),
+      ptr::null_mut(),
+      DIGCF_ALLCLASSES | DIGCF_PRESENT | DIGCF_DEVICEINTERFACE,
+    )
+  }?;
+
+  Ok(build_device_data(&class_devs_info, &GUID_DEVINTERFACE_HID)?.len())
+}
+
+/// Async version of `list_hid_device`, for use from a tokio runtime.
+///
+/// The enumeration itself is blocking, so it runs on tokio's blocking thread
+/// pool instead of stalling the calling task's worker thread.
+#[cfg(all(windows, feature = "tokio"))]
+pub async fn list_hid_device_async() -> Result<Vec<HidDevice>, ListHidError> {
+  tokio::task::spawn_blocking(list_hid_device)
+    .await
+    .map_err(std::io::Error::other)?
+}
+
+/// Lists the keyboards, i.e. the Generic Desktop / Keyboard collections.
+///
+/// Virtual devices, such as those created by remote desktop sessions or
+/// software drivers, are included; filter them out if only hardware matters:
+///
+/// ```no_run
+/// let keyboards: Vec<_> = listhid::keyboards()?
+///   .into_iter()
+///   .filter(|device| device.vendor_id != 0)
+///   .collect();
+/// # Ok::<(), listhid::ListHidError>(())
+/// ```
+#[cfg(windows)]
+pub fn keyboards() -> Result<Vec<HidDevice>, ListHidError> {
+  HidQuery::new()
+    .usage(USAGE_PAGE_GENERIC, USAGE_GENERIC_KEYBOARD)
+    .list()
+}
+
+/// Lists the mice, i.e. the Generic Desktop / Mouse collections.
+///
+/// ```no_run
+/// let mice: Vec<_> = listhid::mice()?
+///   .into_iter()
+///   .filter(|device| device.vendor_id != 0)
+///   .collect();
+/// # Ok::<(), listhid::ListHidError>(())
+/// ```
+#[cfg(windows)]
+pub fn mice() -> Result<Vec<HidDevice>, ListHidError> {
+  HidQuery::new()
+    .usage(USAGE_PAGE_GENERIC, USAGE_GENERIC_MOUSE)
+    .list()
+}
+
+/// Lists the gamepads, i.e. the Generic Desktop / Game Pad collections.
+///
+/// ```no_run
+/// let gamepads: Vec<_> = listhid::gamepads()?
+///   .into_iter()
+///   .filter(|device| device.vendor_id != 0)
+///   .collect();
+/// # Ok::<(), listhid::ListHidError>(())
+/// ```
+#[cfg(windows)]
+pub fn gamepads() -> Result<Vec<HidDevice>, ListHidError> {
+  HidQuery::new()
+    .usage(USAGE_PAGE_GENERIC, USAGE_GENERIC_GAMEPAD)
+    .list()
+}
+
+/// Lists the joysticks, i.e. the Generic Desktop / Joystick collections.
+///
+/// ```no_run
+/// let joysticks: Vec<_> = listhid::joysticks()?
+///   .into_iter()
+///   .filter(|device| device.vendor_id != 0)
+///   .collect();
+/// # Ok::<(), listhid::ListHidError>(())
+/// ```
+#[cfg(windows)]
+pub fn joysticks() -> Result<Vec<HidDevice>, ListHidError> {
+  HidQuery::new()
+    .usage(USAGE_PAGE_GENERIC, USAGE_GENERIC_JOYSTICK)
+    .list()
+}
+
+/// Lists the present devices exposing the device interface class `guid`,
+/// using the same machinery as `list_hid_device`.
+///
+/// Interfaces of a class other than HID don't answer the `HidD_*` calls, so
+/// their vendor and product IDs are parsed from the path where possible, and
+/// the HID strings and usage are `None`.
+#[cfg(windows)]
+pub fn list_devices_by_interface(
+  guid: winapi::shared::guiddef::GUID,
+) -> Result<Vec<HidDevice>, ListHidError> {
+  HidQuery::new().interface_class(guid).list()
+}
+
+/// Same as `list_hid_device`, but also returns the devices that were skipped
+/// because opening or querying them failed, together with the error.
+#[cfg(windows)]
+pub fn list_hid_device_with_skipped() -> Result<(Vec<HidDevice>, Vec<SkippedDevice>), ListHidError>
+{
+  HidQuery::new().list_with_skipped()
+}
+
+/// Same as `list_hid_device`, but opens and queries up to `max_concurrency`
+/// devices at a time, each on its own thread, which is faster when some are
+/// slow to answer. 8 balances speed against the handles and threads used.
+///
+/// ```no_run
+/// let devices = listhid::list_hid_device_parallel(8)?;
+/// # Ok::<(), listhid::ListHidError>(())
+/// ```
+#[cfg(windows)]
+pub fn list_hid_device_parallel(max_concurrency: usize) -> Result<Vec<HidDevice>, ListHidError> {
+  HidQuery::new()
+    .options(EnumOptions {
+      max_concurrency,
+      ..EnumOptions::default()
+    })
+    .list()
+}
+
+/// Lists the present HID devices of another computer, `machine`, given by
+/// name, e.g. `PC01` or `\\\\PC01`, from its SetupAPI data.
+///
+/// Remote devices can't be opened, so only what SetupAPI and the registry
+/// know is filled in, as at `DetailLevel::Registry`: the path, IDs, usage,
+/// instance IDs, class, descriptions and power data. The fields read from
+/// the device, i.e. `manufacturer_string`, `serial_number_string`,
+/// `version_number` and `usb_speed`, are `None`, and `product_string` is
+/// the friendly name or description. `dev_inst`, `problem_code` and
+/// `is_disabled` are left empty too, as device nodes only make sense on the
+/// computer they belong to.
+///
+/// Windows dropped remote access to SetupAPI in Windows 8, so this only
+/// works against older remote computers, and fails with
+/// `ListHidError::Enumeration` otherwise. It also needs the Remote Registry
+/// service running on `machine` and administrator rights there.
+#[cfg(windows)]
+pub fn list_hid_device_on_machine(machine: &str) -> Result<Vec<HidDevice>, ListHidError> {
+  use std::ptr;
+  use win32::{
+    setup_di_get_class_devs_ex, setup_di_get_device_instance_id,
+    setup_di_get_device_interface_detail,
+  };
+  use winapi::shared::hidclass::GUID_DEVINTERFACE_HID;
+  use winapi::um::setupapi::{DIGCF_ALLCLASSES, DIGCF_DEVICEINTERFACE, DIGCF_PRESENT};
+
+  let machine = if machine.starts_with("\\\\") {
+    machine.to_owned()
+  } else {
+    format!("\\\\{}", machine)
+  };
+  let class_devs_info = unsafe {
+    setup_di_get_class_devs_ex(
+      ptr::null(),
+      ptr::null(),
+      ptr::null_mut(),
+      DIGCF_ALLCLASSES | DIGCF_PRESENT | DIGCF_DEVICEINTERFACE,
+      Some(&machine),
+    )
+  }?;
+
+  let mut devices = Vec::new();
+  let mut scratch = Vec::new();
+  for mut device_data in build_device_data(&class_devs_info, &GUID_DEVINTERFACE_HID)? {
+    let mut device_interface_detail = unsafe {
+      setup_di_get_device_interface_detail(
+        &class_devs_info,
+        &mut device_data.interface_data,
+        &mut scratch,
+      )
+    }?;
+    let instance_id = setup_di_get_device_instance_id(
+      &class_devs_info,
+      &mut device_interface_detail.device_info_data,
+    )
+    .ok();
+
+    // The device node read for these belongs to the remote computer, so
+    // what the local configuration manager made of it is meaningless.
+    let mut device = read_device_info(
+      &class_devs_info,
+      &device_data,
+      &device_interface_detail,
+      instance_id,
+      DetailLevel::Registry,
+      &mut scratch,
+    );
+    device.windows.dev_inst = None;
+    device.problem_code = None;
+    device.is_disabled = false;
+    device.mirror_deprecated_fields();
+    devices.push(device);
   }
 
+  devices.sort();
   Ok(devices)
 }
+
+/// Looks up a single HID device by its interface path, without enumerating
+/// every HID interface.
+///
+/// The path is matched case-insensitively by SetupAPI. A stale path, e.g. of a
+/// device that has since been removed, yields `Ok(None)`.
+#[cfg(windows)]
+pub fn get_device_by_path(path: &str) -> Result<Option<HidDevice>, ListHidError> {
+  use std::ptr;
+  use win32::{
+    setup_di_create_device_info_list, setup_di_get_device_instance_id,
+    setup_di_get_device_interface_detail, setup_di_open_device_interface,
+  };
+  use winapi::shared::winerror::{
+    ERROR_DEVICE_NOT_CONNECTED, ERROR_FILE_NOT_FOUND, ERROR_NOT_FOUND,
+  };
+  use winapi::um::setupapi::{ERROR_NO_SUCH_DEVICE_INTERFACE, SPINT_ACTIVE};
+
+  let is_stale = |error: &std::io::Error| match error.raw_os_error() {
+    Some(code) => [
+      ERROR_NO_SUCH_DEVICE_INTERFACE,
+      ERROR_NOT_FOUND,
+      ERROR_FILE_NOT_FOUND,
+      ERROR_DEVICE_NOT_CONNECTED,
+    ]
+    .contains(&(code as u32)),
+    None => false,
+  };
+
+  let class_devs_info = unsafe { setup_di_create_device_info_list(ptr::null(), ptr::null_mut()) }?;
+
+  let interface_data = match setup_di_open_device_interface(&class_devs_info, path, 0) {
+    Ok(interface_data) => interface_data,
+    Err(ref error) if is_stale(error) => return Ok(None),
+    Err(error) => return Err(error.into()),
+  };
+  if interface_data.Flags & SPINT_ACTIVE == 0 {
+    return Ok(None);
+  }
+
+  let mut device_data = DeviceData {
+    interface_data,
+    info_data: None,
+  };
+  let mut scratch = Vec::new();
+  let mut device_interface_detail = unsafe {
+    setup_di_get_device_interface_detail(
+      &class_devs_info,
+      &mut device_data.interface_data,
+      &mut scratch,
+    )
+  }?;
+  device_data.info_data = Some(device_interface_detail.device_info_data);
+  let instance_id = setup_di_get_device_instance_id(
+    &class_devs_info,
+    &mut device_interface_detail.device_info_data,
+  )
+  .ok();
+
+  match read_hid_device(
+    &class_devs_info,
+    &device_data,
+    &device_interface_detail,
+    instance_id,
+    true,
+    DetailLevel::Full,
+    &mut scratch,
+  ) {
+    Ok(device) => Ok(Some(device)),
+    Err(ListHidError::DeviceOpen { ref source, .. })
+    | Err(ListHidError::HidApi { ref source, .. })
+      if is_stale(source) =>
+    {
+      Ok(None)
+    }
+    Err(error) => Err(error),
+  }
+}
+
+/// Opens the first HID device with the given vendor and product IDs for
+/// reading and writing. For composite devices, which have one interface per
+/// top-level collection, `usage_page` picks the collection.
+///
+/// The devices are matched without being opened, and only the first match
+/// is opened. Fails with `Error::NotFound` if nothing matches.
+///
+/// ```no_run
+/// let mouse = listhid::open_by_ids(0x046d, 0xc077, Some(0x01))?;
+/// # Ok::<(), listhid::Error>(())
+/// ```
+#[cfg(windows)]
+pub fn open_by_ids(
+  vendor_id: u16,
+  product_id: u16,
+  usage_page: Option<u16>,
+) -> Result<OpenHidDevice, Error> {
+  let device = HidQuery::new()
+    .detail_level(DetailLevel::Ids)
+    .list()?
+    .into_iter()
+    .find(|device| {
+      device.vendor_id == vendor_id
+        && device.product_id == product_id
+        && usage_page.is_none_or(|usage_page| device.usage_page == Some(usage_page))
+    })
+    .ok_or(Error::NotFound {
+      vendor_id,
+      product_id,
+    })?;
+
+  device.open()
+}
+
+/// Reads a single HID device straight from its interface path, without
+/// SetupAPI. Cheaper than `get_device_by_path`, e.g. to act on a hotplug
+/// notification.
+///
+/// Only what the device itself reports is filled in: the IDs, strings and
+/// top-level usage. The SetupAPI fields, such as `pdo_name`, `container_id`
+/// or `instance_id`, are `None`. Fails with `ListHidError::DeviceOpen` if
+/// the path cannot be opened.
+#[cfg(windows)]
+pub fn get_hid_device(path: &str) -> Result<HidDevice, ListHidError> {
+  use win32::{
+    hid_d_get_attributes, hid_d_get_manufacturer_string, hid_d_get_preparsed_data,
+    hid_d_get_product_string, hid_d_get_serial_number_string, hid_p_get_caps,
+  };
+
+  let handle = open_device_for_query(path).map_err(|source| ListHidError::DeviceOpen {
+    path: path.to_owned(),
+    source,
+  })?;
+
+  let mut device = HidDevice {
+    path: path.to_owned(),
+    ..Default::default()
+  };
+  let hidd_attributes = hid_d_get_attributes(&handle);
+  let attributes = match &hidd_attributes {
+    Ok(hidd_attributes) => {
+      device.version_number = Some(hidd_attributes.VersionNumber);
+      Some((hidd_attributes.VendorID, hidd_attributes.ProductID))
+    }
+    Err(_) => None,
+  };
+  let ids = resolve_ids(attributes, parse_ids_from_path(path));
+  if let (None, Err(source)) = (ids, hidd_attributes) {
+    return Err(ListHidError::HidApi {
+      path: path.to_owned(),
+      call: "HidD_GetAttributes",
+      source,
+    });
+  }
+  if let Some((vendor_id, product_id)) = ids {
+    device.vendor_id = vendor_id;
+    device.product_id = product_id;
+  }
+  device.manufacturer_string = hid_d_get_manufacturer_string(&handle);
+  device.product_string = hid_d_get_product_string(&handle);
+  device.serial_number_string = hid_d_get_serial_number_string(&handle);
+
+  if let Ok(caps) = hid_d_get_preparsed_data(&handle).and_then(|data| hid_p_get_caps(&data)) {
+    device.usage_page = Some(caps.UsagePage);
+    device.usage = Some(caps.Usage);
+  }
+
+  Ok(device)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const HID_PATH: &str =
+    r"\\?\HID#VID_046D&PID_C534&MI_01#7&1A2B3C&0&0000#{4D1E55B2-F16F-11CF-88CB-001111000030}";
+
+  fn device(vendor_id: u16, product_id: u16, path: &str) -> HidDevice {
+    HidDevice {
+      path: path.to_owned(),
+      vendor_id,
+      product_id,
+      ..Default::default()
+    }
+  }
+
+  #[test]
+  fn normalizing_folds_case() {
+    let normalized =
+      r"\\?\hid#vid_046d&pid_c534&mi_01#7&1a2b3c&0&0000#{4d1e55b2-f16f-11cf-88cb-001111000030}";
+    assert_eq!(normalize_device_path(HID_PATH), normalized);
+    assert_eq!(
+      normalize_device_path(&HID_PATH.to_ascii_lowercase()),
+      normalized
+    );
+  }
+
+  #[test]
+  fn normalizing_restores_class_guid_braces() {
+    let without_braces = HID_PATH.replace(['{', '}'], "");
+    assert_eq!(
+      normalize_device_path(&without_braces),
+      normalize_device_path(HID_PATH)
+    );
+    // Only a GUID gets braces.
+    assert_eq!(
+      normalize_device_path(r"\\?\X#Y#not-a-guid"),
+      r"\\?\x#y#not-a-guid"
+    );
+    assert_eq!(normalize_device_path("/dev/hidraw0"), "/dev/hidraw0");
+  }
+
+  #[test]
+  fn devices_compare_by_normalized_path() {
+    let reported = device(0x046d, 0xc534, HID_PATH);
+    let lowercased = device(0x046d, 0xc534, &HID_PATH.to_ascii_lowercase());
+    let unbraced = device(0x046d, 0xc534, &HID_PATH.replace(['{', '}'], ""));
+    assert_eq!(reported, lowercased);
+    assert_eq!(reported, unbraced);
+    assert_eq!(reported.cmp(&lowercased), std::cmp::Ordering::Equal);
+
+    let other = device(0x046d, 0xc534, &HID_PATH.replace("0000#", "0001#"));
+    assert_ne!(reported, other);
+    assert!(reported < other);
+  }
+
+  #[test]
+  fn sorting_is_stable_whatever_the_input_order() {
+    let sorted = vec![
+      device(0x045e, 0x0800, r"\\?\hid#vid_045e&pid_0800#1"),
+      device(0x046d, 0xc534, r"\\?\hid#vid_046d&pid_c534#b"),
+      device(0x046d, 0xc534, r"\\?\hid#vid_046d&pid_c534&mi_00#a"),
+      device(0x046d, 0xc534, r"\\?\hid#vid_046d&pid_c534&mi_01&col01#a"),
+      device(0x046d, 0xc534, r"\\?\hid#vid_046d&pid_c534&mi_01&col02#a"),
+      device(0x046d, 0xc539, r"\\?\hid#vid_046d&pid_c539#a"),
+      device(0x1532, 0x0084, r"\\?\hid#vid_1532&pid_0084#a"),
+    ];
+
+    // A fixed xorshift, so the shuffles are the same on every run.
+    let mut state = 0x2545_f491_u32;
+    for _ in 0..20 {
+      let mut shuffled = sorted.clone();
+      for i in (1..shuffled.len()).rev() {
+        state ^= state << 13;
+        state ^= state >> 17;
+        state ^= state << 5;
+        shuffled.swap(i, state as usize % (i + 1));
+      }
+      shuffled.sort();
+      let paths =
+        |devices: &[HidDevice]| devices.iter().map(|d| d.path.clone()).collect::<Vec<_>>();
+      assert_eq!(paths(&shuffled), paths(&sorted));
+    }
+  }
+
+  #[cfg(windows)]
+  #[test]
+  fn formats_guids() {
+    use winapi::shared::hidclass::GUID_DEVINTERFACE_HID;
+
+    assert_eq!(
+      guid_to_string(&GUID_DEVINTERFACE_HID),
+      "{4D1E55B2-F16F-11CF-88CB-001111000030}"
+    );
+    // The same GUID as laid out in memory, e.g. in a DEVPROP_TYPE_GUID value.
+    let bytes = [
+      0xb2, 0x55, 0x1e, 0x4d, 0x6f, 0xf1, 0xcf, 0x11, 0x88, 0xcb, 0x00, 0x11, 0x11, 0x00, 0x00,
+      0x30,
+    ];
+    assert_eq!(
+      format_guid(&bytes),
+      "{4D1E55B2-F16F-11CF-88CB-001111000030}"
+    );
+  }
+
+  #[cfg(windows)]
+  #[test]
+  fn recognizes_redirected_parents() {
+    // Parents of devices redirected over Remote Desktop and RemoteFX USB.
+    assert!(is_redirected_parent(
+      r"TERMINPUT_BUS\UMB\2&1B8C1F23&0&TERMINPUT_DEVICE"
+    ));
+    assert!(is_redirected_parent(
+      r"TS_USB_HUB_ENUMERATOR\UMB\2&29B7F1A&0&TSUSBHUB"
+    ));
+    assert!(is_redirected_parent(
+      r"terminput_bus\umb\2&1b8c1f23&0&terminput_device"
+    ));
+    // Parents of local devices.
+    assert!(!is_redirected_parent(
+      r"USB\VID_046D&PID_C534\5&2A7B3F4C&0&2"
+    ));
+    assert!(!is_redirected_parent(r"ACPI\PNP0303\4&1D401FB5&0"));
+    assert!(!is_redirected_parent(r"ROOT\TERMINPUT_BUS\0000"));
+  }
+
+  #[cfg(feature = "serde")]
+  #[test]
+  fn round_trips_through_json() {
+    let mut original = device(0x046d, 0xc534, HID_PATH);
+    original.product_string = Some("Clavier sans fil \u{2328} \u{30ad}\u{30fc}".to_owned());
+    original.usage_page = Some(0x01);
+    original.usage = Some(0x06);
+    original.detail_level = DetailLevel::Full;
+
+    let json = serde_json::to_value(&original).unwrap();
+    assert_eq!(json["manufacturer_string"], serde_json::Value::Null);
+    assert_eq!(json["serial_number_string"], serde_json::Value::Null);
+    let read: HidDevice = serde_json::from_value(json.clone()).unwrap();
+    assert_eq!(read.path, original.path);
+    assert_eq!((read.vendor_id, read.product_id), (0x046d, 0xc534));
+    assert_eq!(read.product_string, original.product_string);
+    assert_eq!(read.manufacturer_string, None);
+    assert_eq!(read.usage_page, Some(0x01));
+    assert_eq!(read.detail_level, DetailLevel::Full);
+    assert_eq!(serde_json::to_value(&read).unwrap(), json);
+  }
+
+  #[cfg(feature = "serde")]
+  #[test]
+  fn reads_snapshots_with_missing_fields() {
+    let read: HidDevice =
+      serde_json::from_str(r#"{"path":"/dev/hidraw0","vendor_id":"046D","product_id":50484}"#)
+        .unwrap();
+    assert_eq!(read.path, "/dev/hidraw0");
+    assert_eq!((read.vendor_id, read.product_id), (0x046d, 0xc534));
+    assert_eq!(read.product_string, None);
+    assert!(!read.is_redirected);
+  }
+
+  #[cfg(windows)]
+  #[test]
+  fn zero_attributes_fall_back_to_the_path_ids() {
+    assert_eq!(
+      resolve_ids(Some((0, 0)), Some((0x046d, 0xc534))),
+      Some((0x046d, 0xc534))
+    );
+    assert_eq!(resolve_ids(Some((0, 0)), None), Some((0, 0)));
+  }
+
+  #[cfg(windows)]
+  #[test]
+  fn nonzero_attributes_win_over_the_fallback() {
+    assert_eq!(
+      resolve_ids(Some((0x046d, 0)), Some((0x1234, 0x5678))),
+      Some((0x046d, 0))
+    );
+    assert_eq!(
+      resolve_ids(Some((0x046d, 0xc534)), None),
+      Some((0x046d, 0xc534))
+    );
+  }
+
+  #[cfg(windows)]
+  #[test]
+  fn unreadable_attributes_fall_back_or_fail() {
+    assert_eq!(
+      resolve_ids(None, Some((0x046d, 0xc534))),
+      Some((0x046d, 0xc534))
+    );
+    assert_eq!(resolve_ids(None, None), None);
+  }
+
+  fn on_parent(parent_instance_id: Option<&str>) -> HidDevice {
+    HidDevice {
+      parent_instance_id: parent_instance_id.map(str::to_owned),
+      ..device(0, 0, HID_PATH)
+    }
+  }
+
+  #[test]
+  fn devices_on_usb_or_bluetooth_are_not_virtual() {
+    assert!(!on_parent(Some(r"USB\VID_046D&PID_C534\5&1A2B3C&0&1")).is_virtual_heuristic());
+    assert!(!on_parent(Some(r"usb\vid_046d&pid_c534\5&1a2b3c&0&1")).is_virtual_heuristic());
+    assert!(!on_parent(Some(
+      r"BTHENUM\{00001124-0000-1000-8000-00805F9B34FB}_VID&0002046D"
+    ))
+    .is_virtual_heuristic());
+    assert!(
+      !on_parent(Some(r"BTHLEDEVICE\{00001812-0000-1000-8000-00805F9B34FB}"))
+        .is_virtual_heuristic()
+    );
+  }
+
+  #[test]
+  fn zero_id_devices_elsewhere_are_virtual() {
+    assert!(on_parent(Some(r"ROOT\SYSTEM\0001")).is_virtual_heuristic());
+    assert!(on_parent(Some(r"SWD\MMDEVAPI\0001")).is_virtual_heuristic());
+    assert!(on_parent(None).is_virtual_heuristic());
+  }
+
+  #[test]
+  fn devices_with_ids_are_not_virtual() {
+    let on_root = HidDevice {
+      parent_instance_id: Some(r"ROOT\SYSTEM\0001".to_owned()),
+      ..device(0x046d, 0, HID_PATH)
+    };
+    assert!(!on_root.is_virtual_heuristic());
+    assert!(!HidDevice {
+      parent_instance_id: None,
+      ..device(0, 0xc534, HID_PATH)
+    }
+    .is_virtual_heuristic());
+  }
+
+  fn with_usage(usage_page: u16, usage: Option<u16>) -> HidDevice {
+    HidDevice {
+      usage_page: Some(usage_page),
+      usage,
+      ..device(0x046d, 0xc534, HID_PATH)
+    }
+  }
+
+  #[test]
+  fn generic_desktop_usages_identify_the_device_kind() {
+    let keyboard = with_usage(0x01, Some(0x06));
+    assert!(keyboard.is_keyboard() && !keyboard.is_mouse() && !keyboard.is_gamepad());
+    let mouse = with_usage(0x01, Some(0x02));
+    assert!(!mouse.is_keyboard() && mouse.is_mouse() && !mouse.is_gamepad());
+    let gamepad = with_usage(0x01, Some(0x05));
+    assert!(!gamepad.is_keyboard() && !gamepad.is_mouse() && gamepad.is_gamepad());
+  }
+
+  #[test]
+  fn usages_on_other_pages_are_no_device_kind() {
+    // Consumer Control and Telephony reuse the Generic Desktop usage numbers.
+    for usage_page in [0x0c, 0x06] {
+      for usage in [0x02, 0x05, 0x06] {
+        let device = with_usage(usage_page, Some(usage));
+        assert!(!device.is_keyboard() && !device.is_mouse() && !device.is_gamepad());
+      }
+    }
+  }
+
+  #[test]
+  fn unread_usages_are_no_device_kind() {
+    // As listed with `DetailLevel::Ids`.
+    let ids_only = HidDevice {
+      detail_level: DetailLevel::Ids,
+      ..with_usage(0x01, None)
+    };
+    assert!(!ids_only.is_keyboard() && !ids_only.is_mouse() && !ids_only.is_gamepad());
+    assert!(!device(0x046d, 0xc534, HID_PATH).is_keyboard());
+  }
+
+  #[cfg(windows)]
+  #[test]
+  fn empty_fallback_enumeration_keeps_the_original_error() {
+    use winapi::shared::winerror::ERROR_INVALID_DATA;
+
+    let error = std::io::Error::from_raw_os_error(ERROR_INVALID_DATA as i32);
+    let result = or_original_error(Vec::<u32>::new(), error);
+    assert_eq!(
+      result.unwrap_err().raw_os_error(),
+      Some(ERROR_INVALID_DATA as i32)
+    );
+
+    let error = std::io::Error::from_raw_os_error(ERROR_INVALID_DATA as i32);
+    assert_eq!(or_original_error(vec![1, 2], error).unwrap(), [1, 2]);
+  }
+
+  #[test]
+  fn not_found_stays_not_found_as_an_io_error() {
+    let error = Error::NotFound {
+      vendor_id: 0x046d,
+      product_id: 0xc077,
+    };
+    assert_eq!(error.to_string(), "no HID device 046d:c077");
+    assert_eq!(
+      std::io::Error::from(error).kind(),
+      std::io::ErrorKind::NotFound
+    );
+  }
+
+  #[cfg(windows)]
+  fn with_windows_data() -> HidDevice {
+    let mut device = device(0x046d, 0xc534, HID_PATH);
+    device.windows.dev_inst = Some(42);
+    device.windows.pdo_name = Some(r"\Device\00000042".to_owned());
+    device.windows.instance_id = Some(r"HID\VID_046D&PID_C534&MI_01\7&1A2B3C&0&0000".to_owned());
+    device
+  }
+
+  #[cfg(windows)]
+  #[test]
+  #[allow(deprecated)]
+  fn deprecated_fields_mirror_the_windows_data() {
+    use os::windows::HidDeviceExt;
+
+    let mut device = with_windows_data();
+    assert_eq!(device.pdo_name, None);
+    device.mirror_deprecated_fields();
+    assert_eq!(device.dev_inst(), Some(42));
+    assert_eq!(device.dev_inst, Some(42));
+    assert_eq!(device.pdo_name(), Some(r"\Device\00000042"));
+    assert_eq!(device.pdo_name.as_deref(), device.pdo_name());
+    assert_eq!(device.instance_id.as_deref(), device.instance_id());
+  }
+
+  #[cfg(all(windows, feature = "serde"))]
+  #[test]
+  fn windows_data_keeps_its_serialized_names() {
+    use os::windows::HidDeviceExt;
+
+    let json = serde_json::to_value(with_windows_data()).unwrap();
+    assert_eq!(json["dev_inst"], 42);
+    assert_eq!(json["pdo_name"], r"\Device\00000042");
+    let read: HidDevice = serde_json::from_value(json).unwrap();
+    assert_eq!(read.dev_inst(), Some(42));
+    assert_eq!(read.instance_id(), with_windows_data().instance_id());
+  }
+}
@@ -0,0 +1,445 @@
+#[cfg(feature = "udev")]
+mod udev;
+
+use crate::{DetailLevel, HidDevice};
+use std::path::{Path, PathBuf};
+use std::{fs, io};
+
+/// Where the hidraw nodes are read from, so a fixture tree can stand in for
+/// the real one.
+pub(crate) struct Roots {
+  /// `/sys/class/hidraw`.
+  pub(crate) class_dir: PathBuf,
+  /// `/dev`, where the nodes' paths point.
+  pub(crate) dev_dir: PathBuf,
+  /// `/proc/bus/input/devices`.
+  pub(crate) input_devices: PathBuf,
+  /// `/run/udev/data`.
+  #[cfg(feature = "udev")]
+  pub(crate) udev_data_dir: PathBuf,
+}
+
+impl Default for Roots {
+  fn default() -> Self {
+    Roots {
+      class_dir: PathBuf::from("/sys/class/hidraw"),
+      dev_dir: PathBuf::from("/dev"),
+      input_devices: PathBuf::from("/proc/bus/input/devices"),
+      #[cfg(feature = "udev")]
+      udev_data_dir: PathBuf::from("/run/udev/data"),
+    }
+  }
+}
+
+/// The fields of a hidraw node's parent HID device, from its `uevent` file.
+#[derive(Debug, Default)]
+struct HidUevent {
+  vendor_id: u16,
+  product_id: u16,
+  name: Option<String>,
+  uniq: Option<String>,
+}
+
+/// Parses the `KEY=value` lines of a HID device's `uevent`, e.g.
+/// `HID_ID=0003:0000046D:0000C534`, `HID_NAME=Logitech USB Receiver` and
+/// `HID_UNIQ=`. `None` without a well-formed `HID_ID`.
+fn parse_uevent(uevent: &str) -> Option<HidUevent> {
+  let mut parsed = HidUevent::default();
+  let mut has_id = false;
+
+  for line in uevent.lines() {
+    let (key, value) = match line.split_once('=') {
+      Some(pair) => pair,
+      None => continue,
+    };
+    let non_empty = || Some(value.to_owned()).filter(|value| !value.is_empty());
+    match key {
+      "HID_ID" => {
+        // The bus type, then the IDs, which are 32-bit in the kernel but
+        // only ever USB-sized.
+        let mut parts = value.split(':').map(|part| u32::from_str_radix(part, 16));
+        match (parts.next(), parts.next(), parts.next(), parts.next()) {
+          (Some(Ok(_)), Some(Ok(vendor_id)), Some(Ok(product_id)), None) => {
+            parsed.vendor_id = vendor_id as u16;
+            parsed.product_id = product_id as u16;
+            has_id = true;
+          }
+          _ => return None,
+        }
+      }
+      "HID_NAME" => parsed.name = non_empty(),
+      "HID_UNIQ" => parsed.uniq = non_empty(),
+      _ => {}
+    }
+  }
+
+  Some(parsed).filter(|_| has_id)
+}
+
+/// An input device from `/proc/bus/input/devices`.
+struct InputDevice {
+  name: Option<String>,
+  /// Its sysfs path, which runs through the HID device's directory.
+  sysfs: String,
+}
+
+/// Parses the blocks of `/proc/bus/input/devices`, keeping their `N: Name=`
+/// and `S: Sysfs=` lines.
+fn parse_input_devices(devices: &str) -> Vec<InputDevice> {
+  devices
+    .split("\n\n")
+    .filter_map(|block| {
+      let mut name = None;
+      let mut sysfs = None;
+      for line in block.lines() {
+        if let Some(value) = line.strip_prefix("N: Name=") {
+          name = Some(value.trim_matches('"').to_owned()).filter(|name| !name.is_empty());
+        } else if let Some(value) = line.strip_prefix("S: Sysfs=") {
+          sysfs = Some(value.to_owned());
+        }
+      }
+      Some(InputDevice {
+        name,
+        sysfs: sysfs?,
+      })
+    })
+    .collect()
+}
+
+/// Parses the IDs from the name of a HID device's sysfs directory, e.g.
+/// `0003:046D:C534.0001` for bus, vendor ID, product ID and instance.
+fn parse_hid_dir_name(name: &str) -> Option<(u16, u16)> {
+  let mut parts = name.split([':', '.']);
+  let _bus = parts.next()?;
+  let vendor_id = u16::from_str_radix(parts.next()?, 16).ok()?;
+  let product_id = u16::from_str_radix(parts.next()?, 16).ok()?;
+  Some((vendor_id, product_id))
+}
+
+/// Fills in `device`, whose `uevent` can't be read, as far as the name of
+/// its HID device's directory, which the `device` link in `node_dir` points
+/// to, and `input_devices` tell: the IDs and, for devices that are also
+/// input devices, the name. `false` if even the link can't be read.
+fn read_hidraw_without_uevent(
+  device: &mut HidDevice,
+  node_dir: &Path,
+  input_devices: &[InputDevice],
+) -> bool {
+  let hid_dir = match fs::read_link(node_dir.join("device")) {
+    Ok(link) => link,
+    Err(_) => return false,
+  };
+  let hid_dir_name = match hid_dir.file_name().and_then(|name| name.to_str()) {
+    Some(name) => name,
+    None => return false,
+  };
+  let (vendor_id, product_id) = match parse_hid_dir_name(hid_dir_name) {
+    Some(ids) => ids,
+    None => return false,
+  };
+
+  device.vendor_id = vendor_id;
+  device.product_id = product_id;
+  let component = format!("/{}/", hid_dir_name);
+  device.product_string = input_devices
+    .iter()
+    .find(|input_device| input_device.sysfs.contains(&component))
+    .and_then(|input_device| input_device.name.clone());
+  device.detail_level = DetailLevel::Ids;
+  true
+}
+
+/// Reads the hidraw node `name`, e.g. `hidraw0`. `input_devices` is read
+/// from `/proc/bus/input/devices` the first time a node's `uevent` can't be.
+fn read_hidraw(
+  roots: &Roots,
+  name: &str,
+  input_devices: &mut Option<Vec<InputDevice>>,
+) -> Result<HidDevice, io::Error> {
+  let node_dir = roots.class_dir.join(name);
+  let mut device = HidDevice {
+    path: roots.dev_dir.join(name).to_string_lossy().into_owned(),
+    detail_level: DetailLevel::PathsOnly,
+    ..HidDevice::default()
+  };
+
+  let uevent = match fs::read_to_string(node_dir.join("device").join("uevent")) {
+    Ok(uevent) => uevent,
+    Err(ref error) if error.kind() == io::ErrorKind::PermissionDenied => {
+      let input_devices = input_devices.get_or_insert_with(|| {
+        fs::read_to_string(&roots.input_devices)
+          .map(|devices| parse_input_devices(&devices))
+          .unwrap_or_default()
+      });
+      if !read_hidraw_without_uevent(&mut device, &node_dir, input_devices) {
+        log::debug!(
+          "listing {} by path only, its sysfs entry is not readable",
+          name
+        );
+      }
+      return Ok(device);
+    }
+    Err(error) => return Err(error),
+  };
+  let uevent = parse_uevent(&uevent).ok_or_else(|| {
+    io::Error::new(
+      io::ErrorKind::InvalidData,
+      format!("{} has no valid HID_ID", name),
+    )
+  })?;
+
+  device.vendor_id = uevent.vendor_id;
+  device.product_id = uevent.product_id;
+  device.product_string = uevent.name;
+  device.serial_number_string = uevent.uniq;
+  device.detail_level = DetailLevel::Ids;
+  #[cfg(feature = "udev")]
+  let device = udev::fill_from_udev(device, &node_dir, &roots.udev_data_dir);
+  Ok(device)
+}
+
+/// The names of the hidraw nodes in `dir`.
+fn hidraw_names(dir: &Path) -> Result<Vec<String>, io::Error> {
+  let mut names = Vec::new();
+  for entry in fs::read_dir(dir)? {
+    let name = entry?.file_name().to_string_lossy().into_owned();
+    if name.starts_with("hidraw") {
+      names.push(name);
+    }
+  }
+  Ok(names)
+}
+
+/// Lists the hidraw nodes under `roots`. A missing class directory, i.e. no
+/// hidraw driver, means no devices, and nodes that disappear while being
+/// read, i.e. are unplugged, are left out.
+///
+/// What can't be read for lack of permissions, as for apps on Android, is
+/// left out rather than failing the listing: without access to the class
+/// directory, the nodes are found in the device directory instead, and
+/// nodes whose `uevent` can't be read are filled in from the name of their
+/// HID device and `/proc/bus/input/devices`, or else listed with their path
+/// only, i.e. at `DetailLevel::PathsOnly`.
+pub(crate) fn list_hidraw(roots: &Roots) -> Result<Vec<HidDevice>, io::Error> {
+  let names = match hidraw_names(&roots.class_dir) {
+    Ok(names) => names,
+    Err(ref error) if error.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+    Err(ref error) if error.kind() == io::ErrorKind::PermissionDenied => {
+      match hidraw_names(&roots.dev_dir) {
+        Ok(names) => names,
+        Err(ref error) if error.kind() == io::ErrorKind::PermissionDenied => return Ok(Vec::new()),
+        Err(error) => return Err(error),
+      }
+    }
+    Err(error) => return Err(error),
+  };
+
+  let mut devices = Vec::new();
+  let mut input_devices = None;
+  for name in names {
+    match read_hidraw(roots, &name, &mut input_devices) {
+      Ok(device) => devices.push(device),
+      Err(ref error) if error.kind() == io::ErrorKind::NotFound => {
+        log::debug!("skipping {}, it was removed", name);
+      }
+      Err(error) => return Err(error),
+    }
+  }
+
+  devices.sort();
+  Ok(devices)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::sync::atomic::{AtomicUsize, Ordering};
+
+  /// A directory under the system's temporary one, removed on drop.
+  struct Fixture {
+    dir: PathBuf,
+  }
+
+  impl Fixture {
+    fn new() -> Self {
+      static NEXT: AtomicUsize = AtomicUsize::new(0);
+      let dir = std::env::temp_dir().join(format!(
+        "listhid-linux-{}-{}",
+        std::process::id(),
+        NEXT.fetch_add(1, Ordering::Relaxed)
+      ));
+      fs::create_dir_all(&dir).unwrap();
+      Fixture { dir }
+    }
+
+    fn roots(&self) -> Roots {
+      Roots {
+        class_dir: self.dir.join("sys/class/hidraw"),
+        dev_dir: self.dir.join("dev"),
+        input_devices: self.dir.join("proc/bus/input/devices"),
+        #[cfg(feature = "udev")]
+        udev_data_dir: self.dir.join("run/udev/data"),
+      }
+    }
+
+    /// Adds the hidraw node `name` with the given `uevent` contents, or
+    /// without any.
+    fn add_node(&self, name: &str, uevent: Option<&str>) {
+      let device_dir = self.roots().class_dir.join(name).join("device");
+      fs::create_dir_all(&device_dir).unwrap();
+      if let Some(uevent) = uevent {
+        fs::write(device_dir.join("uevent"), uevent).unwrap();
+      }
+    }
+  }
+
+  impl Drop for Fixture {
+    fn drop(&mut self) {
+      let _ = fs::remove_dir_all(&self.dir);
+    }
+  }
+
+  const UEVENT: &str = "DRIVER=hid-generic\n\
+    HID_ID=0003:0000046D:0000C077\n\
+    HID_NAME=Logitech USB Optical Mouse\n\
+    HID_PHYS=usb-0000:00:14.0-2/input0\n\
+    HID_UNIQ=\n\
+    MODALIAS=hid:b0003g0001v0000046Dp0000C077\n";
+
+  #[test]
+  fn parses_uevent() {
+    let uevent = parse_uevent(UEVENT).unwrap();
+    assert_eq!(uevent.vendor_id, 0x046d);
+    assert_eq!(uevent.product_id, 0xc077);
+    assert_eq!(uevent.name.as_deref(), Some("Logitech USB Optical Mouse"));
+    assert_eq!(uevent.uniq, None);
+  }
+
+  #[test]
+  fn rejects_uevent_without_valid_hid_id() {
+    assert!(parse_uevent("HID_NAME=Mouse\n").is_none());
+    assert!(parse_uevent("HID_ID=0003:0000046D\n").is_none());
+    assert!(parse_uevent("HID_ID=0003:0000046D:0000C077:0001\n").is_none());
+    assert!(parse_uevent("HID_ID=0003:XYZ:0000C077\n").is_none());
+  }
+
+  const INPUT_DEVICES: &str = "I: Bus=0003 Vendor=046d Product=c077 Version=0111\n\
+    N: Name=\"Logitech USB Optical Mouse\"\n\
+    P: Phys=usb-0000:00:14.0-2/input0\n\
+    S: Sysfs=/devices/pci0000:00/0000:00:14.0/usb1/1-2/1-2:1.0/0003:046D:C077.0001/input/input5\n\
+    U: Uniq=\n\
+    H: Handlers=mouse0 event5\n\
+    \n\
+    I: Bus=0019 Vendor=0000 Product=0001 Version=0000\n\
+    N: Name=\"\"\n\
+    S: Sysfs=/devices/LNXSYSTM:00/LNXPWRBN:00/input/input0\n\
+    \n\
+    I: Bus=0000 Vendor=0000 Product=0000 Version=0000\n\
+    N: Name=\"No sysfs\"\n";
+
+  #[test]
+  fn parses_input_devices() {
+    let devices = parse_input_devices(INPUT_DEVICES);
+    assert_eq!(devices.len(), 2);
+    assert_eq!(
+      devices[0].name.as_deref(),
+      Some("Logitech USB Optical Mouse")
+    );
+    assert!(devices[0].sysfs.contains("/0003:046D:C077.0001/"));
+    assert_eq!(devices[1].name, None);
+  }
+
+  #[test]
+  fn parses_hid_dir_name() {
+    assert_eq!(
+      parse_hid_dir_name("0003:046D:C077.0001"),
+      Some((0x046d, 0xc077))
+    );
+    assert_eq!(
+      parse_hid_dir_name("0005:045E:02E0.0003"),
+      Some((0x045e, 0x02e0))
+    );
+    assert_eq!(parse_hid_dir_name("0003:046D"), None);
+    assert_eq!(parse_hid_dir_name("hidraw0"), None);
+  }
+
+  #[test]
+  fn lists_well_formed_node() {
+    let fixture = Fixture::new();
+    fixture.add_node("hidraw0", Some(UEVENT));
+    let roots = fixture.roots();
+
+    let devices = list_hidraw(&roots).unwrap();
+    assert_eq!(devices.len(), 1);
+    let device = &devices[0];
+    assert_eq!(device.path, roots.dev_dir.join("hidraw0").to_string_lossy());
+    assert_eq!((device.vendor_id, device.product_id), (0x046d, 0xc077));
+    assert_eq!(
+      device.product_string.as_deref(),
+      Some("Logitech USB Optical Mouse")
+    );
+    assert_eq!(device.serial_number_string, None);
+    assert_eq!(device.detail_level, DetailLevel::Ids);
+  }
+
+  #[test]
+  fn fails_on_malformed_hid_id() {
+    let fixture = Fixture::new();
+    fixture.add_node("hidraw0", Some("HID_ID=garbage\n"));
+
+    let error = list_hidraw(&fixture.roots()).unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+  }
+
+  #[test]
+  fn missing_class_dir_means_no_devices() {
+    let fixture = Fixture::new();
+    assert!(list_hidraw(&fixture.roots()).unwrap().is_empty());
+  }
+
+  #[test]
+  fn skips_node_removed_while_listing() {
+    let fixture = Fixture::new();
+    fixture.add_node("hidraw0", Some(UEVENT));
+    // Listed in the class directory, but its uevent is already gone.
+    fixture.add_node("hidraw1", None);
+
+    let devices = list_hidraw(&fixture.roots()).unwrap();
+    assert_eq!(devices.len(), 1);
+    assert!(devices[0].path.ends_with("hidraw0"));
+  }
+
+  #[test]
+  fn fills_in_unreadable_uevent_from_input_devices() {
+    let fixture = Fixture::new();
+    let node_dir = fixture.dir.join("hidraw0");
+    fs::create_dir_all(&node_dir).unwrap();
+    std::os::unix::fs::symlink(
+      "../../devices/pci0000:00/0000:00:14.0/usb1/1-2/1-2:1.0/0003:046D:C077.0001",
+      node_dir.join("device"),
+    )
+    .unwrap();
+    let input_devices = parse_input_devices(INPUT_DEVICES);
+
+    let mut device = HidDevice::default();
+    assert!(read_hidraw_without_uevent(
+      &mut device,
+      &node_dir,
+      &input_devices
+    ));
+    assert_eq!((device.vendor_id, device.product_id), (0x046d, 0xc077));
+    assert_eq!(
+      device.product_string.as_deref(),
+      Some("Logitech USB Optical Mouse")
+    );
+    assert_eq!(device.detail_level, DetailLevel::Ids);
+  }
+
+  #[test]
+  fn unreadable_node_without_device_link_is_path_only() {
+    let fixture = Fixture::new();
+    let mut device = HidDevice::default();
+    assert!(!read_hidraw_without_uevent(&mut device, &fixture.dir, &[]));
+    assert_eq!((device.vendor_id, device.product_id), (0, 0));
+  }
+}
@@ -0,0 +1,85 @@
+use crate::HidDevice;
+use std::collections::HashMap;
+use std::path::Path;
+use std::{fs, io};
+
+/// Decodes the `\xHH` escapes udev uses in its `_ENC` properties, e.g.
+/// `USB\x20Receiver`.
+fn decode_enc(value: &str) -> String {
+  let bytes = value.as_bytes();
+  let mut decoded = Vec::with_capacity(bytes.len());
+  let mut i = 0;
+  while i < bytes.len() {
+    let escaped = match bytes.get(i..i + 4) {
+      Some([b'\\', b'x', high, low]) => std::str::from_utf8(&[*high, *low])
+        .ok()
+        .and_then(|hex| u8::from_str_radix(hex, 16).ok()),
+      _ => None,
+    };
+    match escaped {
+      Some(byte) => {
+        decoded.push(byte);
+        i += 4;
+      }
+      None => {
+        decoded.push(bytes[i]);
+        i += 1;
+      }
+    }
+  }
+  String::from_utf8_lossy(&decoded).trim().to_owned()
+}
+
+/// Reads the properties udev recorded for the device node `dev`, e.g.
+/// `243:0`, from the `E:KEY=value` lines of its entry in `data_dir`,
+/// normally `/run/udev/data`.
+fn read_properties(data_dir: &Path, dev: &str) -> Result<HashMap<String, String>, io::Error> {
+  let data = fs::read_to_string(data_dir.join(format!("c{}", dev)))?;
+  Ok(
+    data
+      .lines()
+      .filter_map(|line| line.strip_prefix("E:")?.split_once('='))
+      .map(|(key, value)| (key.to_owned(), value.to_owned()))
+      .collect(),
+  )
+}
+
+/// Fills in what the kernel's `uevent` left out of `device`, the hidraw
+/// node whose sysfs directory is `node_dir`, from the udev database in
+/// `data_dir`: the manufacturer always, and the product string and serial
+/// number when the device didn't report them, as Bluetooth devices often
+/// don't. The udev values are the USB descriptors' or the hardware
+/// database's, which Windows shows too.
+///
+/// Best effort: without udev, or for nodes it hasn't processed yet, the
+/// device is left as it is.
+pub(crate) fn fill_from_udev(mut device: HidDevice, node_dir: &Path, data_dir: &Path) -> HidDevice {
+  let properties = match fs::read_to_string(node_dir.join("dev"))
+    .and_then(|dev| read_properties(data_dir, dev.trim()))
+  {
+    Ok(properties) => properties,
+    Err(error) => {
+      log::debug!("no udev data for {}: {}", device.path, error);
+      return device;
+    }
+  };
+  let property = |encoded: &str, plain: &str| {
+    properties
+      .get(encoded)
+      .map(|value| decode_enc(value))
+      .or_else(|| properties.get(plain).cloned())
+      .filter(|value| !value.is_empty())
+  };
+
+  device.manufacturer_string = property("ID_VENDOR_ENC", "ID_VENDOR_FROM_DATABASE");
+  if device.product_string.is_none() {
+    device.product_string = property("ID_MODEL_ENC", "ID_MODEL_FROM_DATABASE");
+  }
+  if device.serial_number_string.is_none() {
+    device.serial_number_string = properties
+      .get("ID_SERIAL_SHORT")
+      .filter(|serial| !serial.is_empty())
+      .cloned();
+  }
+  device
+}
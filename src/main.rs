@@ -1,8 +1,72 @@
-use listhid::list_hid_device;
+use listhid::{list_hid_device, HidDevice};
+use std::process::exit;
+
+const USAGE: &str = "usage: listhid [--json [--compact]]";
+
+/// How the devices are printed.
+enum Output {
+  Debug,
+  Json { compact: bool },
+}
+
+fn parse_args() -> Result<Output, String> {
+  let mut json = false;
+  let mut compact = false;
+  for arg in std::env::args().skip(1) {
+    match arg.as_str() {
+      "--json" => json = true,
+      "--compact" => compact = true,
+      _ => return Err(format!("unknown option {}", arg)),
+    }
+  }
+  match (json, compact) {
+    (false, true) => Err("--compact only applies to --json".to_owned()),
+    (false, false) => Ok(Output::Debug),
+    (true, compact) => Ok(Output::Json { compact }),
+  }
+}
+
+/// Prints the devices as a JSON array, pretty unless `compact`.
+#[cfg(feature = "json")]
+fn print_json(devices: &[HidDevice], compact: bool) -> Result<(), String> {
+  let json = if compact {
+    serde_json::to_string(devices)
+  } else {
+    serde_json::to_string_pretty(devices)
+  };
+  println!("{}", json.map_err(|e| e.to_string())?);
+  Ok(())
+}
+
+#[cfg(not(feature = "json"))]
+fn print_json(_: &[HidDevice], _: bool) -> Result<(), String> {
+  Err("--json needs listhid built with the `json` feature".to_owned())
+}
 
 fn main() {
-  match list_hid_device() {
-    Ok(devices) => println!("hid devices: {:#?}", devices),
-    Err(e) => println!("error: {}", e),
+  let output = match parse_args() {
+    Ok(output) => output,
+    Err(e) => {
+      eprintln!("error: {}\n{}", e, USAGE);
+      exit(2);
+    }
+  };
+
+  let devices = match list_hid_device() {
+    Ok(devices) => devices,
+    Err(e) => {
+      eprintln!("error: {}", e);
+      exit(1);
+    }
+  };
+
+  match output {
+    Output::Debug => println!("hid devices: {:#?}", devices),
+    Output::Json { compact } => {
+      if let Err(e) = print_json(&devices, compact) {
+        eprintln!("error: {}", e);
+        exit(1);
+      }
+    }
   }
 }
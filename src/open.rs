@@ -0,0 +1,1013 @@
+use crate::win32::{
+  cancel_io, cm_notifications_supported, cm_register_handle_notification, create_event,
+  create_file, hid_d_get_attributes, hid_d_get_feature, hid_d_get_input_report,
+  hid_d_get_num_input_buffers, hid_d_get_physical_descriptor, hid_d_get_preparsed_data,
+  hid_d_set_feature, hid_d_set_num_input_buffers, hid_d_set_output_report, hid_p_get_caps,
+  read_file, read_file_overlapped, write_file, write_file_overlapped, Handle, Notification,
+  PreparsedData,
+};
+use crate::{open_device_for_query, Error, HidCapabilities, HidDevice};
+use std::borrow::Cow;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, PoisonError, RwLock, RwLockReadGuard, TryLockError};
+use std::time::Duration;
+use std::{io, ptr, thread};
+use winapi::shared::minwindef::DWORD;
+use winapi::shared::ntdef::PVOID;
+use winapi::shared::winerror::{
+  ERROR_ACCESS_DENIED, ERROR_CANCELLED, ERROR_DEVICE_NOT_CONNECTED, ERROR_FILE_NOT_FOUND,
+  ERROR_GEN_FAILURE, ERROR_INVALID_FUNCTION, ERROR_OPERATION_ABORTED, ERROR_PATH_NOT_FOUND,
+  ERROR_SHARING_VIOLATION, ERROR_SUCCESS,
+};
+use winapi::um::cfgmgr32::{
+  CM_NOTIFY_ACTION, CM_NOTIFY_ACTION_DEVICEQUERYREMOVE, CM_NOTIFY_ACTION_DEVICEQUERYREMOVEFAILED,
+  CM_NOTIFY_ACTION_DEVICEREMOVECOMPLETE, CM_NOTIFY_ACTION_DEVICEREMOVEPENDING, HCMNOTIFICATION,
+  PCM_NOTIFY_EVENT_DATA,
+};
+use winapi::um::fileapi::OPEN_EXISTING;
+use winapi::um::winbase::FILE_FLAG_OVERLAPPED;
+use winapi::um::winnt::{
+  FILE_ATTRIBUTE_NORMAL, FILE_SHARE_READ, FILE_SHARE_WRITE, GENERIC_READ, GENERIC_WRITE,
+};
+
+/// The access requested when opening a device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccessMode {
+  Read,
+  Write,
+  ReadWrite,
+}
+
+/// Whether other handles may use a device while it is open.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShareMode {
+  /// Other handles may read and write, as during enumeration.
+  Shared,
+  /// No other handle may read or write until this one is closed, e.g. while
+  /// writing a device's flash. System keyboards and mice are opened by
+  /// Windows itself, so an exclusive open of them always fails; check
+  /// `HidDevice::usage_page` and `usage` first to leave them out.
+  Exclusive,
+}
+
+/// Options for opening a device, in the style of `std::fs::OpenOptions`.
+///
+/// By default neither read nor write access is requested and the handle is
+/// shared for both reading and writing, as during enumeration. The options
+/// map directly onto the `CreateFileW` parameters; combinations a driver
+/// doesn't support fail with the OS error rather than being second-guessed.
+///
+/// ```no_run
+/// # let device = listhid::list_hid_device()?.remove(0);
+/// let opened = listhid::HidOpenOptions::new()
+///   .read(true)
+///   .write(false)
+///   .share_read(true)
+///   .share_write(false)
+///   .open(&device)?;
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HidOpenOptions {
+  read: bool,
+  write: bool,
+  share_read: bool,
+  share_write: bool,
+  overlapped: bool,
+}
+
+impl Default for HidOpenOptions {
+  fn default() -> Self {
+    HidOpenOptions {
+      read: false,
+      write: false,
+      share_read: true,
+      share_write: true,
+      overlapped: false,
+    }
+  }
+}
+
+impl HidOpenOptions {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  pub fn read(mut self, read: bool) -> Self {
+    self.read = read;
+    self
+  }
+
+  pub fn write(mut self, write: bool) -> Self {
+    self.write = write;
+    self
+  }
+
+  pub fn share_read(mut self, share_read: bool) -> Self {
+    self.share_read = share_read;
+    self
+  }
+
+  pub fn share_write(mut self, share_write: bool) -> Self {
+    self.share_write = share_write;
+    self
+  }
+
+  /// Sets both `share_read` and `share_write`.
+  pub fn share(self, share_mode: ShareMode) -> Self {
+    let shared = share_mode == ShareMode::Shared;
+    self.share_read(shared).share_write(shared)
+  }
+
+  /// Opens the handle for overlapped (asynchronous) I/O.
+  pub fn overlapped(mut self, overlapped: bool) -> Self {
+    self.overlapped = overlapped;
+    self
+  }
+
+  pub(crate) fn is_overlapped(&self) -> bool {
+    self.overlapped
+  }
+
+  /// The `dwDesiredAccess` passed to `CreateFileW`.
+  pub fn desired_access(&self) -> u32 {
+    let mut desired_access = 0;
+    if self.read {
+      desired_access |= GENERIC_READ;
+    }
+    if self.write {
+      desired_access |= GENERIC_WRITE;
+    }
+    desired_access
+  }
+
+  /// The `dwShareMode` passed to `CreateFileW`.
+  pub fn share_mode(&self) -> u32 {
+    let mut share_mode = 0;
+    if self.share_read {
+      share_mode |= FILE_SHARE_READ;
+    }
+    if self.share_write {
+      share_mode |= FILE_SHARE_WRITE;
+    }
+    share_mode
+  }
+
+  /// The `dwFlagsAndAttributes` passed to `CreateFileW`.
+  pub fn flags_and_attributes(&self) -> u32 {
+    if self.overlapped {
+      FILE_ATTRIBUTE_NORMAL | FILE_FLAG_OVERLAPPED
+    } else {
+      FILE_ATTRIBUTE_NORMAL
+    }
+  }
+
+  pub fn open(&self, device: &HidDevice) -> Result<OpenHidDevice, Error> {
+    OpenHidDevice::open(&device.path, *self)
+  }
+}
+
+/// A step in the removal of an open device, see `OpenHidDevice::on_removed`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RemovalEvent {
+  /// The device is about to be removed safely, e.g. ejected by the user, and
+  /// the removal can still be vetoed.
+  QueryRemove,
+  /// The removal announced by `QueryRemove` was vetoed, by this or another
+  /// handle's owner or by a driver, and the device stays.
+  QueryRemoveFailed,
+  /// The device is being removed and can't be used anymore.
+  RemovePending,
+  /// The device is gone.
+  RemoveComplete,
+}
+
+/// The handle of an `OpenHidDevice`, which removal notifications close from
+/// another thread. I/O holds the read lock for its duration, so the handle is
+/// never closed while in use.
+struct DeviceHandle {
+  handle: RwLock<Handle>,
+  // Set before closing, so no new I/O starts while the pending one is being
+  // cancelled.
+  closing: AtomicBool,
+}
+
+// The handle is only a kernel object reference, usable from any thread.
+unsafe impl Send for DeviceHandle {}
+unsafe impl Sync for DeviceHandle {}
+
+impl DeviceHandle {
+  fn new(handle: Handle) -> Arc<DeviceHandle> {
+    Arc::new(DeviceHandle {
+      handle: RwLock::new(handle),
+      closing: AtomicBool::new(false),
+    })
+  }
+
+  /// Locks the handle for I/O, failing with `ERROR_DEVICE_NOT_CONNECTED`
+  /// once a removal closed it.
+  fn lock(&self) -> Result<RwLockReadGuard<'_, Handle>, io::Error> {
+    let handle = if self.closing.load(Ordering::Acquire) {
+      None
+    } else {
+      Some(self.handle.read().unwrap_or_else(PoisonError::into_inner))
+    };
+    match handle {
+      Some(handle) if handle.native_handle.is_some() => Ok(handle),
+      _ => Err(io::Error::from_raw_os_error(
+        ERROR_DEVICE_NOT_CONNECTED as i32,
+      )),
+    }
+  }
+
+  /// Runs `io` with the handle, mapping its error as `map_error` describes.
+  fn with<T, F>(&self, io: F) -> Result<T, Error>
+  where
+    F: FnOnce(&Handle) -> Result<T, io::Error>,
+  {
+    let handle = self.lock()?;
+    io(&handle).map_err(|e| map_error(&handle, e))
+  }
+
+  /// Closes the handle, cancelling the I/O in progress on other threads and
+  /// waiting for it to return.
+  fn close(&self) {
+    self.closing.store(true, Ordering::Release);
+    loop {
+      match self.handle.try_write() {
+        Ok(mut handle) => {
+          *handle = Handle {
+            native_handle: None,
+          };
+          return;
+        }
+        Err(TryLockError::Poisoned(e)) => {
+          *e.into_inner() = Handle {
+            native_handle: None,
+          };
+          return;
+        }
+        Err(TryLockError::WouldBlock) => {
+          cancel_io(&self.handle.read().unwrap_or_else(PoisonError::into_inner));
+          thread::yield_now();
+        }
+      }
+    }
+  }
+}
+
+/// Converts an I/O error on `handle` to `Error`, as `Error::Disconnected` if
+/// it is one of the errors the device fails with when unplugged and the
+/// device is indeed gone. The other causes of those errors are left as they
+/// are.
+fn map_error(handle: &Handle, e: io::Error) -> Error {
+  if is_removal_error(&e) && hid_d_get_attributes(handle).is_err() {
+    Error::Disconnected
+  } else {
+    e.into()
+  }
+}
+
+/// A callback registered with `OpenHidDevice::on_removed`, with the handle it
+/// closes.
+struct RemovalContext {
+  callback: Mutex<Box<dyn FnMut(RemovalEvent) -> bool + Send>>,
+  handle: Arc<DeviceHandle>,
+}
+
+struct RemovalNotification {
+  // Unregistered first, as its callback uses `context`.
+  _notification: Notification,
+  _context: Box<RemovalContext>,
+}
+
+unsafe extern "system" fn on_removal_notification(
+  _notification: HCMNOTIFICATION,
+  context: PVOID,
+  action: CM_NOTIFY_ACTION,
+  _event_data: PCM_NOTIFY_EVENT_DATA,
+  _event_data_size: DWORD,
+) -> DWORD {
+  let event = match action {
+    CM_NOTIFY_ACTION_DEVICEQUERYREMOVE => RemovalEvent::QueryRemove,
+    CM_NOTIFY_ACTION_DEVICEQUERYREMOVEFAILED => RemovalEvent::QueryRemoveFailed,
+    CM_NOTIFY_ACTION_DEVICEREMOVEPENDING => RemovalEvent::RemovePending,
+    CM_NOTIFY_ACTION_DEVICEREMOVECOMPLETE => RemovalEvent::RemoveComplete,
+    _ => return ERROR_SUCCESS,
+  };
+
+  let context = &*(context as *const RemovalContext);
+  let allowed = match context.callback.lock() {
+    Ok(mut callback) => callback(event),
+    Err(_) => true,
+  };
+  match event {
+    RemovalEvent::QueryRemove if !allowed => ERROR_CANCELLED,
+    RemovalEvent::QueryRemoveFailed => ERROR_SUCCESS,
+    _ => {
+      // An open handle keeps the device from being removed.
+      context.handle.close();
+      ERROR_SUCCESS
+    }
+  }
+}
+
+/// A HID device opened for I/O. The handle is closed on drop.
+pub struct OpenHidDevice {
+  // Unregistered before the handle is closed.
+  removal: Option<RemovalNotification>,
+  handle: Arc<DeviceHandle>,
+  path: String,
+  options: HidOpenOptions,
+  preparsed_data: PreparsedData,
+  capabilities: HidCapabilities,
+  // Created on the first overlapped read or write and reused by later ones.
+  io_event: Option<Handle>,
+}
+
+// The handle is only a kernel object reference, usable from any thread.
+unsafe impl Send for OpenHidDevice {}
+
+impl OpenHidDevice {
+  fn open(path: &str, options: HidOpenOptions) -> Result<OpenHidDevice, Error> {
+    let handle = unsafe {
+      create_file(
+        path,
+        options.desired_access(),
+        options.share_mode(),
+        ptr::null_mut(),
+        OPEN_EXISTING,
+        options.flags_and_attributes(),
+        Handle {
+          native_handle: None,
+        },
+      )
+    }
+    .map_err(|error| open_error(path, error))?;
+    Self::from_handle(handle, path, options)
+  }
+
+  fn from_handle(
+    handle: Handle,
+    path: &str,
+    options: HidOpenOptions,
+  ) -> Result<OpenHidDevice, Error> {
+    let preparsed_data = hid_d_get_preparsed_data(&handle)?;
+    let capabilities = hid_p_get_caps(&preparsed_data)?.into();
+
+    Ok(OpenHidDevice {
+      removal: None,
+      handle: DeviceHandle::new(handle),
+      path: path.to_owned(),
+      options,
+      preparsed_data,
+      capabilities,
+      io_event: None,
+    })
+  }
+
+  pub fn path(&self) -> &str {
+    &self.path
+  }
+
+  /// The options the device was opened with.
+  pub fn options(&self) -> &HidOpenOptions {
+    &self.options
+  }
+
+  /// Splits the device into its handle, path and capabilities. A removal
+  /// callback is unregistered.
+  #[cfg(feature = "tokio")]
+  pub(crate) fn into_parts(mut self) -> (Handle, String, HidCapabilities) {
+    self.removal = None;
+    let handle = match Arc::try_unwrap(self.handle) {
+      Ok(handle) => handle
+        .handle
+        .into_inner()
+        .unwrap_or_else(PoisonError::into_inner),
+      // Only the removal callback shares the handle.
+      Err(_) => unreachable!(),
+    };
+    (handle, self.path, self.capabilities)
+  }
+
+  /// Locks the handle for I/O, see `DeviceHandle::lock`.
+  pub(crate) fn handle(&self) -> Result<RwLockReadGuard<'_, Handle>, io::Error> {
+    self.handle.lock()
+  }
+
+  /// Calls `callback` as the device is removed, so the removal can be
+  /// handled before a read fails.
+  ///
+  /// For `RemovalEvent::QueryRemove`, returning `false` vetoes the removal;
+  /// returning `true` lets it go ahead, for which the handle is closed before
+  /// the removal is let through. The return value is ignored for the other
+  /// events, after all but `QueryRemoveFailed` of which the handle is closed
+  /// too. Once closed, calls fail with `Error::Disconnected`; if the removal
+  /// then fails after all, `reopen` the device and register again.
+  ///
+  /// The callback runs on a system thread, one event at a time, and must not
+  /// drop this device. Registering again replaces the callback. Dropping the
+  /// device, `reopen` and `into_async` unregister it, waiting for a call in
+  /// progress to return. Needs Windows 8 or later; fails with
+  /// `Error::Unsupported` before that.
+  pub fn on_removed<F>(&mut self, callback: F) -> Result<(), Error>
+  where
+    F: FnMut(RemovalEvent) -> bool + Send + 'static,
+  {
+    if !cm_notifications_supported() {
+      return Err(Error::Unsupported);
+    }
+
+    self.removal = None;
+    let context = Box::new(RemovalContext {
+      callback: Mutex::new(Box::new(callback)),
+      handle: self.handle.clone(),
+    });
+    let notification = unsafe {
+      cm_register_handle_notification(
+        &*self.handle.lock()?,
+        &*context as *const RemovalContext as PVOID,
+        Some(on_removal_notification),
+      )
+    }?;
+    self.removal = Some(RemovalNotification {
+      _notification: notification,
+      _context: context,
+    });
+    Ok(())
+  }
+
+  pub(crate) fn preparsed_data(&self) -> &PreparsedData {
+    &self.preparsed_data
+  }
+
+  /// The capabilities read from the device when it was opened.
+  pub fn capabilities(&self) -> &HidCapabilities {
+    &self.capabilities
+  }
+
+  /// The length of the longest input report, including the report ID byte.
+  pub fn input_report_byte_length(&self) -> usize {
+    self.capabilities.input_report_byte_length as usize
+  }
+
+  /// The length of the longest output report, including the report ID byte.
+  pub fn output_report_byte_length(&self) -> usize {
+    self.capabilities.output_report_byte_length as usize
+  }
+
+  /// The length of the longest feature report, including the report ID byte.
+  pub fn feature_report_byte_length(&self) -> usize {
+    self.capabilities.feature_report_byte_length as usize
+  }
+
+  /// Reads the feature report `report_id` into `buf` and returns its length,
+  /// `feature_report_byte_length()`. The first byte of `buf` receives the
+  /// report ID; pass 0 for devices that don't use report IDs.
+  ///
+  /// `buf` must hold at least `feature_report_byte_length()` bytes, otherwise
+  /// `Error::BufferTooSmall` is returned.
+  pub fn get_feature_report(&self, report_id: u8, buf: &mut [u8]) -> Result<usize, Error> {
+    // At least the report ID byte, even for devices without feature reports.
+    let required = self.feature_report_byte_length().max(1);
+    if buf.len() < required {
+      return Err(Error::BufferTooSmall { required });
+    }
+
+    self
+      .handle
+      .with(|handle| hid_d_get_feature(handle, report_id, &mut buf[1..required]))?;
+    buf[0] = report_id;
+    Ok(required)
+  }
+
+  /// Sends a feature report. The first byte of `data` is the report ID, 0 for
+  /// devices that don't use report IDs; shorter reports are padded with zeros
+  /// up to `feature_report_byte_length()`.
+  pub fn send_feature_report(&self, data: &[u8]) -> Result<(), Error> {
+    let expected = self.feature_report_byte_length();
+    if data.is_empty() {
+      return Err(Error::ReportTooShort {
+        expected,
+        actual: 0,
+      });
+    }
+    if data.len() > expected {
+      return Err(Error::ReportTooLong {
+        expected,
+        actual: data.len(),
+      });
+    }
+
+    let mut report = data.to_vec();
+    report.resize(expected, 0);
+    self
+      .handle
+      .with(|handle| hid_d_set_feature(handle, report[0], &report[1..]))
+  }
+
+  /// Polls the device for the input report `report_id` instead of waiting for
+  /// it to send one, and returns its length, `input_report_byte_length()`.
+  /// Pass 0 for devices that don't use report IDs.
+  ///
+  /// Returns `Error::Unsupported` if the driver doesn't support polling, in
+  /// which case `read` is the only way to get input reports.
+  pub fn get_input_report(&self, report_id: u8, buf: &mut [u8]) -> Result<usize, Error> {
+    let required = self.input_report_byte_length().max(1);
+    if buf.len() < required {
+      return Err(Error::BufferTooSmall { required });
+    }
+
+    match self
+      .handle
+      .with(|handle| hid_d_get_input_report(handle, report_id, &mut buf[..required]))
+    {
+      Ok(()) => Ok(required),
+      Err(Error::Io(e)) if e.raw_os_error() == Some(ERROR_INVALID_FUNCTION as i32) => {
+        Err(Error::Unsupported)
+      }
+      Err(e) => Err(e),
+    }
+  }
+
+  /// The number of input reports the HID class driver buffers for this
+  /// handle, 32 by default. Reports arriving while the buffer is full are
+  /// dropped, oldest first.
+  pub fn input_buffer_count(&self) -> Result<u32, Error> {
+    self.handle.with(hid_d_get_num_input_buffers)
+  }
+
+  /// Sets the number of input reports buffered for this handle; other
+  /// handles to the same device keep their own setting. `count` must be
+  /// between 2 and 512, otherwise `Error::InvalidInputBufferCount` is
+  /// returned.
+  pub fn set_input_buffer_count(&self, count: u32) -> Result<(), Error> {
+    if !(2..=512).contains(&count) {
+      return Err(Error::InvalidInputBufferCount(count));
+    }
+
+    self
+      .handle
+      .with(|handle| hid_d_set_num_input_buffers(handle, count))
+  }
+
+  /// Sets the number of input reports buffered for this handle as close to
+  /// `requested` as the driver allows, and returns the number in effect,
+  /// read back after setting it. `requested` is clamped to 2 to 512 first,
+  /// the range `set_input_buffer_count` accepts, rather than rejected.
+  ///
+  /// ```no_run
+  /// # let device = listhid::list_hid_device()?.remove(0);
+  /// let opened = device.open()?;
+  /// let depth = opened.set_input_queue_depth(1024)?;
+  /// println!("buffering {} reports", depth);
+  /// # Ok::<(), Box<dyn std::error::Error>>(())
+  /// ```
+  pub fn set_input_queue_depth(&self, requested: u32) -> Result<u32, Error> {
+    let count = requested.clamp(2, 512);
+    self.handle.with(|handle| {
+      hid_d_set_num_input_buffers(handle, count)?;
+      hid_d_get_num_input_buffers(handle)
+    })
+  }
+
+  /// The device's physical descriptor set, which maps controls to the body
+  /// parts operating them. Most devices don't have one.
+  pub fn physical_descriptor(&self) -> Option<Vec<u8>> {
+    hid_d_get_physical_descriptor(&*self.handle.lock().ok()?)
+  }
+
+  /// Blocks until an input report arrives and copies it into `buf`, returning
+  /// its length. The report starts with the report ID byte, which is 0 for
+  /// devices that don't use report IDs.
+  ///
+  /// `buf` must hold at least `input_report_byte_length()` bytes, otherwise
+  /// `Error::BufferTooSmall` is returned instead of a truncated report.
+  pub fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+    if self.options.is_overlapped() {
+      return Ok(self.read_overlapped(buf, None)?.unwrap_or(0));
+    }
+
+    let required = self.input_report_byte_length();
+    if buf.len() < required {
+      return Err(Error::BufferTooSmall { required });
+    }
+
+    self
+      .handle
+      .with(|handle| read_file(handle, &mut buf[..required]))
+  }
+
+  /// Like `read`, but gives up after `timeout` and returns `Ok(None)` if no
+  /// input report arrived. A zero timeout polls without blocking.
+  ///
+  /// The device must have been opened for overlapped I/O, as `open` and
+  /// `open_with` do; otherwise an `InvalidInput` error is returned.
+  pub fn read_timeout(
+    &mut self,
+    buf: &mut [u8],
+    timeout: Duration,
+  ) -> Result<Option<usize>, Error> {
+    if !self.options.is_overlapped() {
+      return Err(Error::Io(io::Error::new(
+        io::ErrorKind::InvalidInput,
+        "device was not opened for overlapped I/O",
+      )));
+    }
+
+    self.read_overlapped(buf, Some(timeout))
+  }
+
+  /// Iterates over the device's input reports, blocking on each read.
+  ///
+  /// Each report is read into a buffer of `input_report_byte_length()`
+  /// bytes. When the device is unplugged, the read error, usually
+  /// `ERROR_DEVICE_NOT_CONNECTED`, is yielded and the iteration ends; other
+  /// read errors are yielded without ending it.
+  ///
+  /// ```no_run
+  /// # let device = listhid::list_hid_device()?.remove(0);
+  /// let opened = device.open_with(listhid::AccessMode::Read)?;
+  /// for report in opened.input_reports() {
+  ///   println!("{:02x?}", report?);
+  /// }
+  /// # Ok::<(), Box<dyn std::error::Error>>(())
+  /// ```
+  pub fn input_reports(&self) -> InputReports<'_> {
+    InputReports {
+      device: self,
+      read_event: None,
+      done: false,
+    }
+  }
+
+  fn read_overlapped(
+    &mut self,
+    buf: &mut [u8],
+    timeout: Option<Duration>,
+  ) -> Result<Option<usize>, Error> {
+    let required = self.input_report_byte_length();
+    if buf.len() < required {
+      return Err(Error::BufferTooSmall { required });
+    }
+
+    let io_event = lazy_event(&mut self.io_event)?;
+    self
+      .handle
+      .with(|handle| read_file_overlapped(handle, io_event, &mut buf[..required], timeout, None))
+  }
+
+  /// Whether the device is still plugged in, checked by querying its
+  /// attributes, which is much cheaper than a read.
+  pub fn is_connected(&self) -> bool {
+    self.handle.with(hid_d_get_attributes).is_ok()
+  }
+
+  /// Closes the handle and opens the device again from its path with the
+  /// same options, e.g. after a resume from sleep or a surprise removal left
+  /// the handle unusable. The preparsed data and capabilities are read again.
+  ///
+  /// If the device is gone this fails with `Error::Disconnected`; the old
+  /// handle is closed regardless, so other calls fail until a later `reopen`
+  /// succeeds.
+  pub fn reopen(&mut self) -> Result<(), Error> {
+    // Close first, as an exclusive handle would block its own replacement.
+    self.removal = None;
+    self.handle = DeviceHandle::new(Handle {
+      native_handle: None,
+    });
+    self.io_event = None;
+
+    *self = OpenHidDevice::open(&self.path, self.options).map_err(|e| match e {
+      Error::Io(e) if is_not_found_error(&e) => Error::Disconnected,
+      e => e,
+    })?;
+    Ok(())
+  }
+
+  /// Sends an output report and returns the number of bytes written. The
+  /// first byte is the report ID, 0 for devices that don't use report IDs.
+  ///
+  /// `report` must be exactly `output_report_byte_length()` bytes long,
+  /// otherwise `Error::ReportTooShort` or `Error::ReportTooLong` is returned;
+  /// see `write_padded` for shorter reports.
+  pub fn write(&mut self, report: &[u8]) -> Result<usize, Error> {
+    check_output_report(self.output_report_byte_length(), report)?;
+
+    if self.options.is_overlapped() {
+      let io_event = lazy_event(&mut self.io_event)?;
+      self
+        .handle
+        .with(|handle| write_file_overlapped(handle, io_event, report, None))
+        .map(|length| length.unwrap_or(0))
+    } else {
+      self.handle.with(|handle| write_file(handle, report))
+    }
+  }
+
+  /// Sends an output report over the control pipe with
+  /// `HidD_SetOutputReport` rather than the interrupt pipe `write` uses. The
+  /// report follows the same conventions as for `write`.
+  ///
+  /// `write` is preferable in general, as it doesn't block other control
+  /// requests, but some firmwares only accept output reports this way.
+  /// Returns `Error::Unsupported` if the driver doesn't support it, in which
+  /// case `write` is the only option.
+  pub fn set_output_report(&self, report: &[u8]) -> Result<(), Error> {
+    check_output_report(self.output_report_byte_length(), report)?;
+
+    let mut report = report.to_vec();
+    match self
+      .handle
+      .with(|handle| hid_d_set_output_report(handle, &mut report))
+    {
+      Ok(()) => Ok(()),
+      Err(Error::Io(e)) if e.raw_os_error() == Some(ERROR_INVALID_FUNCTION as i32) => {
+        Err(Error::Unsupported)
+      }
+      Err(e) => Err(e),
+    }
+  }
+
+  /// Like `write`, but pads a short `report` with zeros up to
+  /// `output_report_byte_length()`, which `WriteFile` otherwise rejects with
+  /// `ERROR_INVALID_PARAMETER`. A longer `report` is still an
+  /// `Error::ReportTooLong` rather than being cut short.
+  pub fn write_padded(&mut self, report: &[u8]) -> Result<usize, Error> {
+    let padded = pad_output_report(self.output_report_byte_length(), report);
+    self.write(&padded)
+  }
+}
+
+/// Reads one input report per call. If `buf` is shorter than the report, the
+/// report is truncated to fit and the rest of it is discarded.
+impl io::Read for OpenHidDevice {
+  fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+    if buf.len() >= self.input_report_byte_length() {
+      return Ok(OpenHidDevice::read(self, buf)?);
+    }
+
+    let mut report = vec![0u8; self.input_report_byte_length()];
+    let length = OpenHidDevice::read(self, &mut report)?.min(buf.len());
+    buf[..length].copy_from_slice(&report[..length]);
+    Ok(length)
+  }
+}
+
+/// Sends one output report per call, padded with zeros like `write_padded`,
+/// and reports all of `buf` as written.
+impl io::Write for OpenHidDevice {
+  fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+    self.write_padded(buf)?;
+    Ok(buf.len())
+  }
+
+  fn flush(&mut self) -> io::Result<()> {
+    Ok(())
+  }
+}
+
+/// Checks that `report` is a complete output report for a device whose output
+/// reports are `expected` bytes long.
+pub(crate) fn check_output_report(expected: usize, report: &[u8]) -> Result<(), Error> {
+  if expected == 0 {
+    return Err(Error::NoOutputReports);
+  }
+  if report.len() < expected {
+    return Err(Error::ReportTooShort {
+      expected,
+      actual: report.len(),
+    });
+  }
+  if report.len() > expected {
+    return Err(Error::ReportTooLong {
+      expected,
+      actual: report.len(),
+    });
+  }
+  Ok(())
+}
+
+/// Pads `report` with zeros up to `expected` bytes, leaving longer reports
+/// for `check_output_report` to reject.
+fn pad_output_report(expected: usize, report: &[u8]) -> Cow<'_, [u8]> {
+  if report.len() >= expected {
+    return Cow::Borrowed(report);
+  }
+
+  let mut padded = report.to_vec();
+  padded.resize(expected, 0);
+  Cow::Owned(padded)
+}
+
+/// Maps a failed `CreateFileW` of `path` to an `Error`, naming the path if
+/// another handle is in the way.
+fn open_error(path: &str, error: io::Error) -> Error {
+  if error.raw_os_error() == Some(ERROR_SHARING_VIOLATION as i32) {
+    Error::Busy {
+      path: path.to_owned(),
+    }
+  } else {
+    error.into()
+  }
+}
+
+/// Whether a failed open means the device is there but in use or guarded:
+/// `ERROR_ACCESS_DENIED` or `ERROR_SHARING_VIOLATION`. Errors meaning it is
+/// gone, such as `ERROR_FILE_NOT_FOUND`, are not busy.
+fn is_busy_error(e: &io::Error) -> bool {
+  const BUSY_ERRORS: [u32; 2] = [ERROR_ACCESS_DENIED, ERROR_SHARING_VIOLATION];
+  e.raw_os_error()
+    .is_some_and(|code| BUSY_ERRORS.contains(&(code as u32)))
+}
+
+/// Whether a failed open means there is no device at the path.
+fn is_not_found_error(e: &io::Error) -> bool {
+  const NOT_FOUND_ERRORS: [u32; 3] = [
+    ERROR_DEVICE_NOT_CONNECTED,
+    ERROR_FILE_NOT_FOUND,
+    ERROR_PATH_NOT_FOUND,
+  ];
+  e.raw_os_error()
+    .is_some_and(|code| NOT_FOUND_ERRORS.contains(&(code as u32)))
+}
+
+/// Whether `e` is one of the errors pending and new I/O fails with when a
+/// device is unplugged.
+fn is_removal_error(e: &io::Error) -> bool {
+  const REMOVAL_ERRORS: [u32; 3] = [
+    ERROR_DEVICE_NOT_CONNECTED,
+    ERROR_GEN_FAILURE,
+    ERROR_OPERATION_ABORTED,
+  ];
+  e.raw_os_error()
+    .is_some_and(|code| REMOVAL_ERRORS.contains(&(code as u32)))
+}
+
+/// Returns the event in `event`, creating it on first use.
+fn lazy_event(event: &mut Option<Handle>) -> Result<&Handle, io::Error> {
+  if event.is_none() {
+    *event = Some(create_event()?);
+  }
+  Ok(event.as_ref().unwrap())
+}
+
+/// Iterator over the input reports of an `OpenHidDevice`, see
+/// `OpenHidDevice::input_reports`.
+pub struct InputReports<'a> {
+  device: &'a OpenHidDevice,
+  read_event: Option<Handle>,
+  done: bool,
+}
+
+impl InputReports<'_> {
+  fn read_report(&mut self) -> Result<Vec<u8>, io::Error> {
+    let mut report = vec![0u8; self.device.input_report_byte_length()];
+
+    let handle = self.device.handle()?;
+    let bytes_read = if self.device.options.is_overlapped() {
+      let read_event = lazy_event(&mut self.read_event)?;
+      read_file_overlapped(&handle, read_event, &mut report, None, None)?.unwrap_or(0)
+    } else {
+      read_file(&handle, &mut report)?
+    };
+
+    report.truncate(bytes_read);
+    Ok(report)
+  }
+}
+
+impl Iterator for InputReports<'_> {
+  type Item = Result<Vec<u8>, io::Error>;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    if self.done {
+      return None;
+    }
+
+    let result = self.read_report();
+    if let Err(error) = &result {
+      if is_removal_error(error) && !self.device.is_connected() {
+        self.done = true;
+      }
+    }
+    Some(result)
+  }
+}
+
+impl HidDevice {
+  /// Opens the device for reading and writing, shared with other handles.
+  /// The handle is opened for overlapped I/O so `read_timeout` can be used.
+  pub fn open(&self) -> Result<OpenHidDevice, Error> {
+    self.open_with(AccessMode::ReadWrite)
+  }
+
+  /// Opens the device for reading and writing without sharing it, which some
+  /// devices require before accepting feature reports. Fails with
+  /// `Error::Busy` while another handle, possibly in another process, has
+  /// read or write access. The handles listings open have no access rights
+  /// and are closed before they return, so they never get in the way. Like
+  /// `open`, the handle is overlapped, so `read_timeout` can be used.
+  pub fn open_exclusive(&self) -> Result<OpenHidDevice, Error> {
+    HidOpenOptions::new()
+      .read(true)
+      .write(true)
+      .share_read(false)
+      .share_write(false)
+      .overlapped(true)
+      .open(self)
+  }
+
+  /// Whether the device is still plugged in, checked by opening it without
+  /// any access rights and closing it again. A device that is busy or denies
+  /// access still counts as connected.
+  pub fn is_connected(&self) -> bool {
+    match open_device_for_query(&self.path) {
+      Ok(_) => true,
+      Err(error) => is_busy_error(&error),
+    }
+  }
+
+  pub fn open_with(&self, access: AccessMode) -> Result<OpenHidDevice, Error> {
+    HidOpenOptions::new()
+      .read(access != AccessMode::Write)
+      .write(access != AccessMode::Read)
+      .overlapped(true)
+      .open(self)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn os_error(code: u32) -> io::Error {
+    io::Error::from_raw_os_error(code as i32)
+  }
+
+  #[test]
+  fn busy_errors_are_access_denied_and_sharing_violation() {
+    assert!(is_busy_error(&os_error(ERROR_ACCESS_DENIED)));
+    assert!(is_busy_error(&os_error(ERROR_SHARING_VIOLATION)));
+    assert!(!is_busy_error(&os_error(ERROR_FILE_NOT_FOUND)));
+    assert!(!is_busy_error(&os_error(ERROR_DEVICE_NOT_CONNECTED)));
+    assert!(!is_busy_error(&io::Error::other("busy")));
+  }
+
+  #[test]
+  fn not_found_errors_mean_the_device_is_gone() {
+    assert!(is_not_found_error(&os_error(ERROR_DEVICE_NOT_CONNECTED)));
+    assert!(is_not_found_error(&os_error(ERROR_FILE_NOT_FOUND)));
+    assert!(is_not_found_error(&os_error(ERROR_PATH_NOT_FOUND)));
+    assert!(!is_not_found_error(&os_error(ERROR_ACCESS_DENIED)));
+    assert!(!is_not_found_error(&os_error(ERROR_SHARING_VIOLATION)));
+    assert!(!is_not_found_error(&io::Error::new(
+      io::ErrorKind::NotFound,
+      "gone"
+    )));
+  }
+
+  #[test]
+  fn short_output_reports_are_padded_with_zeros() {
+    let padded = pad_output_report(5, &[0x00, 0x01, 0x02]);
+    assert_eq!(&*padded, [0x00, 0x01, 0x02, 0x00, 0x00]);
+    assert!(check_output_report(5, &padded).is_ok());
+  }
+
+  #[test]
+  fn complete_output_reports_are_sent_as_they_are() {
+    let report = [0x02, 0xff, 0xee];
+    assert!(matches!(pad_output_report(3, &report), Cow::Borrowed(_)));
+    assert!(check_output_report(3, &report).is_ok());
+  }
+
+  #[test]
+  fn long_output_reports_are_rejected_rather_than_cut() {
+    let report = [0x00; 6];
+    let padded = pad_output_report(5, &report);
+    assert_eq!(padded.len(), 6);
+    assert!(matches!(
+      check_output_report(5, &padded),
+      Err(Error::ReportTooLong {
+        expected: 5,
+        actual: 6
+      })
+    ));
+  }
+
+  #[test]
+  fn output_reports_are_checked_against_the_device() {
+    assert!(matches!(
+      check_output_report(5, &[0x00; 4]),
+      Err(Error::ReportTooShort {
+        expected: 5,
+        actual: 4
+      })
+    ));
+    assert!(matches!(
+      check_output_report(0, &[0x00]),
+      Err(Error::NoOutputReports)
+    ));
+  }
+}
@@ -0,0 +1,4 @@
+//! Platform-specific extensions, modeled after `std::os`.
+
+#[cfg(windows)]
+pub mod windows;
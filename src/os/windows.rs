@@ -0,0 +1,70 @@
+//! Windows-specific extensions.
+
+use crate::HidDevice;
+
+mod private {
+  pub trait Sealed {}
+  impl Sealed for crate::HidDevice {}
+}
+
+/// Windows-specific data of a `HidDevice`, read from SetupAPI when listing at
+/// `DetailLevel::Registry` or `DetailLevel::Full`; `None` at the lower
+/// levels and for devices read by `get_hid_device`.
+///
+/// ```no_run
+/// use listhid::os::windows::HidDeviceExt;
+///
+/// for device in listhid::list_hid_device()? {
+///   println!("{:?} {:?}", device.instance_id(), device.pdo_name());
+/// }
+/// # Ok::<(), listhid::ListHidError>(())
+/// ```
+pub trait HidDeviceExt: private::Sealed {
+  /// The device node, for the `CM_*` functions of the configuration manager.
+  fn dev_inst(&self) -> Option<u32>;
+  /// The name of the physical device object, e.g. `\Device\00000042`.
+  fn pdo_name(&self) -> Option<&str>;
+  /// The device instance ID, e.g. `HID\VID_046D&PID_C077\7&1A2B3C4D&0&0000`.
+  fn instance_id(&self) -> Option<&str>;
+}
+
+/// The data behind `HidDeviceExt`, kept in `HidDevice`. The deprecated public
+/// fields of the same names only mirror it when listing, for code that still
+/// reads them.
+#[derive(Debug, Default, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub(crate) struct WindowsDeviceData {
+  pub(crate) dev_inst: Option<u32>,
+  pub(crate) pdo_name: Option<String>,
+  pub(crate) instance_id: Option<String>,
+}
+
+impl HidDeviceExt for HidDevice {
+  fn dev_inst(&self) -> Option<u32> {
+    self.windows.dev_inst
+  }
+
+  fn pdo_name(&self) -> Option<&str> {
+    self.windows.pdo_name.as_deref()
+  }
+
+  fn instance_id(&self) -> Option<&str> {
+    self.windows.instance_id.as_deref()
+  }
+}
+
+/// Whether `hid.dll` is available. It is missing from some Server Core and
+/// Nano Server images, where devices are listed without being opened, at
+/// `DetailLevel::Registry` instead of the `DetailLevel::Full` asked for and
+/// with `HidDevice::is_degraded` set, and opening one fails with
+/// `ERROR_PROC_NOT_FOUND`.
+///
+/// ```no_run
+/// if !listhid::os::windows::hid_dll_available() {
+///   eprintln!("hid.dll is missing, the HID strings and versions are unknown");
+/// }
+/// ```
+pub fn hid_dll_available() -> bool {
+  crate::win32::hid_dll_available()
+}
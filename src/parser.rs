@@ -0,0 +1,608 @@
+use crate::win32::{
+  hid_d_get_preparsed_data, hid_p_get_button_caps, hid_p_get_caps, hid_p_get_scaled_usage_value,
+  hid_p_get_usage_value, hid_p_get_usages, hid_p_get_value_caps, hid_p_initialize_report_for_id,
+  hid_p_set_usage, hid_p_set_usage_value, hid_p_status_to_error,
+  hid_p_translate_usages_to_i8042_scan_codes, PreparsedData,
+};
+use crate::{Error, HidCapabilities, HidReportType, OpenHidDevice, ValueCap};
+use std::collections::BTreeMap;
+use std::io;
+use winapi::shared::hidpi::{HIDP_STATUS_BAD_LOG_PHY_VALUES, HIDP_STATUS_NULL};
+
+/// A value read from a report.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UsageValue {
+  pub link_collection: u16,
+  pub usage: u16,
+  /// The raw value, before any scaling to physical units.
+  pub value: u32,
+}
+
+/// An input report decoded into usages, see `ReportParser`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ParsedReport {
+  pub report_id: u8,
+  /// The pressed buttons, by usage page.
+  pub buttons: BTreeMap<u16, Vec<u16>>,
+  /// The values, by usage page.
+  pub values: BTreeMap<u16, Vec<UsageValue>>,
+}
+
+/// The buttons pressed and released between two reports, see
+/// `ReportParser::diff_usages`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct UsageDiff {
+  /// The buttons set in the new report but not in the old one, by usage page.
+  pub pressed: BTreeMap<u16, Vec<u16>>,
+  /// The buttons set in the old report but not in the new one, by usage page.
+  pub released: BTreeMap<u16, Vec<u16>>,
+  /// Whether the new report is a keyboard's ErrorRollOver report, sent when
+  /// more keys are down than it can report. Its usages don't say which keys
+  /// are down, so nothing is pressed or released; keep the old state until
+  /// the next report.
+  pub rollover: bool,
+}
+
+const KEYBOARD_PAGE: u16 = 0x07;
+const ERROR_ROLL_OVER: u16 = 0x01;
+
+/// Decodes a device's input reports using its preparsed data, which is
+/// fetched once when the parser is created.
+///
+/// Value arrays, i.e. values with a report count above one, are left out.
+pub struct ReportParser {
+  preparsed_data: PreparsedData,
+  input_report_byte_length: usize,
+  // The usage pages with buttons, by report ID.
+  button_usage_pages: BTreeMap<u8, Vec<u16>>,
+  value_caps: Vec<ValueCap>,
+}
+
+// The preparsed data is a heap block owned by the parser.
+unsafe impl Send for ReportParser {}
+
+impl ReportParser {
+  pub fn new(device: &OpenHidDevice) -> Result<ReportParser, Error> {
+    let preparsed_data = hid_d_get_preparsed_data(&*device.handle()?)?;
+    let capabilities: HidCapabilities = hid_p_get_caps(&preparsed_data)?.into();
+    let report_type = HidReportType::Input;
+
+    let mut button_usage_pages: BTreeMap<u8, Vec<u16>> = BTreeMap::new();
+    for caps in hid_p_get_button_caps(
+      report_type.to_native(),
+      &preparsed_data,
+      capabilities.button_caps_count(report_type),
+    )? {
+      let usage_pages = button_usage_pages.entry(caps.ReportID).or_default();
+      if !usage_pages.contains(&caps.UsagePage) {
+        usage_pages.push(caps.UsagePage);
+      }
+    }
+    let value_caps = hid_p_get_value_caps(
+      report_type.to_native(),
+      &preparsed_data,
+      capabilities.value_caps_count(report_type),
+    )?
+    .into_iter()
+    .map(ValueCap::from)
+    .filter(|caps| caps.report_count <= 1)
+    .collect();
+
+    Ok(ReportParser {
+      preparsed_data,
+      input_report_byte_length: capabilities.input_report_byte_length as usize,
+      button_usage_pages,
+      value_caps,
+    })
+  }
+
+  /// Decodes an input report, as returned by `OpenHidDevice::read`. The
+  /// report starts with its report ID and must be exactly
+  /// `input_report_byte_length()` bytes long.
+  pub fn parse_input(&self, report: &[u8]) -> Result<ParsedReport, Error> {
+    let expected = self.input_report_byte_length;
+    if report.len() < expected || report.is_empty() {
+      return Err(Error::ReportTooShort {
+        expected,
+        actual: report.len(),
+      });
+    }
+    if report.len() > expected {
+      return Err(Error::ReportTooLong {
+        expected,
+        actual: report.len(),
+      });
+    }
+
+    let report_id = report[0];
+    let button_usage_pages = self.button_usage_pages.get(&report_id);
+    let mut value_caps = self
+      .value_caps
+      .iter()
+      .filter(|caps| caps.report_id == report_id)
+      .peekable();
+    if button_usage_pages.is_none() && value_caps.peek().is_none() {
+      return Err(Error::Io(io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("unknown input report ID {}", report_id),
+      )));
+    }
+
+    let report_type = HidReportType::Input.to_native();
+    let mut parsed = ParsedReport {
+      report_id,
+      ..Default::default()
+    };
+    for &usage_page in button_usage_pages.into_iter().flatten() {
+      let usages = hid_p_get_usages(report_type, usage_page, &self.preparsed_data, report)?;
+      parsed.buttons.insert(usage_page, usages);
+    }
+    for caps in value_caps {
+      for usage in caps.usage_min..=caps.usage_max {
+        let value = hid_p_get_usage_value(
+          report_type,
+          caps.usage_page,
+          caps.link_collection,
+          usage,
+          &self.preparsed_data,
+          report,
+        )?;
+        parsed
+          .values
+          .entry(caps.usage_page)
+          .or_default()
+          .push(UsageValue {
+            link_collection: caps.link_collection,
+            usage,
+            value,
+          });
+      }
+    }
+
+    Ok(parsed)
+  }
+
+  /// Reads the value of `usage` in the input report `report`, scaled from its
+  /// logical range to its physical range, e.g. degrees for a joystick axis.
+  ///
+  /// Values without a physical range are returned unscaled. A value in its
+  /// null state, outside its logical range, gives `Error::NullValue`.
+  pub fn get_scaled_value(&self, usage_page: u16, usage: u16, report: &[u8]) -> Result<i32, Error> {
+    let report_id = report.first().copied().unwrap_or(0);
+    let caps = self
+      .value_caps
+      .iter()
+      .find(|caps| {
+        caps.report_id == report_id
+          && caps.usage_page == usage_page
+          && (caps.usage_min..=caps.usage_max).contains(&usage)
+      })
+      .ok_or(Error::UsageNotFound { usage_page, usage })?;
+
+    let report_type = HidReportType::Input.to_native();
+    match hid_p_get_scaled_usage_value(
+      report_type,
+      usage_page,
+      caps.link_collection,
+      usage,
+      &self.preparsed_data,
+      report,
+    ) {
+      Ok(value) => Ok(value),
+      Err(HIDP_STATUS_NULL) => Err(Error::NullValue { usage_page, usage }),
+      Err(HIDP_STATUS_BAD_LOG_PHY_VALUES) => {
+        let raw = hid_p_get_usage_value(
+          report_type,
+          usage_page,
+          caps.link_collection,
+          usage,
+          &self.preparsed_data,
+          report,
+        )?;
+        scale_value(caps, raw).ok_or(Error::NullValue { usage_page, usage })
+      }
+      Err(status) => Err(hid_p_status_to_error("HidP_GetScaledUsageValue", status).into()),
+    }
+  }
+
+  /// Compares the buttons in two parsed reports, e.g. consecutive keyboard
+  /// reports, to tell which were pressed and which released in between.
+  pub fn diff_usages(prev: &ParsedReport, next: &ParsedReport) -> UsageDiff {
+    let rollover = next
+      .buttons
+      .get(&KEYBOARD_PAGE)
+      .is_some_and(|usages| usages.contains(&ERROR_ROLL_OVER));
+    if rollover {
+      return UsageDiff {
+        rollover,
+        ..Default::default()
+      };
+    }
+
+    // Usages only in `a`, by usage page.
+    let difference = |a: &BTreeMap<u16, Vec<u16>>, b: &BTreeMap<u16, Vec<u16>>| {
+      let mut result = BTreeMap::new();
+      for (usage_page, usages) in a {
+        let others = b.get(usage_page).map_or(&[][..], Vec::as_slice);
+        let only: Vec<u16> = usages
+          .iter()
+          .copied()
+          .filter(|usage| !others.contains(usage))
+          .collect();
+        if !only.is_empty() {
+          result.insert(*usage_page, only);
+        }
+      }
+      result
+    };
+
+    UsageDiff {
+      pressed: difference(&next.buttons, &prev.buttons),
+      released: difference(&prev.buttons, &next.buttons),
+      rollover,
+    }
+  }
+
+  /// The length of the reports `parse_input` accepts.
+  pub fn input_report_byte_length(&self) -> usize {
+    self.input_report_byte_length
+  }
+}
+
+/// Scales the raw report bits of a value linearly from its logical range to
+/// its physical range, as `HidP_GetScaledUsageValue` does, or leaves it
+/// unscaled without a usable physical range. Returns `None` for a value
+/// outside its logical range, i.e. in its null state.
+fn scale_value(caps: &ValueCap, raw: u32) -> Option<i32> {
+  let logical_min = caps.logical_min as i64;
+  let logical_max = caps.logical_max as i64;
+
+  // Sign-extend the field if its logical range is signed.
+  let bit_size = caps.bit_size.clamp(1, 32) as u32;
+  let logical = if logical_min < 0 && bit_size < 32 && raw & (1 << (bit_size - 1)) != 0 {
+    raw as i64 - (1i64 << bit_size)
+  } else if logical_min < 0 {
+    raw as i32 as i64
+  } else {
+    raw as i64
+  };
+  if logical < logical_min || logical > logical_max {
+    return None;
+  }
+
+  let physical_min = caps.physical_min as i64;
+  let physical_max = caps.physical_max as i64;
+  if logical_max == logical_min || physical_max == physical_min {
+    return Some(logical as i32);
+  }
+
+  let physical = (logical - logical_min) * (physical_max - physical_min)
+    / (logical_max - logical_min)
+    + physical_min;
+  Some(physical as i32)
+}
+
+/// Translates keyboard page usages, e.g. from `UsageDiff::pressed`, to the
+/// scan code set 1 codes a PS/2 keyboard sends when the keys are pressed, or
+/// released if `pressed` is false. Extended keys such as the arrows get the
+/// `0xE0` prefix, e.g. `[0xE0, 0x48]` for Up Arrow.
+///
+/// Fails with `ErrorKind::Other` for usages that have no scan code.
+pub fn translate_to_scancodes(usages: &[u16], pressed: bool) -> Result<Vec<u8>, Error> {
+  Ok(hid_p_translate_usages_to_i8042_scan_codes(usages, pressed)?)
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Setting {
+  Usage {
+    usage_page: u16,
+    usage: u16,
+    on: bool,
+  },
+  Value {
+    usage_page: u16,
+    usage: u16,
+    value: u32,
+  },
+}
+
+/// Builds an output or feature report from usages rather than bits.
+///
+/// ```no_run
+/// # let device = listhid::list_hid_device()?.remove(0);
+/// let mut opened = device.open()?;
+/// let report = listhid::ReportBuilder::new(0)
+///   .set_usage(0x08, 0x01, true)
+///   .set_value(0xff00, 0x01, 512)
+///   .build(&opened)?;
+/// opened.write(&report)?;
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct ReportBuilder {
+  report_type: HidReportType,
+  report_id: u8,
+  settings: Vec<Setting>,
+}
+
+impl ReportBuilder {
+  /// Starts an output report with ID `report_id`, 0 for devices that don't
+  /// use report IDs.
+  pub fn new(report_id: u8) -> Self {
+    ReportBuilder {
+      report_type: HidReportType::Output,
+      report_id,
+      settings: Vec::new(),
+    }
+  }
+
+  /// Builds a report of `report_type` instead, e.g. a feature report.
+  pub fn report_type(mut self, report_type: HidReportType) -> Self {
+    self.report_type = report_type;
+    self
+  }
+
+  /// Sets or clears the button `usage`.
+  pub fn set_usage(mut self, usage_page: u16, usage: u16, on: bool) -> Self {
+    self.settings.push(Setting::Usage {
+      usage_page,
+      usage,
+      on,
+    });
+    self
+  }
+
+  /// Sets the raw value of `usage`.
+  pub fn set_value(mut self, usage_page: u16, usage: u16, value: u32) -> Self {
+    self.settings.push(Setting::Value {
+      usage_page,
+      usage,
+      value,
+    });
+    self
+  }
+
+  /// Builds the report for `device`, sized to its report byte length. Usages
+  /// that aren't in the report give `Error::UsageNotFound`.
+  pub fn build(&self, device: &OpenHidDevice) -> Result<Vec<u8>, Error> {
+    let length = match self.report_type {
+      HidReportType::Input => device.input_report_byte_length(),
+      HidReportType::Output => device.output_report_byte_length(),
+      HidReportType::Feature => device.feature_report_byte_length(),
+    };
+    if length == 0 && self.report_type == HidReportType::Output {
+      return Err(Error::NoOutputReports);
+    }
+
+    let report_type = self.report_type.to_native();
+    let preparsed_data = device.preparsed_data();
+    let mut report = vec![0u8; length];
+    hid_p_initialize_report_for_id(report_type, self.report_id, preparsed_data, &mut report)?;
+
+    for setting in &self.settings {
+      let (usage_page, usage, result) = match *setting {
+        Setting::Usage {
+          usage_page,
+          usage,
+          on,
+        } => (
+          usage_page,
+          usage,
+          hid_p_set_usage(
+            report_type,
+            usage_page,
+            usage,
+            on,
+            preparsed_data,
+            &mut report,
+          ),
+        ),
+        Setting::Value {
+          usage_page,
+          usage,
+          value,
+        } => (
+          usage_page,
+          usage,
+          hid_p_set_usage_value(
+            report_type,
+            usage_page,
+            usage,
+            value,
+            preparsed_data,
+            &mut report,
+          ),
+        ),
+      };
+      match result {
+        Ok(()) => {}
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+          return Err(Error::UsageNotFound { usage_page, usage })
+        }
+        Err(e) => return Err(e.into()),
+      }
+    }
+
+    Ok(report)
+  }
+}
+
+impl OpenHidDevice {
+  /// Creates a `ReportParser` for the device's input reports.
+  pub fn report_parser(&self) -> Result<ReportParser, Error> {
+    ReportParser::new(self)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  /// A Generic Desktop value in the first collection of a flight stick,
+  /// with a null state and degrees as the unit if `has_null`, as hat
+  /// switches have.
+  fn flight_stick_value(
+    usage: u16,
+    bit_size: u16,
+    has_null: bool,
+    logical: (i32, i32),
+    physical: (i32, i32),
+  ) -> ValueCap {
+    ValueCap {
+      usage_page: 0x01,
+      report_id: 0,
+      is_alias: false,
+      bit_field: if has_null { 0x42 } else { 0x02 },
+      link_collection: 1,
+      link_usage: 0x01,
+      link_usage_page: 0x01,
+      is_range: false,
+      is_absolute: true,
+      has_null,
+      bit_size,
+      report_count: 1,
+      units_exp: 0,
+      units: if has_null { 0x14 } else { 0 },
+      logical_min: logical.0,
+      logical_max: logical.1,
+      physical_min: physical.0,
+      physical_max: physical.1,
+      usage_min: usage,
+      usage_max: usage,
+      data_index_min: 0,
+      data_index_max: 0,
+    }
+  }
+
+  #[test]
+  fn scale_value_maps_the_logical_range_onto_the_physical_one() {
+    // The hat switch: eight directions in 45 degree steps.
+    let hat = flight_stick_value(0x39, 4, true, (0, 7), (0, 315));
+    assert_eq!(scale_value(&hat, 0), Some(0));
+    assert_eq!(scale_value(&hat, 2), Some(90));
+    assert_eq!(scale_value(&hat, 7), Some(315));
+  }
+
+  #[test]
+  fn scale_value_reads_values_outside_the_logical_range_as_null() {
+    let hat = flight_stick_value(0x39, 4, true, (0, 7), (0, 315));
+    // The hat sends 8 (or any out-of-range value) when centered.
+    assert_eq!(scale_value(&hat, 8), None);
+    assert_eq!(scale_value(&hat, 0xf), None);
+
+    let x = flight_stick_value(0x30, 10, false, (0, 1023), (0, 1023));
+    assert_eq!(scale_value(&x, 1024), None);
+  }
+
+  #[test]
+  fn scale_value_sign_extends_signed_fields() {
+    // A twist axis reported as a signed byte.
+    let rz = flight_stick_value(0x35, 8, false, (-127, 127), (-45, 45));
+    assert_eq!(scale_value(&rz, 0x81), Some(-45));
+    assert_eq!(scale_value(&rz, 0x00), Some(0));
+    assert_eq!(scale_value(&rz, 0x7f), Some(45));
+    // -128 is below the logical minimum.
+    assert_eq!(scale_value(&rz, 0x80), None);
+  }
+
+  #[test]
+  fn scale_value_leaves_values_without_a_physical_range_unscaled() {
+    let throttle = flight_stick_value(0x36, 8, false, (0, 255), (0, 0));
+    assert_eq!(scale_value(&throttle, 200), Some(200));
+  }
+
+  #[test]
+  fn translates_keyboard_usages_to_set_1_scan_codes() {
+    const MAKE_CODES: [(u16, &[u8]); 24] = [
+      (0x04, &[0x1e]),       // A
+      (0x05, &[0x30]),       // B
+      (0x1d, &[0x2c]),       // Z
+      (0x1e, &[0x02]),       // 1
+      (0x27, &[0x0b]),       // 0
+      (0x28, &[0x1c]),       // Enter
+      (0x29, &[0x01]),       // Escape
+      (0x2a, &[0x0e]),       // Backspace
+      (0x2b, &[0x0f]),       // Tab
+      (0x2c, &[0x39]),       // Space
+      (0x3a, &[0x3b]),       // F1
+      (0x45, &[0x58]),       // F12
+      (0x49, &[0xe0, 0x52]), // Insert
+      (0x4a, &[0xe0, 0x47]), // Home
+      (0x4b, &[0xe0, 0x49]), // Page Up
+      (0x4c, &[0xe0, 0x53]), // Delete
+      (0x4f, &[0xe0, 0x4d]), // Right Arrow
+      (0x50, &[0xe0, 0x4b]), // Left Arrow
+      (0x51, &[0xe0, 0x50]), // Down Arrow
+      (0x52, &[0xe0, 0x48]), // Up Arrow
+      (0x54, &[0xe0, 0x35]), // Keypad /
+      (0x58, &[0xe0, 0x1c]), // Keypad Enter
+      (0x59, &[0x4f]),       // Keypad 1
+      (0x62, &[0x52]),       // Keypad 0
+    ];
+    for &(usage, make) in MAKE_CODES.iter() {
+      assert_eq!(
+        translate_to_scancodes(&[usage], true).unwrap(),
+        make,
+        "usage {:#04x}",
+        usage
+      );
+      // Break codes set the top bit of the code after any prefix.
+      let mut break_codes = make.to_vec();
+      *break_codes.last_mut().unwrap() |= 0x80;
+      assert_eq!(
+        translate_to_scancodes(&[usage], false).unwrap(),
+        break_codes,
+        "usage {:#04x}",
+        usage
+      );
+    }
+  }
+
+  fn report(buttons: &[(u16, &[u16])]) -> ParsedReport {
+    ParsedReport {
+      report_id: 0,
+      buttons: buttons
+        .iter()
+        .map(|(usage_page, usages)| (*usage_page, usages.to_vec()))
+        .collect(),
+      values: BTreeMap::new(),
+    }
+  }
+
+  #[test]
+  fn diff_usages_reports_pressed_and_released_by_page() {
+    // Keyboard keys A and B down, and one consumer control button.
+    let prev = report(&[(0x07, &[0x04, 0x05]), (0x0c, &[0xe9])]);
+    // B released, C pressed, and the consumer control button released.
+    let next = report(&[(0x07, &[0x05, 0x06]), (0x09, &[0x01])]);
+    let diff = ReportParser::diff_usages(&prev, &next);
+    assert_eq!(
+      diff.pressed,
+      report(&[(0x07, &[0x06]), (0x09, &[0x01])]).buttons
+    );
+    assert_eq!(
+      diff.released,
+      report(&[(0x07, &[0x04]), (0x0c, &[0xe9])]).buttons
+    );
+    assert!(!diff.rollover);
+  }
+
+  #[test]
+  fn diff_usages_of_an_unchanged_report_is_empty() {
+    let keys = report(&[(0x07, &[0xe1, 0x04]), (0x0c, &[0xe9])]);
+    assert_eq!(
+      ReportParser::diff_usages(&keys, &keys),
+      UsageDiff::default()
+    );
+  }
+
+  #[test]
+  fn diff_usages_flags_error_rollover_without_changes() {
+    let prev = report(&[(0x07, &[0x04])]);
+    let next = report(&[(0x07, &[0x01, 0x01, 0x01, 0x01, 0x01, 0x01])]);
+    let diff = ReportParser::diff_usages(&prev, &next);
+    assert!(diff.rollover);
+    assert!(diff.pressed.is_empty());
+    assert!(diff.released.is_empty());
+  }
+}
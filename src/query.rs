@@ -0,0 +1,346 @@
+use crate::win32::{
+  get_interface_ids, get_interface_usage, setup_di_get_class_devs, setup_di_get_device_instance_id,
+  setup_di_get_device_interface_detail, string_to_lpcwstr,
+};
+use crate::{
+  build_device_data, needs_open, parse_ids_from_path, read_device_info, read_opened_device,
+  DetailLevel, EnumOptions, HidDevice, ListHidError, OpenAccess, SkippedDevice,
+};
+use std::sync::Mutex;
+use std::{ptr, thread};
+use winapi::shared::guiddef::{IsEqualGUID, GUID};
+use winapi::shared::hidclass::GUID_DEVINTERFACE_HID;
+use winapi::um::setupapi::{DIGCF_ALLCLASSES, DIGCF_DEVICEINTERFACE, DIGCF_PRESENT};
+
+/// The filters on a device's IDs and usage, shared by `HidQuery` and
+/// `DeviceWatcherBuilder` so both match devices the same way.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct DeviceFilter {
+  pub(crate) vendor_id: Option<u16>,
+  pub(crate) product_id: Option<u16>,
+  pub(crate) usage: Option<(u16, u16)>,
+}
+
+impl DeviceFilter {
+  /// Whether any filter is set.
+  pub(crate) fn is_empty(&self) -> bool {
+    *self == DeviceFilter::default()
+  }
+
+  /// Whether a device with the vendor and product IDs `ids` and the usage
+  /// page and usage `usage` passes. Data that isn't known, `None`, passes,
+  /// as it can't rule the device out.
+  pub(crate) fn matches(&self, ids: Option<(u16, u16)>, usage: Option<(u16, u16)>) -> bool {
+    let matches = |expected: Option<u16>, actual: u16| match expected {
+      Some(expected) => expected == actual,
+      None => true,
+    };
+    let ids_match = match ids {
+      Some((vendor_id, product_id)) => {
+        matches(self.vendor_id, vendor_id) && matches(self.product_id, product_id)
+      }
+      None => true,
+    };
+    let usage_match = match (self.usage, usage) {
+      (Some(expected), Some(actual)) => expected == actual,
+      _ => true,
+    };
+    ids_match && usage_match
+  }
+}
+
+/// Builder for listing the HID devices that match a set of filters.
+///
+/// Filters are evaluated against SetupAPI data before a device is opened, so
+/// they also apply to devices whose handle cannot be opened.
+#[derive(Debug, Default, Clone)]
+pub struct HidQuery {
+  instance_id_prefix: Option<String>,
+  include_non_present: bool,
+  filter: DeviceFilter,
+  interface_class: Option<GUID>,
+  exclude_redirected: bool,
+  detail_level: DetailLevel,
+  options: EnumOptions,
+}
+
+/// Uppercases an instance ID and collapses its separators into single
+/// backslashes, so `hid/vid_046d` and `HID\\VID_046D` compare equal.
+fn normalize_instance_id(instance_id: &str) -> String {
+  let mut normalized = String::with_capacity(instance_id.len());
+  for c in instance_id.chars() {
+    let c = if c == '/' { '\\' } else { c };
+    if c == '\\' && normalized.ends_with('\\') {
+      continue;
+    }
+    normalized.extend(c.to_uppercase());
+  }
+  normalized
+}
+
+impl HidQuery {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Only matches devices whose device instance ID starts with `prefix`,
+  /// e.g. `HID\VID_046D&PID_C534`. The comparison is case-insensitive.
+  pub fn instance_id_prefix(mut self, prefix: &str) -> Self {
+    self.instance_id_prefix = Some(normalize_instance_id(prefix));
+    self
+  }
+
+  /// Also lists devices that are not currently connected. Their last arrival
+  /// and removal times are still known, but they cannot be opened, so only
+  /// the data available from SetupAPI and the interface path is filled in.
+  pub fn include_non_present(mut self, include_non_present: bool) -> Self {
+    self.include_non_present = include_non_present;
+    self
+  }
+
+  /// Only matches devices whose top-level collection has the given usage page
+  /// and usage, e.g. `(0x01, 0x06)` for keyboards.
+  ///
+  /// The usage is read from the interface properties where available, so
+  /// non-matching devices are usually skipped without being opened.
+  pub fn usage(mut self, usage_page: u16, usage: u16) -> Self {
+    self.filter.usage = Some((usage_page, usage));
+    self
+  }
+
+  /// Only matches devices with the given vendor ID.
+  ///
+  /// Like `usage`, the ID is read from the interface properties or the path
+  /// where available, so non-matching devices are usually skipped without
+  /// being opened.
+  pub fn vendor_id(mut self, vendor_id: u16) -> Self {
+    self.filter.vendor_id = Some(vendor_id);
+    self
+  }
+
+  /// Only matches devices with the given product ID, see `vendor_id`.
+  pub fn product_id(mut self, product_id: u16) -> Self {
+    self.filter.product_id = Some(product_id);
+    self
+  }
+
+  /// Enumerates the device interface class `guid` instead of
+  /// `GUID_DEVINTERFACE_HID`.
+  pub fn interface_class(mut self, guid: GUID) -> Self {
+    self.interface_class = Some(guid);
+    self
+  }
+
+  /// Leaves out devices redirected from a Remote Desktop client, see
+  /// `HidDevice::is_redirected`.
+  pub fn exclude_redirected(mut self) -> Self {
+    self.exclude_redirected = true;
+    self
+  }
+
+  /// Controls how much data is gathered for each device; `DetailLevel::Full`
+  /// by default. Lower levels don't open the devices and are much faster.
+  pub fn detail_level(mut self, detail_level: DetailLevel) -> Self {
+    self.detail_level = detail_level;
+    self
+  }
+
+  /// Sets the options for the SetupAPI scan, see `EnumOptions`.
+  pub fn options(mut self, options: EnumOptions) -> Self {
+    self.options = options;
+    self
+  }
+
+  fn matches_instance_id(&self, instance_id: Option<&str>) -> bool {
+    match (&self.instance_id_prefix, instance_id) {
+      (None, _) => true,
+      (Some(_), None) => false,
+      (Some(prefix), Some(instance_id)) => normalize_instance_id(instance_id).starts_with(prefix),
+    }
+  }
+
+  pub fn list(&self) -> Result<Vec<HidDevice>, ListHidError> {
+    self.list_with_skipped().map(|(devices, _)| devices)
+  }
+
+  /// Same as `list`, but also returns the matching devices that were skipped
+  /// because opening or querying them failed.
+  pub fn list_with_skipped(&self) -> Result<(Vec<HidDevice>, Vec<SkippedDevice>), ListHidError> {
+    #[cfg(feature = "tracing")]
+    {
+      let span = tracing::info_span!(
+        "list_hid_device",
+        device_count = tracing::field::Empty,
+        elapsed_ms = tracing::field::Empty,
+      );
+      let _entered = span.enter();
+      let started = std::time::Instant::now();
+
+      let result = self.enumerate();
+      if let Ok((devices, _)) = &result {
+        span.record("device_count", devices.len());
+      }
+      span.record("elapsed_ms", started.elapsed().as_millis() as u64);
+      result
+    }
+    #[cfg(not(feature = "tracing"))]
+    self.enumerate()
+  }
+
+  /// Whether `device` passes the filters that need data read from it.
+  fn keeps(&self, device: &HidDevice) -> bool {
+    // Without the details, the IDs are zero rather than unknown.
+    let ids = match device.detail_level {
+      DetailLevel::PathsOnly => None,
+      _ => Some((device.vendor_id, device.product_id)),
+    };
+    self
+      .filter
+      .matches(ids, device.usage_page.zip(device.usage))
+      && !(self.exclude_redirected && device.is_redirected)
+  }
+
+  fn enumerate(&self) -> Result<(Vec<HidDevice>, Vec<SkippedDevice>), ListHidError> {
+    let mut flags = DIGCF_ALLCLASSES | DIGCF_DEVICEINTERFACE;
+    if !self.include_non_present {
+      flags |= DIGCF_PRESENT;
+    }
+    let enumerator = self.options.enumerator.as_deref().map(string_to_lpcwstr);
+    let class_devs_info = unsafe {
+      setup_di_get_class_devs(
+        ptr::null(),
+        enumerator
+          .as_ref()
+          .map_or(ptr::null(), |enumerator| enumerator.as_ptr()),
+        ptr::null_mut(),
+        flags,
+      )
+    }?;
+
+    let mut devices = Vec::new();
+    let mut skipped = Vec::new();
+    // Present devices still to be opened, with their wide paths.
+    let mut pending = Vec::new();
+
+    let interface_guid = self.interface_class.unwrap_or(GUID_DEVINTERFACE_HID);
+    let is_hid = IsEqualGUID(&interface_guid, &GUID_DEVINTERFACE_HID);
+
+    // Shared by every device to save allocating buffers for each of them.
+    let mut scratch = Vec::new();
+    for mut device_data in build_device_data(&class_devs_info, &interface_guid)? {
+      let mut device_interface_detail = unsafe {
+        setup_di_get_device_interface_detail(
+          &class_devs_info,
+          &mut device_data.interface_data,
+          &mut scratch,
+        )
+      }?;
+
+      let instance_id = setup_di_get_device_instance_id(
+        &class_devs_info,
+        &mut device_interface_detail.device_info_data,
+      )
+      .ok();
+      if !self.matches_instance_id(instance_id.as_deref()) {
+        continue;
+      }
+      if !self.filter.is_empty() {
+        let ids = get_interface_ids(&class_devs_info, device_data.interface_data)
+          .or_else(|| parse_ids_from_path(&device_interface_detail.device_path));
+        let usage = get_interface_usage(&class_devs_info, device_data.interface_data);
+        if !self.filter.matches(ids, usage) {
+          continue;
+        }
+      }
+
+      let device = read_device_info(
+        &class_devs_info,
+        &device_data,
+        &device_interface_detail,
+        instance_id,
+        self.detail_level,
+        &mut scratch,
+      );
+      if needs_open(&device_data, self.detail_level) {
+        pending.push((device, device_interface_detail.device_path_wide));
+      } else if self.keeps(&device) {
+        devices.push(device);
+      }
+    }
+
+    for (device, result) in read_opened_devices(
+      pending,
+      is_hid,
+      self.options.open_access,
+      self.options.max_concurrency,
+    ) {
+      match result {
+        Ok(()) => {
+          if self.keeps(&device) {
+            devices.push(device);
+          }
+        }
+        Err(error) if self.options.strict => return Err(error),
+        Err(error) => skipped.push(SkippedDevice {
+          path: device.path,
+          error,
+        }),
+      }
+    }
+
+    devices.sort();
+    Ok((devices, skipped))
+  }
+}
+
+/// Runs `read_opened_device` on each of the `pending` devices, on up to
+/// `max_concurrency` threads, and returns them with the results in their
+/// original order.
+fn read_opened_devices(
+  pending: Vec<(HidDevice, Vec<u16>)>,
+  is_hid: bool,
+  open_access: OpenAccess,
+  max_concurrency: usize,
+) -> Vec<(HidDevice, Result<(), ListHidError>)> {
+  let workers = max_concurrency.min(pending.len());
+  if workers <= 1 {
+    return pending
+      .into_iter()
+      .map(|(mut device, path_wide)| {
+        let result = read_opened_device(&mut device, &path_wide, is_hid, open_access);
+        (device, result)
+      })
+      .collect();
+  }
+
+  let queue = Mutex::new(pending.into_iter().enumerate());
+  let results = Mutex::new(Vec::new());
+  // The workers start without a current span; entering the listing's keeps
+  // their `open_device` spans inside it.
+  #[cfg(feature = "tracing")]
+  let span = tracing::Span::current();
+  thread::scope(|scope| {
+    for _ in 0..workers {
+      scope.spawn(|| {
+        #[cfg(feature = "tracing")]
+        let _entered = span.enter();
+        loop {
+          let next = queue.lock().unwrap().next();
+          let (index, (mut device, path_wide)) = match next {
+            Some(next) => next,
+            None => break,
+          };
+          let result = read_opened_device(&mut device, &path_wide, is_hid, open_access);
+          results.lock().unwrap().push((index, device, result));
+        }
+      });
+    }
+  });
+
+  let mut results = results.into_inner().unwrap();
+  results.sort_by_key(|(index, _, _)| *index);
+  results
+    .into_iter()
+    .map(|(_, device, result)| (device, result))
+    .collect()
+}
@@ -0,0 +1,24 @@
+//! The thin Win32 wrappers listhid is built on, for building your own
+//! enumeration or I/O where the rest of the crate doesn't fit.
+//!
+//! This is an escape hatch for power users. The wrappers stay close to the
+//! functions they wrap: several take raw pointers and handles that must be
+//! valid for the call, `Handle::native_handle` can be set to any `HANDLE`,
+//! which is then closed on drop, and none of the checks the rest of the
+//! crate does on report lengths or device state are made. Errors are the
+//! plain `io::Error` from `GetLastError` or the `HIDP_STATUS` code.
+
+pub use crate::win32::{
+  cancel_io, create_event, create_file, create_file_wide, decode_device_property,
+  hid_d_get_attributes, hid_d_get_feature, hid_d_get_input_report, hid_d_get_manufacturer_string,
+  hid_d_get_manufacturer_string_result, hid_d_get_num_input_buffers, hid_d_get_physical_descriptor,
+  hid_d_get_preparsed_data, hid_d_get_product_string, hid_d_get_product_string_result,
+  hid_d_get_serial_number_string, hid_d_get_serial_number_string_result, hid_d_set_feature,
+  hid_d_set_num_input_buffers, hid_d_set_output_report, hid_p_get_button_caps, hid_p_get_caps,
+  hid_p_get_value_caps, read_file, read_file_overlapped, setup_di_create_device_info_list,
+  setup_di_enum_device_info, setup_di_enum_device_interfaces, setup_di_get_class_devs,
+  setup_di_get_class_devs_ex, setup_di_get_device_instance_id,
+  setup_di_get_device_interface_detail, setup_di_open_device_interface, string_to_lpcwstr,
+  write_file, write_file_overlapped, DevPropValue, DeviceInterfaceDetail, HDevInfo, Handle,
+  PreparsedData,
+};
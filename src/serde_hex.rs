@@ -0,0 +1,104 @@
+//! Serializes a 16-bit ID, such as a vendor or product ID, as a zero-padded
+//! uppercase hex string, e.g. `"046D"`, for `#[serde(with = "...")]`.
+//!
+//! Deserializing accepts the hex string, with or without a `0x` prefix and in
+//! either case, as well as a plain number, so data written either way reads
+//! back. `HidDevice` reads its IDs with it whatever the features.
+//!
+//! Formats that aren't human-readable, such as bincode, can't tell a number
+//! from a string without a hint, so the ID is a plain `u16` there both ways.
+//!
+//! ```
+//! #[derive(serde::Serialize, serde::Deserialize)]
+//! struct Allowed {
+//!   #[serde(with = "listhid::serde_hex")]
+//!   vendor_id: u16,
+//! }
+//! ```
+
+use serde::de::{self, Deserializer, Unexpected, Visitor};
+use serde::Serializer;
+use std::convert::TryFrom;
+use std::fmt;
+
+pub fn serialize<S: Serializer>(id: &u16, serializer: S) -> Result<S::Ok, S::Error> {
+  if serializer.is_human_readable() {
+    serializer.serialize_str(&format!("{:04X}", id))
+  } else {
+    serializer.serialize_u16(*id)
+  }
+}
+
+pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u16, D::Error> {
+  if deserializer.is_human_readable() {
+    deserializer.deserialize_any(IdVisitor)
+  } else {
+    deserializer.deserialize_u16(IdVisitor)
+  }
+}
+
+struct IdVisitor;
+
+impl<'de> Visitor<'de> for IdVisitor {
+  type Value = u16;
+
+  fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+    formatter.write_str("a 16-bit ID, as a number or a hex string")
+  }
+
+  fn visit_u64<E: de::Error>(self, value: u64) -> Result<u16, E> {
+    u16::try_from(value).map_err(|_| E::invalid_value(Unexpected::Unsigned(value), &self))
+  }
+
+  fn visit_i64<E: de::Error>(self, value: i64) -> Result<u16, E> {
+    u16::try_from(value).map_err(|_| E::invalid_value(Unexpected::Signed(value), &self))
+  }
+
+  fn visit_str<E: de::Error>(self, value: &str) -> Result<u16, E> {
+    let digits = value
+      .strip_prefix("0x")
+      .or_else(|| value.strip_prefix("0X"))
+      .unwrap_or(value);
+    u16::from_str_radix(digits, 16).map_err(|_| E::invalid_value(Unexpected::Str(value), &self))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  #[derive(Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+  struct Ids {
+    #[serde(with = "super")]
+    vendor_id: u16,
+  }
+
+  #[test]
+  fn serializes_zero_padded_uppercase_hex() {
+    let json = serde_json::to_string(&Ids { vendor_id: 0x46d }).unwrap();
+    assert_eq!(json, r#"{"vendor_id":"046D"}"#);
+  }
+
+  #[test]
+  fn deserializes_hex_strings_and_numbers() {
+    for json in [
+      r#"{"vendor_id":"046D"}"#,
+      r#"{"vendor_id":"046d"}"#,
+      r#"{"vendor_id":"0x046D"}"#,
+      r#"{"vendor_id":1133}"#,
+    ] {
+      let ids: Ids = serde_json::from_str(json).unwrap();
+      assert_eq!(ids, Ids { vendor_id: 0x46d }, "{}", json);
+    }
+  }
+
+  #[test]
+  fn rejects_ids_out_of_range() {
+    for json in [
+      r#"{"vendor_id":"10000"}"#,
+      r#"{"vendor_id":65536}"#,
+      r#"{"vendor_id":-1}"#,
+      r#"{"vendor_id":"vid"}"#,
+    ] {
+      assert!(serde_json::from_str::<Ids>(json).is_err(), "{}", json);
+    }
+  }
+}
@@ -0,0 +1,751 @@
+use crate::debounce::Debouncer;
+use crate::query::DeviceFilter;
+use crate::watcher_core::WatcherCore;
+use crate::win32::{
+  cm_notifications_supported, cm_register_interface_notification, get_interface_ids,
+  get_interface_usage, notification_interface_path, setup_di_create_device_info_list,
+  setup_di_open_device_interface, string_to_lpcwstr, Notification,
+};
+use crate::{
+  get_device_by_path, list_hid_device, parse_ids_from_path, Error, HidDevice, ListHidError,
+};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use std::{io, mem, ptr, slice};
+use winapi::shared::hidclass::GUID_DEVINTERFACE_HID;
+use winapi::shared::minwindef::{DWORD, LPARAM, LPVOID, LRESULT, TRUE, UINT, WPARAM};
+use winapi::shared::ntdef::{HANDLE, PVOID, WCHAR};
+use winapi::shared::windef::HWND;
+use winapi::shared::winerror::{ERROR_CLASS_ALREADY_EXISTS, ERROR_SUCCESS};
+use winapi::um::cfgmgr32::{
+  CM_NOTIFY_ACTION, CM_NOTIFY_ACTION_DEVICEINTERFACEARRIVAL,
+  CM_NOTIFY_ACTION_DEVICEINTERFACEREMOVAL, HCMNOTIFICATION, PCM_NOTIFY_EVENT_DATA,
+};
+use winapi::um::dbt::{
+  DBT_DEVICEARRIVAL, DBT_DEVICEREMOVECOMPLETE, DBT_DEVTYP_DEVICEINTERFACE,
+  DEV_BROADCAST_DEVICEINTERFACE_W, DEV_BROADCAST_HDR,
+};
+use winapi::um::errhandlingapi::GetLastError;
+use winapi::um::libloaderapi::GetModuleHandleW;
+use winapi::um::winuser::{
+  CreateWindowExW, DefWindowProcW, DestroyWindow, DispatchMessageW, GetMessageW, GetWindowLongPtrW,
+  PostMessageW, PostQuitMessage, RegisterClassExW, RegisterDeviceNotificationW, SetWindowLongPtrW,
+  UnregisterDeviceNotification, DEVICE_NOTIFY_WINDOW_HANDLE, GWLP_USERDATA, HWND_MESSAGE, MSG,
+  WM_CLOSE, WM_DESTROY, WM_DEVICECHANGE, WNDCLASSEXW,
+};
+
+/// A HID interface coming or going, with its path.
+#[derive(Debug, Clone)]
+pub enum HidEvent {
+  Arrived(String),
+  /// Sent instead of `Arrived` by watchers built with
+  /// `DeviceWatcherBuilder::resolve_devices`: the new device, read in full.
+  ArrivedDevice(Box<HidDevice>),
+  /// Sent instead of `Arrived` by watchers built with
+  /// `DeviceWatcherBuilder::resolve_devices` when the new device couldn't be
+  /// read, with the last error.
+  ArrivedUnresolved(String, Arc<Error>),
+  Removed(String),
+  /// Only sent to `DeviceWatcher::events` receivers: this many events were
+  /// dropped because the receiver's channel was full.
+  Lagged(u64),
+}
+
+/// How many events `DeviceWatcher::events` buffers for a receiver.
+const DEFAULT_EVENTS_CAPACITY: usize = 64;
+
+/// A receiver returned by `DeviceWatcher::events`.
+struct Subscriber {
+  sender: SyncSender<HidEvent>,
+  // Events dropped since the last one that was sent.
+  lagged: u64,
+}
+
+impl Subscriber {
+  /// Sends `event`, or counts it as dropped if the channel is full. Returns
+  /// false once the receiver is gone.
+  fn send(&mut self, event: HidEvent) -> bool {
+    if self.lagged > 0 {
+      match self.sender.try_send(HidEvent::Lagged(self.lagged)) {
+        Ok(()) => self.lagged = 0,
+        Err(TrySendError::Full(_)) => {
+          self.lagged += 1;
+          return true;
+        }
+        Err(TrySendError::Disconnected(_)) => return false,
+      }
+    }
+    match self.sender.try_send(event) {
+      Ok(()) => true,
+      Err(TrySendError::Full(_)) => {
+        self.lagged += 1;
+        true
+      }
+      Err(TrySendError::Disconnected(_)) => false,
+    }
+  }
+}
+
+/// Watches for HID interfaces being added and removed, e.g. devices being
+/// plugged in and unplugged, and calls a callback with each change.
+///
+/// Windows delivers the notifications on its thread pool; they are passed on
+/// to a thread owned by the watcher, which calls the callback one at a time.
+/// The callback can therefore block or call back into this crate, e.g. to
+/// look up the new device with `get_device_by_path`, without holding up or
+/// deadlocking the notifications. `stop`, or dropping the watcher, stops the
+/// notifications and waits for the callback to return; from within the
+/// callback it can't wait, but the callback isn't called again either.
+///
+/// Events can also be received over channels from `events`, e.g. with a
+/// callback that does nothing.
+///
+/// Needs Windows 8 or later; `new` fails with `Error::Unsupported` before
+/// that, where `LegacyDeviceWatcher` can be used instead.
+///
+/// ```no_run
+/// let watcher = listhid::DeviceWatcher::new(|event| println!("{:?}", event))?;
+/// std::thread::sleep(std::time::Duration::from_secs(60));
+/// drop(watcher);
+/// # Ok::<(), listhid::Error>(())
+/// ```
+pub struct DeviceWatcher {
+  notification: Option<Notification>,
+  // Passed to the notification callback, so it must outlive `notification`.
+  sender: Option<Box<Mutex<Sender<HidEvent>>>>,
+  dispatcher: WatcherCore,
+  subscribers: Arc<Mutex<Vec<Subscriber>>>,
+}
+
+/// Builder for a `DeviceWatcher` that only reports some devices, see
+/// `DeviceWatcher::builder`.
+#[derive(Debug, Default, Clone)]
+pub struct DeviceWatcherBuilder {
+  filter: DeviceFilter,
+  resolve_devices: bool,
+  debounce: Option<Duration>,
+}
+
+impl DeviceWatcherBuilder {
+  /// Only reports devices with the given vendor ID.
+  pub fn vendor_id(mut self, vendor_id: u16) -> Self {
+    self.filter.vendor_id = Some(vendor_id);
+    self
+  }
+
+  /// Only reports devices with the given product ID.
+  pub fn product_id(mut self, product_id: u16) -> Self {
+    self.filter.product_id = Some(product_id);
+    self
+  }
+
+  /// Only reports devices whose top-level collection has the given usage
+  /// page and usage.
+  pub fn usage(mut self, usage_page: u16, usage: u16) -> Self {
+    self.filter.usage = Some((usage_page, usage));
+    self
+  }
+
+  /// Reports arrivals as `HidEvent::ArrivedDevice`, with the device read as
+  /// `get_device_by_path` does, rather than just the path.
+  ///
+  /// Right after the arrival the device often can't be opened yet, so the
+  /// read is retried with a growing delay for up to half a second; if it
+  /// still fails, `HidEvent::ArrivedUnresolved` is reported instead. Events
+  /// are reported in order, so the ones behind an arrival wait for it.
+  pub fn resolve_devices(mut self, resolve_devices: bool) -> Self {
+    self.resolve_devices = resolve_devices;
+    self
+  }
+
+  /// Holds each interface's events back until none came for `window`, and
+  /// then reports only the last one, if the interface's presence changed:
+  /// an arrival followed by a removal within the window, or a removal
+  /// followed by an arrival, is not reported at all, and repeated arrivals
+  /// are reported once. For the storms of events e.g. docking a laptop
+  /// causes. Every event is delayed by at least `window`.
+  ///
+  /// Only `DeviceWatcher` debounces. `LegacyDeviceWatcher` calls its
+  /// callback from the window procedure as each message arrives, with no
+  /// thread of its own to hold events back on, and `PollingWatcher` already
+  /// coalesces whatever happens between two listings, its interval acting
+  /// as the window.
+  pub fn debounce(mut self, window: Duration) -> Self {
+    self.debounce = Some(window);
+    self
+  }
+
+  /// Starts the watcher, see `DeviceWatcher::new`.
+  pub fn build<F>(self, callback: F) -> Result<DeviceWatcher, Error>
+  where
+    F: FnMut(HidEvent) + Send + 'static,
+  {
+    DeviceWatcher::with_builder(self, callback)
+  }
+}
+
+/// Reads the device that arrived at `path`, retrying while it isn't ready,
+/// see `DeviceWatcherBuilder::resolve_devices`.
+fn resolve_arrival(path: String) -> HidEvent {
+  // 10 + 20 + 40 + 80 + 160 + 190 = 500 ms at most.
+  const RETRY_DELAYS_MS: [u64; 6] = [10, 20, 40, 80, 160, 190];
+
+  let mut result = get_device_by_path(&path);
+  for delay in RETRY_DELAYS_MS {
+    if let Ok(Some(_)) = result {
+      break;
+    }
+    thread::sleep(Duration::from_millis(delay));
+    result = get_device_by_path(&path);
+  }
+
+  match result {
+    Ok(Some(device)) => HidEvent::ArrivedDevice(Box::new(device)),
+    // The interface is already gone again.
+    Ok(None) => HidEvent::ArrivedUnresolved(path, Arc::new(Error::Disconnected)),
+    Err(error) => HidEvent::ArrivedUnresolved(path, Arc::new(error.into())),
+  }
+}
+
+/// Whether the device of `event` passes `filter`. The IDs and usage are read
+/// from the interface properties, which outlive the device, or the IDs from
+/// the path, so the device is never opened. An event whose data can't be
+/// read is passed on rather than risk losing a matching device.
+fn event_matches(filter: &DeviceFilter, event: &HidEvent) -> bool {
+  let path = match event {
+    HidEvent::Arrived(path) | HidEvent::ArrivedUnresolved(path, _) | HidEvent::Removed(path) => {
+      path
+    }
+    HidEvent::ArrivedDevice(_) | HidEvent::Lagged(_) => return true,
+  };
+  if filter.is_empty() {
+    return true;
+  }
+
+  let class_devs_info =
+    match unsafe { setup_di_create_device_info_list(ptr::null(), ptr::null_mut()) } {
+      Ok(class_devs_info) => class_devs_info,
+      Err(_) => return filter.matches(parse_ids_from_path(path), None),
+    };
+  match setup_di_open_device_interface(&class_devs_info, path, 0) {
+    Ok(interface_data) => filter.matches(
+      get_interface_ids(&class_devs_info, interface_data).or_else(|| parse_ids_from_path(path)),
+      get_interface_usage(&class_devs_info, interface_data),
+    ),
+    Err(_) => filter.matches(parse_ids_from_path(path), None),
+  }
+}
+
+impl DeviceWatcher {
+  /// Starts watching all HID devices.
+  pub fn new<F>(callback: F) -> Result<DeviceWatcher, Error>
+  where
+    F: FnMut(HidEvent) + Send + 'static,
+  {
+    Self::with_builder(DeviceWatcherBuilder::default(), callback)
+  }
+
+  /// Returns a builder for a watcher that only reports the devices matching
+  /// its filters, e.g. a product of one's own. Other devices' events are
+  /// dropped before they reach the callback or `events`.
+  ///
+  /// ```no_run
+  /// let watcher = listhid::DeviceWatcher::builder()
+  ///   .vendor_id(0x1234)
+  ///   .product_id(0x5678)
+  ///   .build(|event| println!("{:?}", event))?;
+  /// # Ok::<(), listhid::Error>(())
+  /// ```
+  pub fn builder() -> DeviceWatcherBuilder {
+    DeviceWatcherBuilder::default()
+  }
+
+  fn with_builder<F>(builder: DeviceWatcherBuilder, callback: F) -> Result<DeviceWatcher, Error>
+  where
+    F: FnMut(HidEvent) + Send + 'static,
+  {
+    if !cm_notifications_supported() {
+      return Err(Error::Unsupported);
+    }
+
+    let mut watcher = Self::with_dispatcher(builder, callback)?;
+    let sender = watcher.sender.as_deref().unwrap();
+    let notification = unsafe {
+      cm_register_interface_notification(
+        &GUID_DEVINTERFACE_HID,
+        sender as *const Mutex<Sender<HidEvent>> as PVOID,
+        Some(on_notification),
+      )
+    }?;
+    watcher.notification = Some(notification);
+    Ok(watcher)
+  }
+
+  /// Starts a watcher whose dispatcher thread reads the events sent to
+  /// `sender`, without registering for the notifications that send them.
+  fn with_dispatcher<F>(
+    builder: DeviceWatcherBuilder,
+    mut callback: F,
+  ) -> Result<DeviceWatcher, Error>
+  where
+    F: FnMut(HidEvent) + Send + 'static,
+  {
+    let subscribers: Arc<Mutex<Vec<Subscriber>>> = Arc::default();
+    let (sender, receiver) = mpsc::channel::<HidEvent>();
+    let dispatcher = WatcherCore::spawn("listhid-watcher", {
+      let subscribers = subscribers.clone();
+      move |running| {
+        let mut debouncer = builder.debounce.map(Debouncer::new);
+        loop {
+          // Waits for the next event, or for the next debounced one to be due.
+          let event = match debouncer.as_ref().and_then(Debouncer::next_due) {
+            Some(due) => {
+              match receiver.recv_timeout(due.saturating_duration_since(Instant::now())) {
+                Ok(event) => Some(event),
+                Err(RecvTimeoutError::Timeout) => None,
+                Err(RecvTimeoutError::Disconnected) => break,
+              }
+            }
+            None => match receiver.recv() {
+              Ok(event) => Some(event),
+              Err(_) => break,
+            },
+          };
+
+          let mut ready = Vec::new();
+          if let Some(event) = event.filter(|event| event_matches(&builder.filter, event)) {
+            match &mut debouncer {
+              Some(debouncer) => ready.extend(debouncer.push(event, Instant::now())),
+              None => ready.push(event),
+            }
+          }
+          if let Some(debouncer) = &mut debouncer {
+            ready.extend(debouncer.pop_due(Instant::now()));
+          }
+
+          for event in ready {
+            let event = match event {
+              HidEvent::Arrived(path) if builder.resolve_devices => resolve_arrival(path),
+              event => event,
+            };
+            if !running.get() {
+              return;
+            }
+            subscribers
+              .lock()
+              .unwrap()
+              .retain_mut(|subscriber| subscriber.send(event.clone()));
+            callback(event);
+          }
+        }
+      }
+    })?;
+
+    Ok(DeviceWatcher {
+      notification: None,
+      sender: Some(Box::new(Mutex::new(sender))),
+      dispatcher,
+      subscribers,
+    })
+  }
+
+  /// Stops the notifications and waits for the callback to return, see
+  /// `WatcherCore`. The receivers from `events` are closed. Called on drop.
+  pub fn stop(&mut self) {
+    // Unregistering waits for notification callbacks in progress, so the
+    // sender is unused once it returns. Closing the channel then ends the
+    // dispatcher thread.
+    let notification = self.notification.take();
+    let sender = self.sender.take();
+    self.dispatcher.stop(move || {
+      drop(notification);
+      drop(sender);
+    });
+    // Closes the receivers' channels.
+    self.subscribers.lock().unwrap().clear();
+  }
+
+  /// Returns a receiver for the events from now on, buffering up to 64 of
+  /// them. See `events_with_capacity`.
+  pub fn events(&self) -> Receiver<HidEvent> {
+    self.events_with_capacity(DEFAULT_EVENTS_CAPACITY)
+  }
+
+  /// Returns a receiver for the events from now on, buffering up to
+  /// `capacity` of them.
+  ///
+  /// Events are never held back for a receiver that lags: once its buffer is
+  /// full, further events are dropped and counted, and the count is sent as
+  /// `HidEvent::Lagged` as soon as there is room again. Dropping the
+  /// receiver just stops the events to it. The channel closes when the
+  /// watcher is dropped.
+  pub fn events_with_capacity(&self, capacity: usize) -> Receiver<HidEvent> {
+    // A zero-capacity channel would drop every event nobody is waiting for.
+    let (sender, receiver) = mpsc::sync_channel(capacity.max(1));
+    self
+      .subscribers
+      .lock()
+      .unwrap()
+      .push(Subscriber { sender, lagged: 0 });
+    receiver
+  }
+}
+
+impl Drop for DeviceWatcher {
+  fn drop(&mut self) {
+    self.stop();
+  }
+}
+
+unsafe extern "system" fn on_notification(
+  _notification: HCMNOTIFICATION,
+  context: PVOID,
+  action: CM_NOTIFY_ACTION,
+  event_data: PCM_NOTIFY_EVENT_DATA,
+  event_data_size: DWORD,
+) -> DWORD {
+  let event = match action {
+    CM_NOTIFY_ACTION_DEVICEINTERFACEARRIVAL => {
+      HidEvent::Arrived(notification_interface_path(event_data, event_data_size))
+    }
+    CM_NOTIFY_ACTION_DEVICEINTERFACEREMOVAL => {
+      HidEvent::Removed(notification_interface_path(event_data, event_data_size))
+    }
+    _ => return ERROR_SUCCESS,
+  };
+
+  let sender = &*(context as *const Mutex<Sender<HidEvent>>);
+  if let Ok(sender) = sender.lock() {
+    // Fails only once the dispatcher is gone, i.e. while dropping.
+    let _ = sender.send(event);
+  }
+  ERROR_SUCCESS
+}
+
+/// Like `DeviceWatcher`, but built on the `WM_DEVICECHANGE` messages sent to
+/// a hidden window, which also works before Windows 8.
+///
+/// The callback is called on the watcher's thread, which runs the window's
+/// message loop, so the same re-entrancy as with `DeviceWatcher` applies. A
+/// panic in the callback aborts the process, as it can't unwind through the
+/// window procedure. `stop`, or dropping the watcher, destroys the window,
+/// unregisters the notifications and waits for the callback to return, with
+/// the same guarantees as for `DeviceWatcher`. Events aren't debounced, see
+/// `DeviceWatcherBuilder::debounce`.
+pub struct LegacyDeviceWatcher {
+  // The window, as an integer so the watcher is `Send`. Only used to post
+  // `WM_CLOSE`, which any thread may do.
+  window: usize,
+  pump: WatcherCore,
+}
+
+type WindowCallback = Box<dyn FnMut(HidEvent)>;
+
+const WINDOW_CLASS: &str = "listhid_device_watcher";
+
+impl LegacyDeviceWatcher {
+  pub fn new<F>(callback: F) -> Result<LegacyDeviceWatcher, Error>
+  where
+    F: FnMut(HidEvent) + Send + 'static,
+  {
+    let (ready, created) = mpsc::channel();
+    let mut pump = WatcherCore::spawn("listhid-legacy-watcher", move |running| {
+      let mut callback = callback;
+      let mut callback: WindowCallback = Box::new(move |event| {
+        if running.get() {
+          callback(event);
+        }
+      });
+      unsafe { run_message_window(&mut callback, ready) };
+    })?;
+
+    match created.recv() {
+      Ok(Ok(window)) => Ok(LegacyDeviceWatcher { window, pump }),
+      Ok(Err(error)) => {
+        pump.stop(|| {});
+        Err(error.into())
+      }
+      // The thread ended without reporting, i.e. it panicked.
+      Err(_) => {
+        pump.stop(|| {});
+        Err(io::Error::other("the device watcher thread failed").into())
+      }
+    }
+  }
+
+  /// Destroys the window and waits for the callback to return, see
+  /// `WatcherCore`. Called on drop.
+  pub fn stop(&mut self) {
+    let window = self.window as HWND;
+    // Destroys the window, which ends the message loop.
+    self.pump.stop(|| {
+      unsafe { PostMessageW(window, WM_CLOSE, 0, 0) };
+    });
+  }
+}
+
+impl Drop for LegacyDeviceWatcher {
+  fn drop(&mut self) {
+    self.stop();
+  }
+}
+
+/// Creates a message-only window registered for HID interface changes,
+/// reports it through `ready`, and runs its message loop until it is
+/// destroyed. `callback` must outlive the window.
+unsafe fn run_message_window(
+  callback: &mut WindowCallback,
+  ready: Sender<Result<usize, io::Error>>,
+) {
+  let instance = GetModuleHandleW(ptr::null());
+  let class_name = string_to_lpcwstr(WINDOW_CLASS);
+  let mut class: WNDCLASSEXW = mem::zeroed();
+  class.cbSize = mem::size_of::<WNDCLASSEXW>() as UINT;
+  class.lpfnWndProc = Some(window_proc);
+  class.hInstance = instance;
+  class.lpszClassName = class_name.as_ptr();
+  // Registered once per process; later watchers reuse it.
+  if RegisterClassExW(&class) == 0 && GetLastError() != ERROR_CLASS_ALREADY_EXISTS {
+    let _ = ready.send(Err(io::Error::last_os_error()));
+    return;
+  }
+
+  let window = CreateWindowExW(
+    0,
+    class_name.as_ptr(),
+    ptr::null(),
+    0,
+    0,
+    0,
+    0,
+    0,
+    HWND_MESSAGE,
+    ptr::null_mut(),
+    instance,
+    ptr::null_mut(),
+  );
+  if window.is_null() {
+    let _ = ready.send(Err(io::Error::last_os_error()));
+    return;
+  }
+  SetWindowLongPtrW(
+    window,
+    GWLP_USERDATA,
+    callback as *mut WindowCallback as isize,
+  );
+
+  let mut filter: DEV_BROADCAST_DEVICEINTERFACE_W = mem::zeroed();
+  filter.dbcc_size = mem::size_of::<DEV_BROADCAST_DEVICEINTERFACE_W>() as DWORD;
+  filter.dbcc_devicetype = DBT_DEVTYP_DEVICEINTERFACE;
+  filter.dbcc_classguid = GUID_DEVINTERFACE_HID;
+  let notification = RegisterDeviceNotificationW(
+    window as HANDLE,
+    &mut filter as *mut DEV_BROADCAST_DEVICEINTERFACE_W as LPVOID,
+    DEVICE_NOTIFY_WINDOW_HANDLE,
+  );
+  if notification.is_null() {
+    let _ = ready.send(Err(io::Error::last_os_error()));
+    DestroyWindow(window);
+    return;
+  }
+  let _ = ready.send(Ok(window as usize));
+
+  let mut message: MSG = mem::zeroed();
+  while GetMessageW(&mut message, ptr::null_mut(), 0, 0) > 0 {
+    DispatchMessageW(&message);
+  }
+  UnregisterDeviceNotification(notification);
+}
+
+unsafe extern "system" fn window_proc(
+  window: HWND,
+  message: UINT,
+  wparam: WPARAM,
+  lparam: LPARAM,
+) -> LRESULT {
+  match message {
+    WM_DEVICECHANGE if wparam == DBT_DEVICEARRIVAL || wparam == DBT_DEVICEREMOVECOMPLETE => {
+      let callback = GetWindowLongPtrW(window, GWLP_USERDATA) as *mut WindowCallback;
+      let header = lparam as *const DEV_BROADCAST_HDR;
+      if !callback.is_null()
+        && !header.is_null()
+        && (*header).dbch_devicetype == DBT_DEVTYP_DEVICEINTERFACE
+      {
+        let path = broadcast_interface_path(header as *const DEV_BROADCAST_DEVICEINTERFACE_W);
+        (*callback)(if wparam == DBT_DEVICEARRIVAL {
+          HidEvent::Arrived(path)
+        } else {
+          HidEvent::Removed(path)
+        });
+      }
+      TRUE as LRESULT
+    }
+    WM_DESTROY => {
+      PostQuitMessage(0);
+      0
+    }
+    // Including `WM_CLOSE`, which destroys the window.
+    _ => DefWindowProcW(window, message, wparam, lparam),
+  }
+}
+
+/// Reads the interface path from a `DEV_BROADCAST_DEVICEINTERFACE_W` of
+/// `dbcc_size` bytes.
+unsafe fn broadcast_interface_path(broadcast: *const DEV_BROADCAST_DEVICEINTERFACE_W) -> String {
+  let name = (*broadcast).dbcc_name.as_ptr();
+  let offset = name as usize - broadcast as usize;
+  let length = ((*broadcast).dbcc_size as usize).saturating_sub(offset) / mem::size_of::<WCHAR>();
+  let path = slice::from_raw_parts(name, length);
+  let end = path.iter().position(|&c| c == 0).unwrap_or(length);
+  String::from_utf16_lossy(&path[..end])
+}
+
+/// A change between two listings of a `PollingWatcher`.
+#[derive(Debug)]
+pub enum PollEvent {
+  Added(HidDevice),
+  Removed(HidDevice),
+  /// Listing the devices failed. The watcher keeps the previous listing and
+  /// tries again at the next interval.
+  Error(ListHidError),
+}
+
+/// Watches for HID devices being added and removed by listing them at a
+/// fixed interval and comparing each listing with the previous one, by
+/// path. For sandboxes and other contexts where the notifications
+/// `DeviceWatcher` relies on don't arrive; otherwise prefer that, as it
+/// reacts immediately and costs nothing while nothing changes.
+///
+/// The callback is called on the watcher's thread. Devices present when the
+/// watcher is created are not reported, nor are devices that come and go
+/// between two listings, so there is no separate debouncing. `stop` and
+/// drop behave as for `DeviceWatcher`.
+pub struct PollingWatcher {
+  stop: Option<Sender<()>>,
+  poller: WatcherCore,
+}
+
+impl PollingWatcher {
+  pub fn with_interval<F>(interval: Duration, mut callback: F) -> Result<PollingWatcher, Error>
+  where
+    F: FnMut(PollEvent) + Send + 'static,
+  {
+    let mut snapshot = list_hid_device()?;
+    snapshot.sort_by(|a, b| a.path.cmp(&b.path));
+
+    let (stop, stopped) = mpsc::channel();
+    let poller = WatcherCore::spawn("listhid-poller", move |running| {
+      let mut callback = |event| {
+        if running.get() {
+          callback(event);
+        }
+      };
+      // Closing the stop channel ends the loop.
+      while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(interval) {
+        match list_hid_device() {
+          Ok(mut devices) => {
+            devices.sort_by(|a, b| a.path.cmp(&b.path));
+            let previous = mem::replace(&mut snapshot, devices);
+            diff_snapshots(previous, &snapshot, &mut callback);
+          }
+          Err(error) => callback(PollEvent::Error(error)),
+        }
+      }
+    })?;
+
+    Ok(PollingWatcher {
+      stop: Some(stop),
+      poller,
+    })
+  }
+
+  /// Stops polling and waits for the callback to return, see `WatcherCore`.
+  /// Called on drop.
+  pub fn stop(&mut self) {
+    let stop = self.stop.take();
+    self.poller.stop(move || drop(stop));
+  }
+}
+
+impl Drop for PollingWatcher {
+  fn drop(&mut self) {
+    self.stop();
+  }
+}
+
+/// Reports the devices in only one of two listings sorted by path. Allocates
+/// nothing if they are the same.
+fn diff_snapshots(
+  previous: Vec<HidDevice>,
+  current: &[HidDevice],
+  callback: &mut impl FnMut(PollEvent),
+) {
+  let mut current = current.iter().peekable();
+  for device in previous {
+    while let Some(added) = current.next_if(|added| added.path < device.path) {
+      callback(PollEvent::Added(added.clone()));
+    }
+    if current.next_if(|kept| kept.path == device.path).is_none() {
+      callback(PollEvent::Removed(device));
+    }
+  }
+  for added in current {
+    callback(PollEvent::Added(added.clone()));
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::sync::atomic::{AtomicUsize, Ordering};
+
+  #[test]
+  fn stop_ends_the_dispatcher_and_closes_the_receivers() {
+    let count = Arc::new(AtomicUsize::new(0));
+    let mut watcher = DeviceWatcher::with_dispatcher(DeviceWatcherBuilder::default(), {
+      let count = count.clone();
+      move |_| {
+        count.fetch_add(1, Ordering::SeqCst);
+      }
+    })
+    .unwrap();
+    let events = watcher.events();
+
+    // Sent the way the notification callback does. A clone of the sender
+    // would keep the dispatcher from ever seeing the channel close.
+    let sender = watcher.sender.as_ref().unwrap();
+    sender
+      .lock()
+      .unwrap()
+      .send(HidEvent::Removed("removed".to_owned()))
+      .unwrap();
+    assert!(matches!(
+      events.recv_timeout(Duration::from_secs(5)),
+      Ok(HidEvent::Removed(path)) if path == "removed"
+    ));
+
+    // Drops the sender and returns once the dispatcher thread has ended.
+    watcher.stop();
+    assert!(events.recv().is_err());
+    assert!(watcher.sender.is_none());
+    assert_eq!(count.load(Ordering::SeqCst), 1);
+    // Stopping again, as dropping does, is a no-op.
+    watcher.stop();
+  }
+
+  #[test]
+  #[ignore = "needs a HID device plugged in or out while it runs"]
+  fn reports_a_plug_event() {
+    // Set to run it, e.g. `LISTHID_TEST_PLUG=1 cargo test -- --ignored`.
+    if std::env::var_os("LISTHID_TEST_PLUG").is_none() {
+      return;
+    }
+    let watcher = DeviceWatcher::new(|_| {}).unwrap();
+    let events = watcher.events();
+    println!("Plug in or remove a HID device within a minute.");
+    let event = events.recv_timeout(Duration::from_secs(60)).unwrap();
+    assert!(matches!(event, HidEvent::Arrived(_) | HidEvent::Removed(_)));
+  }
+}
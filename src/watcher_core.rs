@@ -0,0 +1,149 @@
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+
+/// The lifecycle shared by the watchers, each of which calls its callback on
+/// a thread of its own.
+///
+/// The callback is only called while the watcher is running, which `stop`
+/// ends first. `stop` then has the watcher's own teardown end the thread and
+/// waits for it, so once it returns the callback is never called again.
+/// Called from the callback itself, where waiting would deadlock, `stop`
+/// returns without waiting; the callback still isn't called again after the
+/// call in progress.
+pub(crate) struct WatcherCore {
+  running: Arc<AtomicBool>,
+  thread: Option<JoinHandle<()>>,
+}
+
+/// Whether a `WatcherCore` hasn't been stopped, checked by its thread before
+/// each call of the callback.
+#[derive(Clone)]
+pub(crate) struct Running(Arc<AtomicBool>);
+
+impl Running {
+  pub(crate) fn get(&self) -> bool {
+    self.0.load(Ordering::SeqCst)
+  }
+}
+
+impl WatcherCore {
+  pub(crate) fn spawn<F>(name: &str, body: F) -> io::Result<WatcherCore>
+  where
+    F: FnOnce(Running) + Send + 'static,
+  {
+    let running = Arc::new(AtomicBool::new(true));
+    let thread = thread::Builder::new().name(name.to_owned()).spawn({
+      let running = Running(running.clone());
+      move || body(running)
+    })?;
+    Ok(WatcherCore {
+      running,
+      thread: Some(thread),
+    })
+  }
+
+  /// Stops calling the callback, ends the thread with `teardown` and waits
+  /// for it. Only the first call runs `teardown`.
+  pub(crate) fn stop(&mut self, teardown: impl FnOnce()) {
+    self.running.store(false, Ordering::SeqCst);
+    if let Some(thread) = self.thread.take() {
+      teardown();
+      if thread.thread().id() != thread::current().id() {
+        let _ = thread.join();
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::sync::atomic::AtomicUsize;
+  use std::sync::mpsc::{self, Sender};
+  use std::sync::Mutex;
+  use std::time::Duration;
+
+  /// Spawns a core calling `callback` for each `Some` sent until `None`, the
+  /// way the watchers check `running` before each call.
+  fn spawn_counter<F>(callback: F) -> (WatcherCore, Sender<Option<()>>)
+  where
+    F: Fn() + Send + 'static,
+  {
+    let (sender, receiver) = mpsc::channel();
+    let core = WatcherCore::spawn("listhid-test-watcher", move |running| {
+      for event in receiver {
+        match event {
+          Some(()) if running.get() => callback(),
+          Some(()) => {}
+          None => break,
+        }
+      }
+    })
+    .unwrap();
+    (core, sender)
+  }
+
+  #[test]
+  fn callback_is_never_called_after_stop_returns() {
+    for _ in 0..100 {
+      let count = Arc::new(AtomicUsize::new(0));
+      let (mut core, sender) = spawn_counter({
+        let count = count.clone();
+        move || {
+          count.fetch_add(1, Ordering::SeqCst);
+        }
+      });
+      let feeder = thread::spawn({
+        let sender = sender.clone();
+        move || while sender.send(Some(())).is_ok() {}
+      });
+      while count.load(Ordering::SeqCst) == 0 {
+        thread::yield_now();
+      }
+
+      core.stop(|| {
+        let _ = sender.send(None);
+      });
+      let stopped = count.load(Ordering::SeqCst);
+      let _ = sender.send(Some(()));
+      thread::sleep(Duration::from_millis(1));
+      assert_eq!(count.load(Ordering::SeqCst), stopped);
+      // The receiver is gone with the thread, which ends the feeder.
+      feeder.join().unwrap();
+      assert_eq!(count.load(Ordering::SeqCst), stopped);
+    }
+  }
+
+  #[test]
+  fn stop_from_the_callback_returns_without_waiting() {
+    let core = Arc::new(Mutex::new(None::<WatcherCore>));
+    let count = Arc::new(AtomicUsize::new(0));
+    let (done_sender, done) = mpsc::channel();
+    let (spawned, sender) = spawn_counter({
+      let core = core.clone();
+      let count = count.clone();
+      move || {
+        count.fetch_add(1, Ordering::SeqCst);
+        core.lock().unwrap().as_mut().unwrap().stop(|| {
+          let _ = done_sender.send(());
+        });
+      }
+    });
+    *core.lock().unwrap() = Some(spawned);
+
+    sender.send(Some(())).unwrap();
+    done.recv_timeout(Duration::from_secs(5)).unwrap();
+    sender.send(Some(())).unwrap();
+    sender.send(None).unwrap();
+    // Stopping again is a no-op: the teardown only runs once.
+    core
+      .lock()
+      .unwrap()
+      .as_mut()
+      .unwrap()
+      .stop(|| unreachable!());
+    assert_eq!(count.load(Ordering::SeqCst), 1);
+  }
+}
@@ -0,0 +1,224 @@
+//! The `hid.dll` functions, looked up at run time rather than imported, so
+//! the crate still loads where `hid.dll` or some of its functions are
+//! missing, as on some Server Core and Nano Server images. Each function
+//! stands in for the winapi one of the same name; when it is missing, it
+//! fails the way the real one does: the `HidD_*` functions return `FALSE`
+//! with `ERROR_PROC_NOT_FOUND` as the last error, and the `HidP_*` ones
+//! `HIDP_STATUS_NOT_IMPLEMENTED`.
+
+#![allow(non_snake_case)]
+
+use std::mem;
+use std::sync::OnceLock;
+use winapi::shared::hidpi::{
+  HIDP_KEYBOARD_DIRECTION, HIDP_REPORT_TYPE, HIDP_STATUS_NOT_IMPLEMENTED, PHIDP_BUTTON_CAPS,
+  PHIDP_CAPS, PHIDP_INSERT_SCANCODES, PHIDP_KEYBOARD_MODIFIER_STATE, PHIDP_LINK_COLLECTION_NODE,
+  PHIDP_PREPARSED_DATA, PHIDP_VALUE_CAPS,
+};
+use winapi::shared::hidsdi::PHIDD_ATTRIBUTES;
+use winapi::shared::hidusage::{PUSAGE, USAGE};
+use winapi::shared::minwindef::{FARPROC, HMODULE, PULONG, PUSHORT, UCHAR, ULONG, USHORT};
+use winapi::shared::ntdef::{BOOLEAN, FALSE, HANDLE, LPCSTR, NTSTATUS, PCHAR, PLONG, PVOID};
+use winapi::shared::winerror::ERROR_PROC_NOT_FOUND;
+use winapi::um::errhandlingapi::SetLastError;
+use winapi::um::libloaderapi::{GetProcAddress, LoadLibraryW};
+
+/// `hid.dll`, loaded on first use and never freed. Null if it is missing.
+fn module() -> HMODULE {
+  // The module handle is stored as an address, as raw pointers aren't `Sync`.
+  static MODULE: OnceLock<usize> = OnceLock::new();
+  *MODULE
+    .get_or_init(|| unsafe { LoadLibraryW(super::string_to_lpcwstr("hid.dll").as_ptr()) as usize })
+    as HMODULE
+}
+
+/// Looks up `name`, NUL-terminated, in `hid.dll`. Null if either is missing.
+fn get_proc(name: &str) -> FARPROC {
+  debug_assert!(name.ends_with('\0'));
+  match module() {
+    module if module.is_null() => std::ptr::null_mut(),
+    module => unsafe { GetProcAddress(module, name.as_ptr() as LPCSTR) },
+  }
+}
+
+/// Whether `hid.dll` could be loaded and has `HidD_GetAttributes`, without
+/// which no device can be queried.
+pub fn is_available() -> bool {
+  !get_proc("HidD_GetAttributes\0").is_null()
+}
+
+/// The result of a missing `HidD_*` function.
+fn missing_hid_d() -> BOOLEAN {
+  unsafe { SetLastError(ERROR_PROC_NOT_FOUND) };
+  FALSE
+}
+
+macro_rules! hid_functions {
+  ($(fn $name:ident($($arg:ident: $ty:ty),* $(,)?) -> $ret:ty, else $missing:expr;)*) => {
+    $(
+      /// # Safety
+      ///
+      /// Same as the `hid.dll` function.
+      #[allow(clippy::too_many_arguments)]
+      pub unsafe fn $name($($arg: $ty),*) -> $ret {
+        type Proc = unsafe extern "system" fn($($ty),*) -> $ret;
+        static PROC: OnceLock<usize> = OnceLock::new();
+        match *PROC.get_or_init(|| get_proc(concat!(stringify!($name), "\0")) as usize) {
+          0 => $missing,
+          proc => unsafe { mem::transmute::<usize, Proc>(proc)($($arg),*) },
+        }
+      }
+    )*
+  };
+}
+
+hid_functions! {
+  fn HidD_FreePreparsedData(preparsed_data: PHIDP_PREPARSED_DATA) -> BOOLEAN, else missing_hid_d();
+  fn HidD_GetAttributes(device: HANDLE, attributes: PHIDD_ATTRIBUTES) -> BOOLEAN,
+    else missing_hid_d();
+  fn HidD_GetFeature(device: HANDLE, buffer: PVOID, length: ULONG) -> BOOLEAN,
+    else missing_hid_d();
+  fn HidD_GetInputReport(device: HANDLE, buffer: PVOID, length: ULONG) -> BOOLEAN,
+    else missing_hid_d();
+  fn HidD_GetManufacturerString(device: HANDLE, buffer: PVOID, length: ULONG) -> BOOLEAN,
+    else missing_hid_d();
+  fn HidD_GetNumInputBuffers(device: HANDLE, number_buffers: PULONG) -> BOOLEAN,
+    else missing_hid_d();
+  fn HidD_GetPhysicalDescriptor(device: HANDLE, buffer: PVOID, length: ULONG) -> BOOLEAN,
+    else missing_hid_d();
+  fn HidD_GetPreparsedData(device: HANDLE, preparsed_data: *mut PHIDP_PREPARSED_DATA) -> BOOLEAN,
+    else missing_hid_d();
+  fn HidD_GetProductString(device: HANDLE, buffer: PVOID, length: ULONG) -> BOOLEAN,
+    else missing_hid_d();
+  fn HidD_GetSerialNumberString(device: HANDLE, buffer: PVOID, length: ULONG) -> BOOLEAN,
+    else missing_hid_d();
+  fn HidD_SetFeature(device: HANDLE, buffer: PVOID, length: ULONG) -> BOOLEAN,
+    else missing_hid_d();
+  fn HidD_SetNumInputBuffers(device: HANDLE, number_buffers: ULONG) -> BOOLEAN,
+    else missing_hid_d();
+  fn HidD_SetOutputReport(device: HANDLE, buffer: PVOID, length: ULONG) -> BOOLEAN,
+    else missing_hid_d();
+  fn HidP_GetButtonCaps(
+    report_type: HIDP_REPORT_TYPE,
+    button_caps: PHIDP_BUTTON_CAPS,
+    button_caps_length: PUSHORT,
+    preparsed_data: PHIDP_PREPARSED_DATA,
+  ) -> NTSTATUS, else HIDP_STATUS_NOT_IMPLEMENTED;
+  fn HidP_GetCaps(preparsed_data: PHIDP_PREPARSED_DATA, caps: PHIDP_CAPS) -> NTSTATUS,
+    else HIDP_STATUS_NOT_IMPLEMENTED;
+  fn HidP_GetLinkCollectionNodes(
+    nodes: PHIDP_LINK_COLLECTION_NODE,
+    nodes_length: PULONG,
+    preparsed_data: PHIDP_PREPARSED_DATA,
+  ) -> NTSTATUS, else HIDP_STATUS_NOT_IMPLEMENTED;
+  fn HidP_GetScaledUsageValue(
+    report_type: HIDP_REPORT_TYPE,
+    usage_page: USAGE,
+    link_collection: USHORT,
+    usage: USAGE,
+    usage_value: PLONG,
+    preparsed_data: PHIDP_PREPARSED_DATA,
+    report: PCHAR,
+    report_length: ULONG,
+  ) -> NTSTATUS, else HIDP_STATUS_NOT_IMPLEMENTED;
+  fn HidP_GetUsageValue(
+    report_type: HIDP_REPORT_TYPE,
+    usage_page: USAGE,
+    link_collection: USHORT,
+    usage: USAGE,
+    usage_value: PULONG,
+    preparsed_data: PHIDP_PREPARSED_DATA,
+    report: PCHAR,
+    report_length: ULONG,
+  ) -> NTSTATUS, else HIDP_STATUS_NOT_IMPLEMENTED;
+  fn HidP_GetUsages(
+    report_type: HIDP_REPORT_TYPE,
+    usage_page: USAGE,
+    link_collection: USHORT,
+    usage_list: PUSAGE,
+    usage_length: PULONG,
+    preparsed_data: PHIDP_PREPARSED_DATA,
+    report: PCHAR,
+    report_length: ULONG,
+  ) -> NTSTATUS, else HIDP_STATUS_NOT_IMPLEMENTED;
+  fn HidP_GetValueCaps(
+    report_type: HIDP_REPORT_TYPE,
+    value_caps: PHIDP_VALUE_CAPS,
+    value_caps_length: PUSHORT,
+    preparsed_data: PHIDP_PREPARSED_DATA,
+  ) -> NTSTATUS, else HIDP_STATUS_NOT_IMPLEMENTED;
+  fn HidP_InitializeReportForID(
+    report_type: HIDP_REPORT_TYPE,
+    report_id: UCHAR,
+    preparsed_data: PHIDP_PREPARSED_DATA,
+    report: PCHAR,
+    report_length: ULONG,
+  ) -> NTSTATUS, else HIDP_STATUS_NOT_IMPLEMENTED;
+  fn HidP_MaxUsageListLength(
+    report_type: HIDP_REPORT_TYPE,
+    usage_page: USAGE,
+    preparsed_data: PHIDP_PREPARSED_DATA,
+  ) -> ULONG, else 0;
+  fn HidP_SetUsageValue(
+    report_type: HIDP_REPORT_TYPE,
+    usage_page: USAGE,
+    link_collection: USHORT,
+    usage: USAGE,
+    usage_value: ULONG,
+    preparsed_data: PHIDP_PREPARSED_DATA,
+    report: PCHAR,
+    report_length: ULONG,
+  ) -> NTSTATUS, else HIDP_STATUS_NOT_IMPLEMENTED;
+  fn HidP_SetUsages(
+    report_type: HIDP_REPORT_TYPE,
+    usage_page: USAGE,
+    link_collection: USHORT,
+    usage_list: PUSAGE,
+    usage_length: PULONG,
+    preparsed_data: PHIDP_PREPARSED_DATA,
+    report: PCHAR,
+    report_length: ULONG,
+  ) -> NTSTATUS, else HIDP_STATUS_NOT_IMPLEMENTED;
+  fn HidP_TranslateUsagesToI8042ScanCodes(
+    changed_usage_list: PUSAGE,
+    usage_list_length: ULONG,
+    key_action: HIDP_KEYBOARD_DIRECTION,
+    modifier_state: PHIDP_KEYBOARD_MODIFIER_STATE,
+    insert_codes_procedure: PHIDP_INSERT_SCANCODES,
+    insert_codes_context: PVOID,
+  ) -> NTSTATUS, else HIDP_STATUS_NOT_IMPLEMENTED;
+  fn HidP_UnsetUsages(
+    report_type: HIDP_REPORT_TYPE,
+    usage_page: USAGE,
+    link_collection: USHORT,
+    usage_list: PUSAGE,
+    usage_length: PULONG,
+    preparsed_data: PHIDP_PREPARSED_DATA,
+    report: PCHAR,
+    report_length: ULONG,
+  ) -> NTSTATUS, else HIDP_STATUS_NOT_IMPLEMENTED;
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use winapi::um::errhandlingapi::GetLastError;
+
+  #[test]
+  fn missing_functions_are_null() {
+    assert!(get_proc("HidD_NoSuchFunction\0").is_null());
+  }
+
+  #[test]
+  fn missing_hid_d_functions_fail_like_the_real_ones() {
+    assert_eq!(missing_hid_d(), FALSE);
+    assert_eq!(unsafe { GetLastError() }, ERROR_PROC_NOT_FOUND);
+  }
+
+  #[test]
+  fn present_functions_are_found() {
+    // hid.dll ships with every desktop Windows.
+    assert!(is_available());
+    assert!(!get_proc("HidP_GetCaps\0").is_null());
+  }
+}
@@ -1,29 +1,87 @@
 extern crate winapi;
 
+mod hid_dll;
+
+use std::collections::HashMap;
 use std::ffi::OsStr;
 use std::ffi::OsString;
 use std::iter::once;
 use std::os::windows::ffi::OsStrExt;
 use std::os::windows::prelude::*;
-use std::{io, mem, ptr};
+use std::sync::OnceLock;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use std::{io, mem, ptr, slice};
 
+pub use hid_dll::is_available as hid_dll_available;
+use hid_dll::{
+  HidD_FreePreparsedData, HidD_GetAttributes, HidD_GetFeature, HidD_GetInputReport,
+  HidD_GetManufacturerString, HidD_GetNumInputBuffers, HidD_GetPhysicalDescriptor,
+  HidD_GetPreparsedData, HidD_GetProductString, HidD_GetSerialNumberString, HidD_SetFeature,
+  HidD_SetNumInputBuffers, HidD_SetOutputReport, HidP_GetButtonCaps, HidP_GetCaps,
+  HidP_GetLinkCollectionNodes, HidP_GetScaledUsageValue, HidP_GetUsageValue, HidP_GetUsages,
+  HidP_GetValueCaps, HidP_InitializeReportForID, HidP_MaxUsageListLength, HidP_SetUsageValue,
+  HidP_SetUsages, HidP_TranslateUsagesToI8042ScanCodes, HidP_UnsetUsages,
+};
+use winapi::shared::cfg::DN_HAS_PROBLEM;
+use winapi::shared::devpkey::{
+  DEVPKEY_Device_BusReportedDeviceDesc, DEVPKEY_Device_ContainerId, DEVPKEY_Device_LastArrivalDate,
+  DEVPKEY_Device_LastRemovalDate, DEVPKEY_Device_Parent,
+};
+use winapi::shared::devpropdef::{
+  DEVPROPKEY, DEVPROPTYPE, DEVPROP_TYPE_FILETIME, DEVPROP_TYPE_GUID, DEVPROP_TYPE_STRING,
+  DEVPROP_TYPE_UINT16, DEVPROP_TYPE_UINT32,
+};
 use winapi::shared::guiddef::GUID;
-use winapi::shared::hidsdi::{
-  HidD_GetAttributes, HidD_GetProductString, HidD_GetSerialNumberString, HIDD_ATTRIBUTES,
+use winapi::shared::hidpi::{
+  HidP_Keyboard_Break, HidP_Keyboard_Make, HIDP_BUTTON_CAPS, HIDP_CAPS,
+  HIDP_KEYBOARD_MODIFIER_STATE, HIDP_LINK_COLLECTION_NODE, HIDP_REPORT_TYPE,
+  HIDP_STATUS_BUTTON_NOT_PRESSED, HIDP_STATUS_INCOMPATIBLE_REPORT_ID, HIDP_STATUS_SUCCESS,
+  HIDP_STATUS_USAGE_NOT_FOUND, HIDP_VALUE_CAPS, PHIDP_PREPARSED_DATA,
 };
-use winapi::shared::minwindef::DWORD;
-use winapi::shared::ntdef::{FALSE, HANDLE, LPCWSTR, PCWSTR, PVOID, PWCHAR, WCHAR};
+use winapi::shared::hidsdi::HIDD_ATTRIBUTES;
+use winapi::shared::minwindef::{DWORD, FARPROC, FILETIME, LPCVOID, TRUE, ULONG, USHORT};
+use winapi::shared::ntdef::{
+  BOOLEAN, FALSE, HANDLE, LONG, LPCSTR, LPCWSTR, NTSTATUS, PCHAR, PCWSTR, PVOID, PWCHAR, WCHAR,
+};
+use winapi::shared::usbioctl::{
+  IOCTL_USB_GET_NODE_CONNECTION_INFORMATION_EX, IOCTL_USB_GET_NODE_CONNECTION_INFORMATION_EX_V2,
+  USB_NODE_CONNECTION_INFORMATION_EX, USB_NODE_CONNECTION_INFORMATION_EX_V2,
+};
+use winapi::shared::usbiodef::GUID_DEVINTERFACE_USB_HUB;
 use winapi::shared::windef::HWND;
-use winapi::shared::winerror::{ERROR_INSUFFICIENT_BUFFER, ERROR_NO_MORE_ITEMS};
+use winapi::shared::winerror::{
+  ERROR_GEN_FAILURE, ERROR_INSUFFICIENT_BUFFER, ERROR_IO_PENDING, ERROR_NO_MORE_ITEMS,
+  ERROR_OPERATION_ABORTED, ERROR_PROC_NOT_FOUND, WAIT_TIMEOUT,
+};
+use winapi::um::cfgmgr32::{
+  CM_Get_DevNode_Registry_PropertyW, CM_Get_DevNode_Status, CM_Get_Device_IDW,
+  CM_Get_Device_Interface_ListW, CM_Get_Device_Interface_List_SizeW, CM_Get_Parent, CM_DRP_ADDRESS,
+  CM_GET_DEVICE_INTERFACE_LIST_PRESENT, CM_NOTIFY_FILTER, CM_NOTIFY_FILTER_TYPE_DEVICEHANDLE,
+  CM_NOTIFY_FILTER_TYPE_DEVICEINTERFACE, CONFIGRET, CR_SUCCESS, DEVINST, HCMNOTIFICATION,
+  MAX_DEVICE_ID_LEN, PCM_NOTIFY_CALLBACK, PCM_NOTIFY_EVENT_DATA, PCM_NOTIFY_FILTER,
+  PHCMNOTIFICATION,
+};
 use winapi::um::errhandlingapi::GetLastError;
-use winapi::um::fileapi::CreateFileW;
+use winapi::um::fileapi::{CreateFileW, ReadFile, WriteFile, OPEN_EXISTING};
 use winapi::um::handleapi::{CloseHandle, INVALID_HANDLE_VALUE};
+use winapi::um::ioapiset::{CancelIoEx, DeviceIoControl, GetOverlappedResult};
+use winapi::um::libloaderapi::{GetProcAddress, LoadLibraryW};
+use winapi::um::minwinbase::OVERLAPPED;
 use winapi::um::setupapi::{
-  SetupDiDestroyDeviceInfoList, SetupDiEnumDeviceInfo, SetupDiEnumDeviceInterfaces,
-  SetupDiGetClassDevsW, SetupDiGetDeviceInterfaceDetailW, SetupDiGetDeviceRegistryPropertyW,
-  HDEVINFO, PSP_DEVICE_INTERFACE_DETAIL_DATA_W, SPDRP_PHYSICAL_DEVICE_OBJECT_NAME,
+  SetupDiCreateDeviceInfoList, SetupDiDestroyDeviceInfoList, SetupDiEnumDeviceInfo,
+  SetupDiEnumDeviceInterfaces, SetupDiGetClassDevsExW, SetupDiGetClassDevsW,
+  SetupDiGetDeviceInstanceIdW, SetupDiGetDeviceInterfaceDetailW,
+  SetupDiGetDeviceInterfacePropertyW, SetupDiGetDevicePropertyW, SetupDiGetDeviceRegistryPropertyW,
+  SetupDiOpenDeviceInfoW, SetupDiOpenDeviceInterfaceW, HDEVINFO,
+  PSP_DEVICE_INTERFACE_DETAIL_DATA_W, SPDRP_CLASS, SPDRP_CLASSGUID, SPDRP_DEVICEDESC,
+  SPDRP_DEVICE_POWER_DATA, SPDRP_FRIENDLYNAME, SPDRP_PHYSICAL_DEVICE_OBJECT_NAME,
   SP_DEVICE_INTERFACE_DATA, SP_DEVICE_INTERFACE_DETAIL_DATA_W, SP_DEVINFO_DATA,
 };
+#[cfg(feature = "tokio")]
+use winapi::um::synchapi::SetEvent;
+use winapi::um::synchapi::{CreateEventW, ResetEvent, WaitForMultipleObjects, WaitForSingleObject};
+use winapi::um::winbase::{INFINITE, WAIT_OBJECT_0};
+use winapi::um::winnt::{FILE_SHARE_READ, FILE_SHARE_WRITE};
 
 pub struct HDevInfo {
   native_handle: Option<HDEVINFO>,
@@ -37,6 +95,71 @@ impl Drop for HDevInfo {
   }
 }
 
+// The CM notification functions, which winapi 0.3 doesn't declare. They
+// exist since Windows 8, so they are looked up at run time to keep the crate
+// loadable on Windows 7.
+type CmRegisterNotification = unsafe extern "system" fn(
+  PCM_NOTIFY_FILTER,
+  PVOID,
+  PCM_NOTIFY_CALLBACK,
+  PHCMNOTIFICATION,
+) -> CONFIGRET;
+type CmUnregisterNotification = unsafe extern "system" fn(HCMNOTIFICATION) -> CONFIGRET;
+type CmMapCrToWin32Err = unsafe extern "system" fn(CONFIGRET, DWORD) -> DWORD;
+
+struct CmNotificationApi {
+  register: CmRegisterNotification,
+  unregister: CmUnregisterNotification,
+  map_cr_to_win32_err: CmMapCrToWin32Err,
+}
+
+fn cm_notification_api() -> Option<&'static CmNotificationApi> {
+  static API: OnceLock<Option<CmNotificationApi>> = OnceLock::new();
+  API
+    .get_or_init(|| unsafe {
+      let module = LoadLibraryW(string_to_lpcwstr("cfgmgr32.dll").as_ptr());
+      if module.is_null() {
+        return None;
+      }
+      let register = GetProcAddress(module, b"CM_Register_Notification\0".as_ptr() as LPCSTR);
+      let unregister = GetProcAddress(module, b"CM_Unregister_Notification\0".as_ptr() as LPCSTR);
+      let map_cr_to_win32_err = GetProcAddress(module, b"CM_MapCrToWin32Err\0".as_ptr() as LPCSTR);
+      if register.is_null() || unregister.is_null() || map_cr_to_win32_err.is_null() {
+        return None;
+      }
+      Some(CmNotificationApi {
+        register: mem::transmute::<FARPROC, CmRegisterNotification>(register),
+        unregister: mem::transmute::<FARPROC, CmUnregisterNotification>(unregister),
+        map_cr_to_win32_err: mem::transmute::<FARPROC, CmMapCrToWin32Err>(map_cr_to_win32_err),
+      })
+    })
+    .as_ref()
+}
+
+/// Whether `cm_register_interface_notification` is available, i.e. this is
+/// Windows 8 or later.
+pub fn cm_notifications_supported() -> bool {
+  cm_notification_api().is_some()
+}
+
+/// A PnP notification registration, unregistered on drop. Unregistering
+/// waits for callbacks in progress to return.
+pub struct Notification {
+  native_handle: HCMNOTIFICATION,
+}
+
+// The registration is a process-wide object, usable from any thread.
+unsafe impl Send for Notification {}
+
+impl Drop for Notification {
+  fn drop(&mut self) {
+    // Registrations are only made through the API.
+    if let Some(api) = cm_notification_api() {
+      unsafe { (api.unregister)(self.native_handle) };
+    }
+  }
+}
+
 pub struct Handle {
   pub native_handle: Option<HANDLE>,
 }
@@ -49,26 +172,105 @@ impl Drop for Handle {
   }
 }
 
+// Interface properties of HID collections, from devpkey.h.
+const DEVPKEY_HID_FMTID: GUID = GUID {
+  Data1: 0xcbf3_8310,
+  Data2: 0x4a17,
+  Data3: 0x4310,
+  Data4: [0xa1, 0xeb, 0x24, 0x7f, 0x0b, 0x67, 0x59, 0x3b],
+};
+#[allow(non_upper_case_globals)]
+const DEVPKEY_DeviceInterface_HID_UsagePage: DEVPROPKEY = DEVPROPKEY {
+  fmtid: DEVPKEY_HID_FMTID,
+  pid: 2,
+};
+#[allow(non_upper_case_globals)]
+const DEVPKEY_DeviceInterface_HID_UsageId: DEVPROPKEY = DEVPROPKEY {
+  fmtid: DEVPKEY_HID_FMTID,
+  pid: 3,
+};
+#[allow(non_upper_case_globals)]
+const DEVPKEY_DeviceInterface_HID_VendorId: DEVPROPKEY = DEVPROPKEY {
+  fmtid: DEVPKEY_HID_FMTID,
+  pid: 5,
+};
+#[allow(non_upper_case_globals)]
+const DEVPKEY_DeviceInterface_HID_ProductId: DEVPROPKEY = DEVPROPKEY {
+  fmtid: DEVPKEY_HID_FMTID,
+  pid: 6,
+};
+
+/// A device's preparsed data, freed with `HidD_FreePreparsedData` when
+/// dropped, so a failing `HidP_*` call after `hid_d_get_preparsed_data`
+/// doesn't leak it. Only `hid_d_get_preparsed_data` creates one, so it is
+/// never null.
+pub struct PreparsedData {
+  native_handle: PHIDP_PREPARSED_DATA,
+}
+
+impl Drop for PreparsedData {
+  fn drop(&mut self) {
+    unsafe { HidD_FreePreparsedData(self.native_handle) };
+  }
+}
+
+/// A device property decoded by `decode_device_property`.
+pub enum DevPropValue {
+  String(String),
+  UInt16(u16),
+  UInt32(u32),
+  Guid([u8; 16]),
+  FileTime(FILETIME),
+}
+
 pub struct DeviceInterfaceDetail {
   pub device_path: String,
+  /// The path as returned by Windows, NUL-terminated, for opening the device
+  /// without a round trip through UTF-8.
+  pub device_path_wide: Vec<WCHAR>,
   pub device_info_data: SP_DEVINFO_DATA,
 }
 
+/// Converts the UTF-16 string in the first `length` characters at
+/// `wide_string`, up to the first NUL. A buffer filled without a NUL, as
+/// some devices do with their strings, is taken whole; invalid UTF-16 is
+/// replaced rather than failing.
 fn lpcwstr_to_string(wide_string: LPCWSTR, length: usize) -> String {
-  let slice = unsafe {
-    std::slice::from_raw_parts(wide_string, length)
-      .split(|&v| v == 0)
-      .next()
-      .unwrap()
+  let buffer = unsafe { std::slice::from_raw_parts(wide_string, length) };
+  let end = buffer.iter().position(|&c| c == 0).unwrap_or(length);
+  String::from_utf16_lossy(&buffer[..end])
+}
+
+/// Converts a `FILETIME`, counted in 100ns intervals since 1601-01-01 UTC,
+/// into a `SystemTime`.
+pub fn filetime_to_system_time(ft: FILETIME) -> SystemTime {
+  const INTERVALS_PER_SECOND: u64 = 10_000_000;
+  const UNIX_EPOCH_INTERVALS: u64 = 11_644_473_600 * INTERVALS_PER_SECOND;
+
+  let intervals = (u64::from(ft.dwHighDateTime) << 32) | u64::from(ft.dwLowDateTime);
+  let to_duration = |intervals: u64| {
+    Duration::new(
+      intervals / INTERVALS_PER_SECOND,
+      (intervals % INTERVALS_PER_SECOND) as u32 * 100,
+    )
   };
-  OsString::from_wide(slice).into_string().unwrap()
+
+  if intervals >= UNIX_EPOCH_INTERVALS {
+    UNIX_EPOCH + to_duration(intervals - UNIX_EPOCH_INTERVALS)
+  } else {
+    UNIX_EPOCH - to_duration(UNIX_EPOCH_INTERVALS - intervals)
+  }
 }
 
-fn string_to_lpcwstr(s: &str) -> Vec<WCHAR> {
+pub fn string_to_lpcwstr(s: &str) -> Vec<WCHAR> {
   OsStr::new(s).encode_wide().chain(once(0)).collect()
 }
 
-pub fn setup_di_get_class_devs(
+/// # Safety
+///
+/// `class_guid` must be null or point to a GUID, and `enumerator` must be
+/// null or point to a NUL-terminated UTF-16 string.
+pub unsafe fn setup_di_get_class_devs(
   class_guid: *const GUID,
   enumerator: PCWSTR,
   hwnd_parent: HWND,
@@ -82,6 +284,56 @@ pub fn setup_di_get_class_devs(
   }
 }
 
+/// Same as `setup_di_get_class_devs`, on the computer named `machine_name`,
+/// e.g. `\\\\PC01`, or the local one if `None`.
+///
+/// # Safety
+///
+/// `class_guid` must be null or point to a GUID, and `enumerator` must be
+/// null or point to a NUL-terminated UTF-16 string.
+pub unsafe fn setup_di_get_class_devs_ex(
+  class_guid: *const GUID,
+  enumerator: PCWSTR,
+  hwnd_parent: HWND,
+  flags: DWORD,
+  machine_name: Option<&str>,
+) -> Result<HDevInfo, io::Error> {
+  let machine_name = machine_name.map(string_to_lpcwstr);
+  match unsafe {
+    SetupDiGetClassDevsExW(
+      class_guid,
+      enumerator,
+      hwnd_parent,
+      flags,
+      ptr::null_mut(),
+      machine_name
+        .as_ref()
+        .map_or(ptr::null(), |machine_name| machine_name.as_ptr()),
+      ptr::null_mut(),
+    )
+  } {
+    INVALID_HANDLE_VALUE => Err(io::Error::last_os_error()),
+    handle => Ok(HDevInfo {
+      native_handle: Some(handle),
+    }),
+  }
+}
+
+/// # Safety
+///
+/// `class_guid` must be null or point to a GUID.
+pub unsafe fn setup_di_create_device_info_list(
+  class_guid: *const GUID,
+  hwnd_parent: HWND,
+) -> Result<HDevInfo, io::Error> {
+  match unsafe { SetupDiCreateDeviceInfoList(class_guid, hwnd_parent) } {
+    INVALID_HANDLE_VALUE => Err(io::Error::last_os_error()),
+    handle => Ok(HDevInfo {
+      native_handle: Some(handle),
+    }),
+  }
+}
+
 pub fn setup_di_enum_device_info(
   handle_dev_info: &HDevInfo,
 ) -> Result<std::vec::Vec<winapi::um::setupapi::SP_DEVINFO_DATA>, io::Error> {
@@ -112,7 +364,11 @@ pub fn setup_di_enum_device_info(
   Ok(device_info_entries)
 }
 
-pub fn setup_di_enum_device_interfaces(
+/// # Safety
+///
+/// `device_info_data` must be null or point to an `SP_DEVINFO_DATA` of
+/// `handle_dev_info`, and `interface_class_guid` must point to a GUID.
+pub unsafe fn setup_di_enum_device_interfaces(
   handle_dev_info: &HDevInfo,
   device_info_data: winapi::um::setupapi::PSP_DEVINFO_DATA,
   interface_class_guid: *const winapi::shared::guiddef::GUID,
@@ -146,9 +402,86 @@ pub fn setup_di_enum_device_interfaces(
   Ok(interface_data_entries)
 }
 
-pub fn setup_di_get_device_interface_detail(
+/// Adds the device with the given instance ID to `handle_dev_info`.
+pub fn setup_di_open_device_info(
+  handle_dev_info: &HDevInfo,
+  instance_id: &str,
+) -> Result<winapi::um::setupapi::SP_DEVINFO_DATA, io::Error> {
+  let mut device_info_data: SP_DEVINFO_DATA = unsafe { mem::zeroed() };
+  device_info_data.cbSize = mem::size_of::<SP_DEVINFO_DATA>() as u32;
+
+  if unsafe {
+    SetupDiOpenDeviceInfoW(
+      handle_dev_info.native_handle.unwrap_or(ptr::null_mut()),
+      string_to_lpcwstr(instance_id).as_ptr(),
+      ptr::null_mut(),
+      0,
+      &mut device_info_data,
+    )
+  } == 0
+  {
+    return Err(io::Error::last_os_error());
+  }
+
+  Ok(device_info_data)
+}
+
+pub fn setup_di_open_device_interface(
+  handle_dev_info: &HDevInfo,
+  device_path: &str,
+  open_flags: DWORD,
+) -> Result<winapi::um::setupapi::SP_DEVICE_INTERFACE_DATA, io::Error> {
+  let mut device_interface_data: SP_DEVICE_INTERFACE_DATA = unsafe { mem::zeroed() };
+  device_interface_data.cbSize = mem::size_of::<SP_DEVICE_INTERFACE_DATA>() as u32;
+
+  if unsafe {
+    SetupDiOpenDeviceInterfaceW(
+      handle_dev_info.native_handle.unwrap_or(ptr::null_mut()),
+      string_to_lpcwstr(device_path).as_ptr(),
+      open_flags,
+      &mut device_interface_data,
+    )
+  } == 0
+  {
+    return Err(io::Error::last_os_error());
+  }
+
+  Ok(device_interface_data)
+}
+
+/// The number of WCHARs available for `DevicePath` in an interface detail
+/// buffer of `required_size` bytes, as reported by
+/// `SetupDiGetDeviceInterfaceDetailW`. Sizes too small to hold the struct
+/// itself are rejected rather than trusted.
+fn device_path_capacity(required_size: u32) -> Result<usize, io::Error> {
+  let required_size = required_size as usize;
+  if required_size < mem::size_of::<SP_DEVICE_INTERFACE_DETAIL_DATA_W>() {
+    return Err(io::Error::new(
+      io::ErrorKind::InvalidData,
+      format!(
+        "SetupDiGetDeviceInterfaceDetailW reported a size of {} bytes, less than the {} of \
+         SP_DEVICE_INTERFACE_DETAIL_DATA_W",
+        required_size,
+        mem::size_of::<SP_DEVICE_INTERFACE_DETAIL_DATA_W>()
+      ),
+    ));
+  }
+
+  Ok((required_size - mem::size_of::<DWORD>()) / mem::size_of::<WCHAR>())
+}
+
+/// Reads the interface path and device of `interface_data`. `scratch` is
+/// used as the buffer for the API; pass the same one for every device of an
+/// enumeration to avoid an allocation per device.
+///
+/// # Safety
+///
+/// `interface_data` must point to an `SP_DEVICE_INTERFACE_DATA` of
+/// `handle_dev_info`.
+pub unsafe fn setup_di_get_device_interface_detail(
   handle_dev_info: &HDevInfo,
   interface_data: winapi::um::setupapi::PSP_DEVICE_INTERFACE_DATA,
+  scratch: &mut Vec<u8>,
 ) -> Result<DeviceInterfaceDetail, io::Error> {
   let mut device_info_data: SP_DEVINFO_DATA = unsafe { mem::zeroed() };
   device_info_data.cbSize = mem::size_of::<SP_DEVINFO_DATA>() as u32;
@@ -171,12 +504,13 @@ pub fn setup_di_get_device_interface_detail(
   }
 
   // 2. prepare buffer
-  let mut raw_memory = vec![0u8; required_size as usize];
+  let path_size = device_path_capacity(required_size)?;
+  scratch.clear();
+  scratch.resize(required_size as usize, 0);
   let device_interface_detail_data_ptr: PSP_DEVICE_INTERFACE_DETAIL_DATA_W =
-    raw_memory.as_mut_ptr() as PSP_DEVICE_INTERFACE_DETAIL_DATA_W;
+    scratch.as_mut_ptr() as PSP_DEVICE_INTERFACE_DETAIL_DATA_W;
   let device_path_ptr: PWCHAR =
     unsafe { (*device_interface_detail_data_ptr).DevicePath.as_mut_ptr() };
-  let path_size = (required_size as usize - mem::size_of::<DWORD>()) / mem::size_of::<WCHAR>();
 
   // 3. call the API again to retrieve the information
   if unsafe {
@@ -187,7 +521,7 @@ pub fn setup_di_get_device_interface_detail(
       handle_dev_info.native_handle.unwrap_or(ptr::null_mut()),
       interface_data,
       device_interface_detail_data_ptr,
-      raw_memory.len() as u32,
+      scratch.len() as u32,
       &mut required_size,
       &mut device_info_data,
     )
@@ -196,13 +530,62 @@ pub fn setup_di_get_device_interface_detail(
     return Err(io::Error::last_os_error());
   }
 
+  let device_path_wide: Vec<WCHAR> =
+    unsafe { std::slice::from_raw_parts(device_path_ptr, path_size) }
+      .iter()
+      .copied()
+      .take_while(|&c| c != 0)
+      .chain(once(0))
+      .collect();
   Ok(DeviceInterfaceDetail {
-    device_path: lpcwstr_to_string(device_path_ptr, path_size),
+    device_path: String::from_utf16_lossy(&device_path_wide[..device_path_wide.len() - 1]),
+    device_path_wide,
     device_info_data,
   })
 }
 
-pub fn create_file(
+pub fn setup_di_get_device_instance_id(
+  handle_dev_info: &HDevInfo,
+  device_info_data: &mut winapi::um::setupapi::SP_DEVINFO_DATA,
+) -> Result<String, io::Error> {
+  // 1. retrieve required size of the buffer, in characters
+  let mut required_size: DWORD = 0;
+  if unsafe {
+    SetupDiGetDeviceInstanceIdW(
+      handle_dev_info.native_handle.unwrap_or(ptr::null_mut()),
+      device_info_data,
+      ptr::null_mut(),
+      0,
+      &mut required_size,
+    )
+  } == 0
+    && unsafe { GetLastError() } != ERROR_INSUFFICIENT_BUFFER
+  {
+    return Err(io::Error::last_os_error());
+  }
+
+  // 2. call the API again with a buffer of that size
+  let mut buffer: Vec<WCHAR> = vec![0; required_size as usize];
+  if unsafe {
+    SetupDiGetDeviceInstanceIdW(
+      handle_dev_info.native_handle.unwrap_or(ptr::null_mut()),
+      device_info_data,
+      buffer.as_mut_ptr(),
+      buffer.len() as u32,
+      ptr::null_mut(),
+    )
+  } == 0
+  {
+    return Err(io::Error::last_os_error());
+  }
+
+  Ok(lpcwstr_to_string(buffer.as_ptr(), buffer.len()))
+}
+
+/// # Safety
+///
+/// `security_attributes` must be null or point to a `SECURITY_ATTRIBUTES`.
+pub unsafe fn create_file(
   file_name: &str,
   desired_access: DWORD,
   share_mode: DWORD,
@@ -211,9 +594,39 @@ pub fn create_file(
   flags_and_attributes: DWORD,
   template_file: Handle,
 ) -> Result<Handle, io::Error> {
+  create_file_wide(
+    &string_to_lpcwstr(file_name),
+    desired_access,
+    share_mode,
+    security_attributes,
+    creation_disposition,
+    flags_and_attributes,
+    template_file,
+  )
+}
+
+/// Same as `create_file`, for a NUL-terminated UTF-16 `file_name`.
+///
+/// # Safety
+///
+/// As for `create_file`.
+pub unsafe fn create_file_wide(
+  file_name: &[WCHAR],
+  desired_access: DWORD,
+  share_mode: DWORD,
+  security_attributes: winapi::um::minwinbase::LPSECURITY_ATTRIBUTES,
+  creation_disposition: DWORD,
+  flags_and_attributes: DWORD,
+  template_file: Handle,
+) -> Result<Handle, io::Error> {
+  debug_assert_eq!(
+    file_name.last(),
+    Some(&0),
+    "file name must be NUL-terminated"
+  );
   match unsafe {
     CreateFileW(
-      string_to_lpcwstr(file_name).as_ptr(),
+      file_name.as_ptr(),
       desired_access,
       share_mode,
       security_attributes,
@@ -229,103 +642,1762 @@ pub fn create_file(
   }
 }
 
-pub fn hid_d_get_attributes(
-  handle: &Handle,
-) -> Result<winapi::shared::hidsdi::HIDD_ATTRIBUTES, io::Error> {
-  let mut attr: HIDD_ATTRIBUTES = unsafe { mem::zeroed() };
+pub fn read_file(handle: &Handle, buffer: &mut [u8]) -> Result<usize, io::Error> {
+  let mut bytes_read: DWORD = 0;
 
-  if unsafe { HidD_GetAttributes(handle.native_handle.unwrap_or(ptr::null_mut()), &mut attr) } == 0
+  if unsafe {
+    ReadFile(
+      handle.native_handle.unwrap_or(ptr::null_mut()),
+      buffer.as_mut_ptr() as PVOID,
+      buffer.len() as DWORD,
+      &mut bytes_read,
+      ptr::null_mut(),
+    )
+  } == 0
   {
     return Err(io::Error::last_os_error());
   }
 
-  Ok(attr)
+  Ok(bytes_read as usize)
 }
 
-fn setup_di_get_device_registry_property(
-  handle_dev_info: &HDevInfo,
-  device_info_data: &mut winapi::um::setupapi::SP_DEVINFO_DATA,
-  property: DWORD,
-) -> Result<Vec<u8>, io::Error> {
-  let mut property_reg_data_type: DWORD = 0;
-  let mut required_size: DWORD = 0;
-  unsafe {
-    SetupDiGetDeviceRegistryPropertyW(
-      handle_dev_info.native_handle.unwrap_or(ptr::null_mut()),
-      device_info_data,
-      property,
-      &mut property_reg_data_type,
-      ptr::null_mut(),
-      0,
-      &mut required_size,
-    );
-  };
+/// Registers `callback` for arrivals and removals of interfaces of the class
+/// `class_guid`. It is called on a system thread pool thread with `context`.
+/// Fails with `ERROR_PROC_NOT_FOUND` before Windows 8.
+///
+/// # Safety
+///
+/// `context` must stay valid for `callback` until the returned registration
+/// is dropped.
+pub unsafe fn cm_register_interface_notification(
+  class_guid: &GUID,
+  context: PVOID,
+  callback: PCM_NOTIFY_CALLBACK,
+) -> Result<Notification, io::Error> {
+  let mut filter: CM_NOTIFY_FILTER = mem::zeroed();
+  filter.cbSize = mem::size_of::<CM_NOTIFY_FILTER>() as DWORD;
+  filter.FilterType = CM_NOTIFY_FILTER_TYPE_DEVICEINTERFACE;
+  filter.u.DeviceInterface_mut().ClassGuid = *class_guid;
+  cm_register_notification(&mut filter, context, callback)
+}
 
-  let mut raw_memory = vec![0u8; required_size as usize];
-  unsafe {
-    SetupDiGetDeviceRegistryPropertyW(
-      handle_dev_info.native_handle.unwrap_or(ptr::null_mut()),
-      device_info_data,
-      property,
-      &mut property_reg_data_type,
-      raw_memory.as_mut_ptr(),
-      raw_memory.len() as u32,
-      ptr::null_mut(),
-    );
-  };
+/// Registers `callback` for the removal events of the device `handle` is
+/// open on, like `cm_register_interface_notification` does for interfaces.
+///
+/// # Safety
+///
+/// `context` must stay valid for `callback` until the returned registration
+/// is dropped.
+pub unsafe fn cm_register_handle_notification(
+  handle: &Handle,
+  context: PVOID,
+  callback: PCM_NOTIFY_CALLBACK,
+) -> Result<Notification, io::Error> {
+  let mut filter: CM_NOTIFY_FILTER = mem::zeroed();
+  filter.cbSize = mem::size_of::<CM_NOTIFY_FILTER>() as DWORD;
+  filter.FilterType = CM_NOTIFY_FILTER_TYPE_DEVICEHANDLE;
+  filter.u.DeviceHandle_mut().hTarget = handle.native_handle.unwrap_or(INVALID_HANDLE_VALUE);
+  cm_register_notification(&mut filter, context, callback)
+}
 
-  Ok(raw_memory)
+unsafe fn cm_register_notification(
+  filter: &mut CM_NOTIFY_FILTER,
+  context: PVOID,
+  callback: PCM_NOTIFY_CALLBACK,
+) -> Result<Notification, io::Error> {
+  let api = cm_notification_api()
+    .ok_or_else(|| io::Error::from_raw_os_error(ERROR_PROC_NOT_FOUND as i32))?;
+
+  let mut native_handle: HCMNOTIFICATION = ptr::null_mut();
+  match (api.register)(filter, context, callback, &mut native_handle) {
+    CR_SUCCESS => Ok(Notification { native_handle }),
+    cr => Err(io::Error::from_raw_os_error(
+      (api.map_cr_to_win32_err)(cr, ERROR_GEN_FAILURE) as i32,
+    )),
+  }
 }
 
-pub fn get_pdo_name(
-  handle_dev_info: &HDevInfo,
-  device_info_data: Option<winapi::um::setupapi::SP_DEVINFO_DATA>,
-) -> Option<String> {
-  let mut info_data = match device_info_data {
-    None => return None,
-    Some(data) => data,
-  };
+/// Reads the interface path from the data of an interface arrival or removal
+/// notification of `size` bytes.
+///
+/// # Safety
+///
+/// `event_data` must point to `size` readable bytes of a
+/// `CM_NOTIFY_FILTER_TYPE_DEVICEINTERFACE` notification.
+pub unsafe fn notification_interface_path(
+  event_data: PCM_NOTIFY_EVENT_DATA,
+  size: DWORD,
+) -> String {
+  let symbolic_link = (*event_data).u.DeviceInterface().SymbolicLink.as_ptr();
+  let offset = symbolic_link as usize - event_data as usize;
+  let length = (size as usize).saturating_sub(offset) / mem::size_of::<WCHAR>();
+  let path = slice::from_raw_parts(symbolic_link, length);
+  let end = path.iter().position(|&c| c == 0).unwrap_or(length);
+  String::from_utf16_lossy(&path[..end])
+}
 
-  let mut buffer = match setup_di_get_device_registry_property(
-    &handle_dev_info,
-    &mut info_data,
-    SPDRP_PHYSICAL_DEVICE_OBJECT_NAME,
-  ) {
-    Err(_) => return None,
-    Ok(b) => b,
-  };
+/// Cancels the pending I/O on `handle` from all threads of the process,
+/// synchronous and overlapped alike.
+pub fn cancel_io(handle: &Handle) {
+  if let Some(native_handle) = handle.native_handle {
+    unsafe { CancelIoEx(native_handle, ptr::null_mut()) };
+  }
+}
+
+/// Creates a manual-reset event, initially not signaled.
+pub fn create_event() -> Result<Handle, io::Error> {
+  let event = unsafe { CreateEventW(ptr::null_mut(), TRUE, FALSE as i32, ptr::null()) };
+  if event.is_null() {
+    return Err(io::Error::last_os_error());
+  }
 
-  let device_path_ptr: PWCHAR = buffer.as_mut_ptr() as PWCHAR;
-  let device_path_size = (buffer.len()) / mem::size_of::<WCHAR>();
-  Some(lpcwstr_to_string(device_path_ptr, device_path_size))
+  Ok(Handle {
+    native_handle: Some(event),
+  })
 }
 
-pub fn hid_d_get_product_string(handle: &Handle) -> Option<String> {
-  unsafe {
-    const MAXSIZE: usize = 127;
-    let mut buffer: [WCHAR; MAXSIZE] = std::mem::zeroed();
-    match HidD_GetProductString(
-      handle.native_handle.unwrap_or(ptr::null_mut()),
+/// Reads from a handle opened with `FILE_FLAG_OVERLAPPED`, waiting at most
+/// `timeout` (forever if `None`) for the read to complete. `event` must be a
+/// manual-reset event; it is reset before the read is issued.
+///
+/// Returns `Ok(None)` on timeout, or when `cancel_event` is signaled. The
+/// pending read is cancelled and waited for before returning, so `buffer` is
+/// never written afterwards; if the read completes while being cancelled,
+/// its result is returned instead.
+pub fn read_file_overlapped(
+  handle: &Handle,
+  event: &Handle,
+  buffer: &mut [u8],
+  timeout: Option<Duration>,
+  cancel_event: Option<&Handle>,
+) -> Result<Option<usize>, io::Error> {
+  let native_handle = handle.native_handle.unwrap_or(ptr::null_mut());
+  let mut overlapped: OVERLAPPED = unsafe { mem::zeroed() };
+  overlapped.hEvent = event.native_handle.unwrap_or(ptr::null_mut());
+
+  if unsafe { ResetEvent(overlapped.hEvent) } == 0 {
+    return Err(io::Error::last_os_error());
+  }
+
+  if unsafe {
+    ReadFile(
+      native_handle,
       buffer.as_mut_ptr() as PVOID,
-      buffer.len() as u32,
-    ) {
-      FALSE => None,
-      _ => Some(lpcwstr_to_string(buffer.as_ptr(), buffer.len())),
+      buffer.len() as DWORD,
+      ptr::null_mut(),
+      &mut overlapped,
+    )
+  } == 0
+  {
+    let error = unsafe { GetLastError() };
+    if error != ERROR_IO_PENDING {
+      return Err(io::Error::from_raw_os_error(error as i32));
     }
   }
+
+  wait_overlapped(native_handle, &mut overlapped, timeout, cancel_event)
 }
 
-pub fn hid_d_get_serial_number_string(handle: &Handle) -> Option<String> {
-  unsafe {
-    const MAXSIZE: usize = 127;
-    let mut buffer: [WCHAR; MAXSIZE] = std::mem::zeroed();
-    match HidD_GetSerialNumberString(
+pub fn write_file(handle: &Handle, buffer: &[u8]) -> Result<usize, io::Error> {
+  let mut bytes_written: DWORD = 0;
+
+  if unsafe {
+    WriteFile(
       handle.native_handle.unwrap_or(ptr::null_mut()),
-      buffer.as_mut_ptr() as PVOID,
-      buffer.len() as u32,
-    ) {
-      FALSE => None,
-      _ => Some(lpcwstr_to_string(buffer.as_ptr(), buffer.len())),
+      buffer.as_ptr() as LPCVOID,
+      buffer.len() as DWORD,
+      &mut bytes_written,
+      ptr::null_mut(),
+    )
+  } == 0
+  {
+    return Err(io::Error::last_os_error());
+  }
+
+  Ok(bytes_written as usize)
+}
+
+/// Writes to a handle opened with `FILE_FLAG_OVERLAPPED` and waits for the
+/// write to complete. `event` must be a manual-reset event.
+///
+/// Returns `Ok(None)` if `cancel_event` is signaled first, after the pending
+/// write has been cancelled and waited for.
+pub fn write_file_overlapped(
+  handle: &Handle,
+  event: &Handle,
+  buffer: &[u8],
+  cancel_event: Option<&Handle>,
+) -> Result<Option<usize>, io::Error> {
+  let native_handle = handle.native_handle.unwrap_or(ptr::null_mut());
+  let mut overlapped: OVERLAPPED = unsafe { mem::zeroed() };
+  overlapped.hEvent = event.native_handle.unwrap_or(ptr::null_mut());
+
+  if unsafe { ResetEvent(overlapped.hEvent) } == 0 {
+    return Err(io::Error::last_os_error());
+  }
+
+  if unsafe {
+    WriteFile(
+      native_handle,
+      buffer.as_ptr() as LPCVOID,
+      buffer.len() as DWORD,
+      ptr::null_mut(),
+      &mut overlapped,
+    )
+  } == 0
+  {
+    let error = unsafe { GetLastError() };
+    if error != ERROR_IO_PENDING {
+      return Err(io::Error::from_raw_os_error(error as i32));
+    }
+  }
+
+  wait_overlapped(native_handle, &mut overlapped, None, cancel_event)
+}
+
+/// Waits for the overlapped operation `overlapped` on `native_handle` to
+/// complete, `timeout` to elapse or `cancel_event` to be signaled. In the
+/// latter two cases the operation is cancelled and `Ok(None)` returned once
+/// it has settled, unless it completed in the meantime.
+fn wait_overlapped(
+  native_handle: HANDLE,
+  overlapped: &mut OVERLAPPED,
+  timeout: Option<Duration>,
+  cancel_event: Option<&Handle>,
+) -> Result<Option<usize>, io::Error> {
+  let mut bytes_transferred: DWORD = 0;
+
+  // INFINITE is u32::MAX, so longer timeouts are clamped just below it.
+  let milliseconds = timeout.map_or(INFINITE, |timeout| {
+    timeout.as_millis().min((INFINITE - 1) as u128) as DWORD
+  });
+  let wait = match cancel_event.and_then(|event| event.native_handle) {
+    Some(cancel_event) => {
+      let events = [overlapped.hEvent, cancel_event];
+      match unsafe {
+        WaitForMultipleObjects(
+          events.len() as DWORD,
+          events.as_ptr(),
+          FALSE as i32,
+          milliseconds,
+        )
+      } {
+        // The cancel event counts as a timeout.
+        wait if wait == WAIT_OBJECT_0 + 1 => WAIT_TIMEOUT,
+        wait => wait,
+      }
+    }
+    None => unsafe { WaitForSingleObject(overlapped.hEvent, milliseconds) },
+  };
+
+  match wait {
+    WAIT_OBJECT_0 => {}
+    WAIT_TIMEOUT => {
+      unsafe { CancelIoEx(native_handle, overlapped) };
+
+      // The operation may have completed between the wait ending and the
+      // cancel, so wait for it to settle and keep a completed result.
+      if unsafe { GetOverlappedResult(native_handle, overlapped, &mut bytes_transferred, TRUE) }
+        == 0
+      {
+        let error = io::Error::last_os_error();
+        if error.raw_os_error() == Some(ERROR_OPERATION_ABORTED as i32) {
+          return Ok(None);
+        }
+        return Err(error);
+      }
+      return Ok(Some(bytes_transferred as usize));
     }
+    _ => {
+      let error = io::Error::last_os_error();
+      unsafe {
+        CancelIoEx(native_handle, overlapped);
+        GetOverlappedResult(native_handle, overlapped, &mut bytes_transferred, TRUE);
+      }
+      return Err(error);
+    }
+  }
+
+  if unsafe {
+    GetOverlappedResult(
+      native_handle,
+      overlapped,
+      &mut bytes_transferred,
+      FALSE as i32,
+    )
+  } == 0
+  {
+    return Err(io::Error::last_os_error());
+  }
+
+  Ok(Some(bytes_transferred as usize))
+}
+
+/// Signals `event`.
+#[cfg(feature = "tokio")]
+pub fn set_event(event: &Handle) -> Result<(), io::Error> {
+  if unsafe { SetEvent(event.native_handle.unwrap_or(ptr::null_mut())) } == 0 {
+    return Err(io::Error::last_os_error());
+  }
+
+  Ok(())
+}
+
+pub fn hid_d_get_attributes(
+  handle: &Handle,
+) -> Result<winapi::shared::hidsdi::HIDD_ATTRIBUTES, io::Error> {
+  let mut attr: HIDD_ATTRIBUTES = unsafe { mem::zeroed() };
+  // The HIDD_ATTRIBUTES documentation (hidsdi.h) says the caller sets Size to
+  // sizeof(HIDD_ATTRIBUTES); some drivers reject a zero Size.
+  attr.Size = mem::size_of::<HIDD_ATTRIBUTES>() as ULONG;
+
+  if unsafe { HidD_GetAttributes(handle.native_handle.unwrap_or(ptr::null_mut()), &mut attr) } == 0
+  {
+    return Err(io::Error::last_os_error());
+  }
+
+  if attr.Size as usize != mem::size_of::<HIDD_ATTRIBUTES>() {
+    return Err(io::Error::new(
+      io::ErrorKind::InvalidData,
+      format!(
+        "HidD_GetAttributes returned Size {}, expected {}",
+        attr.Size,
+        mem::size_of::<HIDD_ATTRIBUTES>()
+      ),
+    ));
+  }
+
+  Ok(attr)
+}
+
+/// Wraps a failed `HidP_*` status in an `io::Error`. Usages missing from the
+/// report, or from the report with that ID, give `ErrorKind::NotFound`.
+pub fn hid_p_status_to_error(function: &str, status: NTSTATUS) -> io::Error {
+  let kind = match status {
+    HIDP_STATUS_USAGE_NOT_FOUND | HIDP_STATUS_INCOMPATIBLE_REPORT_ID => io::ErrorKind::NotFound,
+    _ => io::ErrorKind::Other,
+  };
+  io::Error::new(
+    kind,
+    format!("{} failed with status {:#010x}", function, status as u32),
+  )
+}
+
+pub fn hid_d_get_preparsed_data(handle: &Handle) -> Result<PreparsedData, io::Error> {
+  let mut preparsed_data: PHIDP_PREPARSED_DATA = ptr::null_mut();
+
+  if unsafe {
+    HidD_GetPreparsedData(
+      handle.native_handle.unwrap_or(ptr::null_mut()),
+      &mut preparsed_data,
+    )
+  } == 0
+  {
+    return Err(io::Error::last_os_error());
+  }
+
+  Ok(PreparsedData {
+    native_handle: preparsed_data,
+  })
+}
+
+pub fn hid_p_get_caps(preparsed_data: &PreparsedData) -> Result<HIDP_CAPS, io::Error> {
+  let mut caps: HIDP_CAPS = unsafe { mem::zeroed() };
+
+  match unsafe { HidP_GetCaps(preparsed_data.native_handle, &mut caps) } {
+    HIDP_STATUS_SUCCESS => Ok(caps),
+    status => Err(hid_p_status_to_error("HidP_GetCaps", status)),
+  }
+}
+
+pub fn hid_p_get_button_caps(
+  report_type: HIDP_REPORT_TYPE,
+  preparsed_data: &PreparsedData,
+  count: u16,
+) -> Result<Vec<HIDP_BUTTON_CAPS>, io::Error> {
+  let mut button_caps: Vec<HIDP_BUTTON_CAPS> = vec![unsafe { mem::zeroed() }; count as usize];
+  if count == 0 {
+    return Ok(button_caps);
+  }
+
+  let mut length: USHORT = count;
+  match unsafe {
+    HidP_GetButtonCaps(
+      report_type,
+      button_caps.as_mut_ptr(),
+      &mut length,
+      preparsed_data.native_handle,
+    )
+  } {
+    HIDP_STATUS_SUCCESS => {
+      button_caps.truncate(length as usize);
+      Ok(button_caps)
+    }
+    status => Err(hid_p_status_to_error("HidP_GetButtonCaps", status)),
+  }
+}
+
+pub fn hid_p_get_value_caps(
+  report_type: HIDP_REPORT_TYPE,
+  preparsed_data: &PreparsedData,
+  count: u16,
+) -> Result<Vec<HIDP_VALUE_CAPS>, io::Error> {
+  let mut value_caps: Vec<HIDP_VALUE_CAPS> = vec![unsafe { mem::zeroed() }; count as usize];
+  if count == 0 {
+    return Ok(value_caps);
+  }
+
+  let mut length: USHORT = count;
+  match unsafe {
+    HidP_GetValueCaps(
+      report_type,
+      value_caps.as_mut_ptr(),
+      &mut length,
+      preparsed_data.native_handle,
+    )
+  } {
+    HIDP_STATUS_SUCCESS => {
+      value_caps.truncate(length as usize);
+      Ok(value_caps)
+    }
+    status => Err(hid_p_status_to_error("HidP_GetValueCaps", status)),
+  }
+}
+
+pub fn hid_p_get_link_collection_nodes(
+  preparsed_data: &PreparsedData,
+  count: u16,
+) -> Result<Vec<HIDP_LINK_COLLECTION_NODE>, io::Error> {
+  let mut nodes: Vec<HIDP_LINK_COLLECTION_NODE> = vec![unsafe { mem::zeroed() }; count as usize];
+  if count == 0 {
+    return Ok(nodes);
+  }
+
+  let mut length: ULONG = count as ULONG;
+  match unsafe {
+    HidP_GetLinkCollectionNodes(
+      nodes.as_mut_ptr(),
+      &mut length,
+      preparsed_data.native_handle,
+    )
+  } {
+    HIDP_STATUS_SUCCESS => {
+      nodes.truncate(length as usize);
+      Ok(nodes)
+    }
+    status => Err(hid_p_status_to_error("HidP_GetLinkCollectionNodes", status)),
+  }
+}
+
+/// Lists the buttons on `usage_page` that are set in `report`, in any link
+/// collection.
+pub fn hid_p_get_usages(
+  report_type: HIDP_REPORT_TYPE,
+  usage_page: u16,
+  preparsed_data: &PreparsedData,
+  report: &[u8],
+) -> Result<Vec<u16>, io::Error> {
+  let max_length =
+    unsafe { HidP_MaxUsageListLength(report_type, usage_page, preparsed_data.native_handle) };
+  let mut usages = vec![0u16; max_length as usize];
+  if max_length == 0 {
+    return Ok(usages);
+  }
+
+  let mut length: ULONG = max_length;
+  // The report is only read, despite the non-const pointer.
+  match unsafe {
+    HidP_GetUsages(
+      report_type,
+      usage_page,
+      0,
+      usages.as_mut_ptr(),
+      &mut length,
+      preparsed_data.native_handle,
+      report.as_ptr() as PCHAR,
+      report.len() as ULONG,
+    )
+  } {
+    HIDP_STATUS_SUCCESS => {
+      usages.truncate(length as usize);
+      Ok(usages)
+    }
+    status => Err(hid_p_status_to_error("HidP_GetUsages", status)),
+  }
+}
+
+/// Reads the raw value of `usage` in `report`.
+pub fn hid_p_get_usage_value(
+  report_type: HIDP_REPORT_TYPE,
+  usage_page: u16,
+  link_collection: u16,
+  usage: u16,
+  preparsed_data: &PreparsedData,
+  report: &[u8],
+) -> Result<u32, io::Error> {
+  let mut value: ULONG = 0;
+  // The report is only read, despite the non-const pointer.
+  match unsafe {
+    HidP_GetUsageValue(
+      report_type,
+      usage_page,
+      link_collection,
+      usage,
+      &mut value,
+      preparsed_data.native_handle,
+      report.as_ptr() as PCHAR,
+      report.len() as ULONG,
+    )
+  } {
+    HIDP_STATUS_SUCCESS => Ok(value),
+    status => Err(hid_p_status_to_error("HidP_GetUsageValue", status)),
+  }
+}
+
+/// Sets `report` up as an empty report with ID `report_id`, with every value
+/// at its null value if it has one.
+pub fn hid_p_initialize_report_for_id(
+  report_type: HIDP_REPORT_TYPE,
+  report_id: u8,
+  preparsed_data: &PreparsedData,
+  report: &mut [u8],
+) -> Result<(), io::Error> {
+  match unsafe {
+    HidP_InitializeReportForID(
+      report_type,
+      report_id,
+      preparsed_data.native_handle,
+      report.as_mut_ptr() as PCHAR,
+      report.len() as ULONG,
+    )
+  } {
+    HIDP_STATUS_SUCCESS => Ok(()),
+    status => Err(hid_p_status_to_error("HidP_InitializeReportForID", status)),
+  }
+}
+
+/// Sets (`on`) or clears the button `usage` in `report`.
+pub fn hid_p_set_usage(
+  report_type: HIDP_REPORT_TYPE,
+  usage_page: u16,
+  usage: u16,
+  on: bool,
+  preparsed_data: &PreparsedData,
+  report: &mut [u8],
+) -> Result<(), io::Error> {
+  let mut usages = [usage];
+  let mut length: ULONG = 1;
+  let status = unsafe {
+    if on {
+      HidP_SetUsages(
+        report_type,
+        usage_page,
+        0,
+        usages.as_mut_ptr(),
+        &mut length,
+        preparsed_data.native_handle,
+        report.as_mut_ptr() as PCHAR,
+        report.len() as ULONG,
+      )
+    } else {
+      HidP_UnsetUsages(
+        report_type,
+        usage_page,
+        0,
+        usages.as_mut_ptr(),
+        &mut length,
+        preparsed_data.native_handle,
+        report.as_mut_ptr() as PCHAR,
+        report.len() as ULONG,
+      )
+    }
+  };
+  match status {
+    // Clearing a button that isn't set.
+    HIDP_STATUS_SUCCESS | HIDP_STATUS_BUTTON_NOT_PRESSED => Ok(()),
+    status if on => Err(hid_p_status_to_error("HidP_SetUsages", status)),
+    status => Err(hid_p_status_to_error("HidP_UnsetUsages", status)),
+  }
+}
+
+/// Sets the raw value of `usage` in `report`.
+pub fn hid_p_set_usage_value(
+  report_type: HIDP_REPORT_TYPE,
+  usage_page: u16,
+  usage: u16,
+  value: u32,
+  preparsed_data: &PreparsedData,
+  report: &mut [u8],
+) -> Result<(), io::Error> {
+  match unsafe {
+    HidP_SetUsageValue(
+      report_type,
+      usage_page,
+      0,
+      usage,
+      value,
+      preparsed_data.native_handle,
+      report.as_mut_ptr() as PCHAR,
+      report.len() as ULONG,
+    )
+  } {
+    HIDP_STATUS_SUCCESS => Ok(()),
+    status => Err(hid_p_status_to_error("HidP_SetUsageValue", status)),
+  }
+}
+
+/// Reads the value of `usage` in `report` scaled to physical units. Fails
+/// with the raw status, e.g. `HIDP_STATUS_NULL` for a value in its null state
+/// or `HIDP_STATUS_BAD_LOG_PHY_VALUES` if the physical range is unusable.
+pub fn hid_p_get_scaled_usage_value(
+  report_type: HIDP_REPORT_TYPE,
+  usage_page: u16,
+  link_collection: u16,
+  usage: u16,
+  preparsed_data: &PreparsedData,
+  report: &[u8],
+) -> Result<i32, NTSTATUS> {
+  let mut value: LONG = 0;
+  // The report is only read, despite the non-const pointer.
+  match unsafe {
+    HidP_GetScaledUsageValue(
+      report_type,
+      usage_page,
+      link_collection,
+      usage,
+      &mut value,
+      preparsed_data.native_handle,
+      report.as_ptr() as PCHAR,
+      report.len() as ULONG,
+    )
+  } {
+    HIDP_STATUS_SUCCESS => Ok(value),
+    status => Err(status),
+  }
+}
+
+/// Translates keyboard page usages to scan code set 1 codes, i.e. make codes
+/// if `make`, otherwise break codes, with the `0xE0` prefix for extended keys.
+pub fn hid_p_translate_usages_to_i8042_scan_codes(
+  usages: &[u16],
+  make: bool,
+) -> Result<Vec<u8>, io::Error> {
+  unsafe extern "system" fn insert_codes(context: PVOID, codes: PCHAR, length: ULONG) -> BOOLEAN {
+    let scan_codes = &mut *(context as *mut Vec<u8>);
+    scan_codes.extend_from_slice(slice::from_raw_parts(codes as *const u8, length as usize));
+    TRUE as BOOLEAN
+  }
+
+  let mut usages = usages.to_vec();
+  let mut modifier_state: HIDP_KEYBOARD_MODIFIER_STATE = unsafe { mem::zeroed() };
+  let mut scan_codes: Vec<u8> = Vec::new();
+  match unsafe {
+    HidP_TranslateUsagesToI8042ScanCodes(
+      usages.as_mut_ptr(),
+      usages.len() as ULONG,
+      if make {
+        HidP_Keyboard_Make
+      } else {
+        HidP_Keyboard_Break
+      },
+      &mut modifier_state,
+      Some(insert_codes),
+      &mut scan_codes as *mut Vec<u8> as PVOID,
+    )
+  } {
+    HIDP_STATUS_SUCCESS => Ok(scan_codes),
+    status => Err(hid_p_status_to_error(
+      "HidP_TranslateUsagesToI8042ScanCodes",
+      status,
+    )),
+  }
+}
+
+/// Reads a device registry property into `buffer`. The first attempt uses
+/// the buffer's whole capacity, which is enough for most properties once the
+/// buffer is reused, so they take a single call; otherwise the buffer is
+/// sized from the required size SetupAPI reports, and grown again a few
+/// times if the property grows in between.
+fn setup_di_get_device_registry_property(
+  handle_dev_info: &HDevInfo,
+  device_info_data: &mut winapi::um::setupapi::SP_DEVINFO_DATA,
+  property: DWORD,
+  buffer: &mut Vec<u8>,
+) -> Result<(), io::Error> {
+  let mut property_reg_data_type: DWORD = 0;
+  read_into_growing_buffer(buffer, |buffer, required_size| {
+    if unsafe {
+      SetupDiGetDeviceRegistryPropertyW(
+        handle_dev_info.native_handle.unwrap_or(ptr::null_mut()),
+        device_info_data,
+        property,
+        &mut property_reg_data_type,
+        if buffer.is_empty() {
+          ptr::null_mut()
+        } else {
+          buffer.as_mut_ptr()
+        },
+        buffer.len() as u32,
+        required_size,
+      )
+    } != 0
+    {
+      Ok(())
+    } else {
+      Err(io::Error::last_os_error())
+    }
+  })
+}
+
+/// Calls `read` with `buffer` and the size to store the required size in,
+/// growing `buffer` to that size and calling again while it fails with
+/// `ERROR_INSUFFICIENT_BUFFER`, a few times at most. On success `buffer` is
+/// cut to the required size.
+fn read_into_growing_buffer<F>(buffer: &mut Vec<u8>, mut read: F) -> Result<(), io::Error>
+where
+  F: FnMut(&mut [u8], &mut DWORD) -> Result<(), io::Error>,
+{
+  const MAX_ATTEMPTS: usize = 4;
+  const MIN_SIZE: usize = 256;
+
+  let mut required_size = buffer.capacity().max(MIN_SIZE) as DWORD;
+  buffer.clear();
+  for _ in 0..MAX_ATTEMPTS {
+    buffer.resize(required_size as usize, 0);
+    match read(buffer, &mut required_size) {
+      Ok(()) => {
+        buffer.truncate(required_size as usize);
+        return Ok(());
+      }
+      Err(error) if error.raw_os_error() == Some(ERROR_INSUFFICIENT_BUFFER as i32) => {}
+      Err(error) => return Err(error),
+    }
+  }
+
+  Err(io::Error::from_raw_os_error(
+    ERROR_INSUFFICIENT_BUFFER as i32,
+  ))
+}
+
+/// Device registry properties read by `get_registry_properties`, as their
+/// raw data by `SPDRP_*` code.
+pub type RegistryProperties = HashMap<DWORD, Vec<u8>>;
+
+/// The registry properties `HidDevice` is filled in from, for
+/// `get_registry_properties`.
+pub const HID_DEVICE_REGISTRY_PROPERTIES: [DWORD; 6] = [
+  SPDRP_PHYSICAL_DEVICE_OBJECT_NAME,
+  SPDRP_CLASSGUID,
+  SPDRP_CLASS,
+  SPDRP_FRIENDLYNAME,
+  SPDRP_DEVICEDESC,
+  SPDRP_DEVICE_POWER_DATA,
+];
+
+/// Reads the registry `properties` of one device in a single pass, sharing
+/// `buffer` between them, and returns the raw data of those it has. Each is
+/// decoded by its own getter, e.g. `get_class_name`.
+pub fn get_registry_properties(
+  handle_dev_info: &HDevInfo,
+  device_info_data: Option<winapi::um::setupapi::SP_DEVINFO_DATA>,
+  properties: &[DWORD],
+  buffer: &mut Vec<u8>,
+) -> RegistryProperties {
+  let mut info_data = match device_info_data {
+    Some(info_data) => info_data,
+    None => return RegistryProperties::new(),
+  };
+
+  properties
+    .iter()
+    .filter_map(|&property| {
+      setup_di_get_device_registry_property(handle_dev_info, &mut info_data, property, buffer)
+        .ok()?;
+      Some((property, buffer.clone()))
+    })
+    .collect()
+}
+
+/// Decodes the `REG_SZ` registry property `property`.
+fn registry_string(properties: &RegistryProperties, property: DWORD) -> Option<String> {
+  let data: Vec<u16> = properties
+    .get(&property)?
+    .chunks_exact(mem::size_of::<WCHAR>())
+    .map(|chunk| u16::from_le_bytes([chunk[0], chunk[1]]))
+    .collect();
+  Some(lpcwstr_to_string(data.as_ptr(), data.len()))
+}
+
+pub fn get_pdo_name(properties: &RegistryProperties) -> Option<String> {
+  registry_string(properties, SPDRP_PHYSICAL_DEVICE_OBJECT_NAME)
+}
+
+/// Reads the device's setup class GUID, uppercased to match the GUIDs
+/// formatted from binary properties.
+pub fn get_class_guid(properties: &RegistryProperties) -> Option<String> {
+  registry_string(properties, SPDRP_CLASSGUID).map(|guid| guid.to_ascii_uppercase())
+}
+
+/// Reads the device's setup class name, e.g. `HIDClass` or `Keyboard`.
+pub fn get_class_name(properties: &RegistryProperties) -> Option<String> {
+  registry_string(properties, SPDRP_CLASS)
+}
+
+/// Reads the device's description from its driver's INF, e.g. `HID-compliant
+/// mouse`.
+pub fn get_device_desc(properties: &RegistryProperties) -> Option<String> {
+  registry_string(properties, SPDRP_DEVICEDESC)
+}
+
+/// Reads the device's friendly name, which only some drivers set.
+pub fn get_friendly_name(properties: &RegistryProperties) -> Option<String> {
+  registry_string(properties, SPDRP_FRIENDLYNAME)
+}
+
+/// The parts of a device's `CM_POWER_DATA` listhid exposes.
+pub struct PowerInfo {
+  /// Whether the device can wake the system from any device power state.
+  pub wake_capable: bool,
+  /// The device power state it was last put in, 1 for D0 to 4 for D3.
+  pub current_power_state: u32,
+}
+
+/// Decodes the leading fields of a `CM_POWER_DATA`, as returned for
+/// `SPDRP_DEVICE_POWER_DATA`: `PD_Size`, `PD_MostRecentPowerState` and
+/// `PD_Capabilities`, each 4 bytes. Returns `None` if `buf` is too short.
+pub fn parse_cm_power_data(buf: &[u8]) -> Option<PowerInfo> {
+  // PDCAP_WAKE_FROM_D0_SUPPORTED to PDCAP_WAKE_FROM_D3_SUPPORTED.
+  const PDCAP_WAKE_FROM_ANY_D: u32 = 0x10 | 0x20 | 0x40 | 0x80;
+
+  let ulong_at = |offset: usize| -> Option<u32> {
+    let bytes = buf.get(offset..offset + 4)?;
+    Some(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+  };
+  let current_power_state = ulong_at(4)?;
+  let capabilities = ulong_at(8)?;
+
+  Some(PowerInfo {
+    wake_capable: capabilities & PDCAP_WAKE_FROM_ANY_D != 0,
+    current_power_state,
+  })
+}
+
+pub fn get_power_data(properties: &RegistryProperties) -> Option<PowerInfo> {
+  parse_cm_power_data(properties.get(&SPDRP_DEVICE_POWER_DATA)?)
+}
+
+/// The problem code of the device node `dev_inst`, a `CM_PROB_*`, if it has
+/// a problem. `None` if it has none or its status can't be read.
+pub fn cm_get_dev_node_problem(dev_inst: DEVINST) -> Option<ULONG> {
+  let mut status: ULONG = 0;
+  let mut problem: ULONG = 0;
+  match unsafe { CM_Get_DevNode_Status(&mut status, &mut problem, dev_inst, 0) } {
+    CR_SUCCESS if status & DN_HAS_PROBLEM != 0 => Some(problem),
+    _ => None,
+  }
+}
+
+/// How a USB device is connected to its hub port, from
+/// `IOCTL_USB_GET_NODE_CONNECTION_INFORMATION_EX(_V2)`.
+pub struct UsbConnectionSpeed {
+  /// A `USB_DEVICE_SPEED`, `UsbLowSpeed` to `UsbSuperSpeed`.
+  pub speed: u8,
+  /// Whether the device operates at SuperSpeedPlus. Only known from Windows
+  /// 8 on; `false` before.
+  pub super_speed_plus: bool,
+}
+
+fn cm_get_parent(dev_inst: DEVINST) -> Option<DEVINST> {
+  let mut parent: DEVINST = 0;
+  match unsafe { CM_Get_Parent(&mut parent, dev_inst, 0) } {
+    CR_SUCCESS => Some(parent),
+    _ => None,
+  }
+}
+
+fn cm_get_device_id(dev_inst: DEVINST) -> Option<Vec<u16>> {
+  let mut buffer = vec![0u16; MAX_DEVICE_ID_LEN + 1];
+  match unsafe { CM_Get_Device_IDW(dev_inst, buffer.as_mut_ptr(), buffer.len() as ULONG, 0) } {
+    CR_SUCCESS => Some(buffer),
+    _ => None,
+  }
+}
+
+/// The port number of a USB device on its hub.
+fn cm_get_address(dev_inst: DEVINST) -> Option<ULONG> {
+  let mut address: ULONG = 0;
+  let mut length = mem::size_of::<ULONG>() as ULONG;
+  match unsafe {
+    CM_Get_DevNode_Registry_PropertyW(
+      dev_inst,
+      CM_DRP_ADDRESS,
+      ptr::null_mut(),
+      &mut address as *mut _ as PVOID,
+      &mut length,
+      0,
+    )
+  } {
+    CR_SUCCESS => Some(address),
+    _ => None,
+  }
+}
+
+/// The path of the USB hub interface of the device with the NUL-terminated
+/// `device_id`, or `None` if it isn't a hub.
+fn cm_get_usb_hub_path(device_id: &mut [u16]) -> Option<String> {
+  let mut guid = GUID_DEVINTERFACE_USB_HUB;
+  let mut length: ULONG = 0;
+  let ret = unsafe {
+    CM_Get_Device_Interface_List_SizeW(
+      &mut length,
+      &mut guid,
+      device_id.as_mut_ptr(),
+      CM_GET_DEVICE_INTERFACE_LIST_PRESENT,
+    )
+  };
+  // An empty list is a single NUL.
+  if ret != CR_SUCCESS || length <= 1 {
+    return None;
+  }
+
+  let mut buffer = vec![0u16; length as usize];
+  let ret = unsafe {
+    CM_Get_Device_Interface_ListW(
+      &mut guid,
+      device_id.as_mut_ptr(),
+      buffer.as_mut_ptr(),
+      length,
+      CM_GET_DEVICE_INTERFACE_LIST_PRESENT,
+    )
+  };
+  if ret != CR_SUCCESS {
+    return None;
+  }
+  let path = lpcwstr_to_string(buffer.as_ptr(), buffer.len());
+  if path.is_empty() {
+    None
+  } else {
+    Some(path)
+  }
+}
+
+fn device_io_control<T>(handle: &Handle, code: DWORD, data: &mut T) -> Result<(), io::Error> {
+  let size = mem::size_of::<T>() as DWORD;
+  let mut returned: DWORD = 0;
+  let ret = unsafe {
+    DeviceIoControl(
+      handle.native_handle.unwrap_or(INVALID_HANDLE_VALUE),
+      code,
+      data as *mut T as PVOID,
+      size,
+      data as *mut T as PVOID,
+      size,
+      &mut returned,
+      ptr::null_mut(),
+    )
+  };
+  if ret == FALSE as i32 {
+    return Err(io::Error::last_os_error());
+  }
+  Ok(())
+}
+
+/// Asks the hub at `hub_path` how the device on `port` is connected.
+fn usb_get_connection_speed(hub_path: &str, port: ULONG) -> Option<UsbConnectionSpeed> {
+  let hub = unsafe {
+    create_file(
+      hub_path,
+      0,
+      FILE_SHARE_READ | FILE_SHARE_WRITE,
+      ptr::null_mut(),
+      OPEN_EXISTING,
+      0,
+      Handle {
+        native_handle: None,
+      },
+    )
+  }
+  .ok()?;
+
+  let mut info: USB_NODE_CONNECTION_INFORMATION_EX = unsafe { mem::zeroed() };
+  info.ConnectionIndex = port;
+  device_io_control(
+    &hub,
+    IOCTL_USB_GET_NODE_CONNECTION_INFORMATION_EX,
+    &mut info,
+  )
+  .ok()?;
+  let speed = info.Speed;
+
+  // Only SuperSpeedPlus needs the V2 request, which Windows 7 lacks.
+  let mut info_v2: USB_NODE_CONNECTION_INFORMATION_EX_V2 = unsafe { mem::zeroed() };
+  info_v2.ConnectionIndex = port;
+  info_v2.Length = mem::size_of::<USB_NODE_CONNECTION_INFORMATION_EX_V2>() as ULONG;
+  info_v2.SupportedUsbProtocols.set_Usb300(1);
+  let super_speed_plus = device_io_control(
+    &hub,
+    IOCTL_USB_GET_NODE_CONNECTION_INFORMATION_EX_V2,
+    &mut info_v2,
+  )
+  .is_ok()
+    && info_v2.Flags.DeviceIsOperatingAtSuperSpeedPlusOrHigher() != 0;
+
+  Some(UsbConnectionSpeed {
+    speed,
+    super_speed_plus,
+  })
+}
+
+/// Finds the USB device `dev_inst` belongs to, i.e. the nearest ancestor
+/// plugged into a hub, and asks the hub how it is connected. `None` if
+/// `dev_inst` isn't on USB or the hub can't be queried.
+pub fn get_usb_connection_speed(dev_inst: DEVINST) -> Option<UsbConnectionSpeed> {
+  // The HID device itself is enumerated by HID; its USB ancestors, the
+  // interface of a composite device and the device itself, by USB. Anything
+  // else in between, e.g. a Bluetooth radio's stack, means the device isn't
+  // on USB itself.
+  let is_usb = |device_id: &[u16]| {
+    lpcwstr_to_string(device_id.as_ptr(), device_id.len())
+      .to_ascii_uppercase()
+      .starts_with("USB\\")
+  };
+
+  let mut node = cm_get_parent(dev_inst)?;
+  let mut node_id = cm_get_device_id(node)?;
+  while is_usb(&node_id) {
+    let parent = cm_get_parent(node)?;
+    let mut parent_id = cm_get_device_id(parent)?;
+    if let Some(hub_path) = cm_get_usb_hub_path(&mut parent_id) {
+      return usb_get_connection_speed(&hub_path, cm_get_address(node)?);
+    }
+    node = parent;
+    node_id = parent_id;
+  }
+  None
+}
+
+pub fn setup_di_get_device_property(
+  handle_dev_info: &HDevInfo,
+  device_info_data: &mut winapi::um::setupapi::SP_DEVINFO_DATA,
+  property_key: &DEVPROPKEY,
+) -> Result<(DEVPROPTYPE, Vec<u8>), io::Error> {
+  let mut property_type: DEVPROPTYPE = 0;
+
+  // 1. retrieve required size of the buffer
+  let mut required_size: DWORD = 0;
+  if unsafe {
+    SetupDiGetDevicePropertyW(
+      handle_dev_info.native_handle.unwrap_or(ptr::null_mut()),
+      device_info_data,
+      property_key,
+      &mut property_type,
+      ptr::null_mut(),
+      0,
+      &mut required_size,
+      0,
+    )
+  } == 0
+    && unsafe { GetLastError() } != ERROR_INSUFFICIENT_BUFFER
+  {
+    return Err(io::Error::last_os_error());
+  }
+
+  // 2. call the API again with a buffer of that size
+  let mut raw_memory = vec![0u8; required_size as usize];
+  if unsafe {
+    SetupDiGetDevicePropertyW(
+      handle_dev_info.native_handle.unwrap_or(ptr::null_mut()),
+      device_info_data,
+      property_key,
+      &mut property_type,
+      raw_memory.as_mut_ptr(),
+      raw_memory.len() as u32,
+      ptr::null_mut(),
+      0,
+    )
+  } == 0
+  {
+    return Err(io::Error::last_os_error());
+  }
+
+  Ok((property_type, raw_memory))
+}
+
+pub fn setup_di_get_device_interface_property(
+  handle_dev_info: &HDevInfo,
+  interface_data: winapi::um::setupapi::PSP_DEVICE_INTERFACE_DATA,
+  property_key: &DEVPROPKEY,
+) -> Result<(DEVPROPTYPE, Vec<u8>), io::Error> {
+  let mut property_type: DEVPROPTYPE = 0;
+
+  // 1. retrieve required size of the buffer
+  let mut required_size: DWORD = 0;
+  if unsafe {
+    SetupDiGetDeviceInterfacePropertyW(
+      handle_dev_info.native_handle.unwrap_or(ptr::null_mut()),
+      interface_data,
+      property_key,
+      &mut property_type,
+      ptr::null_mut(),
+      0,
+      &mut required_size,
+      0,
+    )
+  } == 0
+    && unsafe { GetLastError() } != ERROR_INSUFFICIENT_BUFFER
+  {
+    return Err(io::Error::last_os_error());
+  }
+
+  // 2. call the API again with a buffer of that size
+  let mut raw_memory = vec![0u8; required_size as usize];
+  if unsafe {
+    SetupDiGetDeviceInterfacePropertyW(
+      handle_dev_info.native_handle.unwrap_or(ptr::null_mut()),
+      interface_data,
+      property_key,
+      &mut property_type,
+      raw_memory.as_mut_ptr(),
+      raw_memory.len() as u32,
+      ptr::null_mut(),
+      0,
+    )
+  } == 0
+  {
+    return Err(io::Error::last_os_error());
+  }
+
+  Ok((property_type, raw_memory))
+}
+
+pub fn decode_device_property(property_type: DEVPROPTYPE, buffer: &[u8]) -> Option<DevPropValue> {
+  match property_type {
+    DEVPROP_TYPE_STRING => {
+      let wide: Vec<WCHAR> = buffer
+        .chunks_exact(mem::size_of::<WCHAR>())
+        .map(|c| WCHAR::from_le_bytes([c[0], c[1]]))
+        .take_while(|&c| c != 0)
+        .collect();
+      OsString::from_wide(&wide)
+        .into_string()
+        .ok()
+        .map(DevPropValue::String)
+    }
+    DEVPROP_TYPE_UINT16 if buffer.len() >= 2 => Some(DevPropValue::UInt16(u16::from_le_bytes([
+      buffer[0], buffer[1],
+    ]))),
+    DEVPROP_TYPE_UINT32 if buffer.len() >= 4 => Some(DevPropValue::UInt32(u32::from_le_bytes([
+      buffer[0], buffer[1], buffer[2], buffer[3],
+    ]))),
+    DEVPROP_TYPE_GUID if buffer.len() >= 16 => {
+      let mut guid = [0u8; 16];
+      guid.copy_from_slice(&buffer[..16]);
+      Some(DevPropValue::Guid(guid))
+    }
+    DEVPROP_TYPE_FILETIME if buffer.len() >= 8 => Some(DevPropValue::FileTime(FILETIME {
+      dwLowDateTime: u32::from_le_bytes([buffer[0], buffer[1], buffer[2], buffer[3]]),
+      dwHighDateTime: u32::from_le_bytes([buffer[4], buffer[5], buffer[6], buffer[7]]),
+    })),
+    _ => None,
+  }
+}
+
+fn get_device_property(
+  handle_dev_info: &HDevInfo,
+  device_info_data: Option<winapi::um::setupapi::SP_DEVINFO_DATA>,
+  property_key: &DEVPROPKEY,
+) -> Option<DevPropValue> {
+  let mut info_data = device_info_data?;
+  let (property_type, buffer) =
+    setup_di_get_device_property(handle_dev_info, &mut info_data, property_key).ok()?;
+  decode_device_property(property_type, &buffer)
+}
+
+fn get_interface_property(
+  handle_dev_info: &HDevInfo,
+  mut interface_data: winapi::um::setupapi::SP_DEVICE_INTERFACE_DATA,
+  property_key: &DEVPROPKEY,
+) -> Option<DevPropValue> {
+  let (property_type, buffer) =
+    setup_di_get_device_interface_property(handle_dev_info, &mut interface_data, property_key)
+      .ok()?;
+  decode_device_property(property_type, &buffer)
+}
+
+fn get_interface_u16_pair(
+  handle_dev_info: &HDevInfo,
+  interface_data: winapi::um::setupapi::SP_DEVICE_INTERFACE_DATA,
+  first_key: &DEVPROPKEY,
+  second_key: &DEVPROPKEY,
+) -> Option<(u16, u16)> {
+  match (
+    get_interface_property(handle_dev_info, interface_data, first_key),
+    get_interface_property(handle_dev_info, interface_data, second_key),
+  ) {
+    (Some(DevPropValue::UInt16(first)), Some(DevPropValue::UInt16(second))) => {
+      Some((first, second))
+    }
+    _ => None,
+  }
+}
+
+/// Reads the top-level collection's usage page and usage from the interface
+/// properties, which doesn't require opening the device.
+pub fn get_interface_usage(
+  handle_dev_info: &HDevInfo,
+  interface_data: winapi::um::setupapi::SP_DEVICE_INTERFACE_DATA,
+) -> Option<(u16, u16)> {
+  get_interface_u16_pair(
+    handle_dev_info,
+    interface_data,
+    &DEVPKEY_DeviceInterface_HID_UsagePage,
+    &DEVPKEY_DeviceInterface_HID_UsageId,
+  )
+}
+
+/// Reads the vendor and product IDs from the interface properties, which
+/// doesn't require opening the device.
+pub fn get_interface_ids(
+  handle_dev_info: &HDevInfo,
+  interface_data: winapi::um::setupapi::SP_DEVICE_INTERFACE_DATA,
+) -> Option<(u16, u16)> {
+  get_interface_u16_pair(
+    handle_dev_info,
+    interface_data,
+    &DEVPKEY_DeviceInterface_HID_VendorId,
+    &DEVPKEY_DeviceInterface_HID_ProductId,
+  )
+}
+
+pub fn get_container_id(
+  handle_dev_info: &HDevInfo,
+  device_info_data: Option<winapi::um::setupapi::SP_DEVINFO_DATA>,
+) -> Option<[u8; 16]> {
+  match get_device_property(
+    handle_dev_info,
+    device_info_data,
+    &DEVPKEY_Device_ContainerId,
+  ) {
+    Some(DevPropValue::Guid(container_id)) => Some(container_id),
+    _ => None,
+  }
+}
+
+pub fn get_bus_reported_device_desc(
+  handle_dev_info: &HDevInfo,
+  device_info_data: Option<winapi::um::setupapi::SP_DEVINFO_DATA>,
+) -> Option<String> {
+  match get_device_property(
+    handle_dev_info,
+    device_info_data,
+    &DEVPKEY_Device_BusReportedDeviceDesc,
+  ) {
+    Some(DevPropValue::String(description)) => Some(description),
+    _ => None,
+  }
+}
+
+pub fn get_parent_instance_id(
+  handle_dev_info: &HDevInfo,
+  device_info_data: Option<winapi::um::setupapi::SP_DEVINFO_DATA>,
+) -> Option<String> {
+  match get_device_property(handle_dev_info, device_info_data, &DEVPKEY_Device_Parent) {
+    Some(DevPropValue::String(parent)) => Some(parent),
+    _ => None,
+  }
+}
+
+pub fn get_last_arrival_date(
+  handle_dev_info: &HDevInfo,
+  device_info_data: Option<winapi::um::setupapi::SP_DEVINFO_DATA>,
+) -> Option<SystemTime> {
+  match get_device_property(
+    handle_dev_info,
+    device_info_data,
+    &DEVPKEY_Device_LastArrivalDate,
+  ) {
+    Some(DevPropValue::FileTime(ft)) => Some(filetime_to_system_time(ft)),
+    _ => None,
+  }
+}
+
+pub fn get_last_removal_date(
+  handle_dev_info: &HDevInfo,
+  device_info_data: Option<winapi::um::setupapi::SP_DEVINFO_DATA>,
+) -> Option<SystemTime> {
+  match get_device_property(
+    handle_dev_info,
+    device_info_data,
+    &DEVPKEY_Device_LastRemovalDate,
+  ) {
+    Some(DevPropValue::FileTime(ft)) => Some(filetime_to_system_time(ft)),
+    _ => None,
+  }
+}
+
+/// Reads one of the device's strings with `get`, one of the
+/// `HidD_Get*String` functions. `Ok(None)` if the device returned an empty
+/// string.
+fn hid_d_get_string(
+  handle: &Handle,
+  get: unsafe fn(HANDLE, PVOID, ULONG) -> BOOLEAN,
+) -> Result<Option<String>, io::Error> {
+  const MAXSIZE: usize = 127;
+  let mut buffer: [WCHAR; MAXSIZE] = [0; MAXSIZE];
+  if unsafe {
+    get(
+      handle.native_handle.unwrap_or(ptr::null_mut()),
+      buffer.as_mut_ptr() as PVOID,
+      (buffer.len() * mem::size_of::<WCHAR>()) as ULONG,
+    )
+  } == FALSE
+  {
+    return Err(io::Error::last_os_error());
+  }
+  Ok(Some(lpcwstr_to_string(buffer.as_ptr(), buffer.len())).filter(|string| !string.is_empty()))
+}
+
+/// Reads the device's manufacturer string. Fails with the OS error if the call
+/// does, which is also how many devices without the string answer; `Ok(None)`
+/// only if the device returned it empty.
+pub fn hid_d_get_manufacturer_string_result(handle: &Handle) -> Result<Option<String>, io::Error> {
+  hid_d_get_string(handle, HidD_GetManufacturerString)
+}
+
+/// Same as `hid_d_get_manufacturer_string_result`, with a failure read as no string.
+pub fn hid_d_get_manufacturer_string(handle: &Handle) -> Option<String> {
+  hid_d_get_manufacturer_string_result(handle).ok().flatten()
+}
+
+/// Reads the device's product string. Fails with the OS error if the call
+/// does, which is also how many devices without the string answer; `Ok(None)`
+/// only if the device returned it empty.
+pub fn hid_d_get_product_string_result(handle: &Handle) -> Result<Option<String>, io::Error> {
+  hid_d_get_string(handle, HidD_GetProductString)
+}
+
+/// Same as `hid_d_get_product_string_result`, with a failure read as no string.
+pub fn hid_d_get_product_string(handle: &Handle) -> Option<String> {
+  hid_d_get_product_string_result(handle).ok().flatten()
+}
+
+/// Reads the feature report `report_id` into `data`, without its report ID
+/// byte, so `data` is one byte shorter than the device's
+/// `FeatureReportByteLength`.
+///
+/// `HidD_GetFeature` takes a buffer starting with the report ID, which is 0
+/// for devices that don't use report IDs, followed by the report; that
+/// buffer is set up here and the ID stripped again.
+pub fn hid_d_get_feature(handle: &Handle, report_id: u8, data: &mut [u8]) -> Result<(), io::Error> {
+  let mut buffer = feature_buffer(report_id, data.len());
+
+  if unsafe {
+    HidD_GetFeature(
+      handle.native_handle.unwrap_or(ptr::null_mut()),
+      buffer.as_mut_ptr() as PVOID,
+      buffer.len() as u32,
+    )
+  } == FALSE
+  {
+    return Err(io::Error::last_os_error());
+  }
+
+  data.copy_from_slice(&buffer[1..]);
+  Ok(())
+}
+
+/// A zeroed buffer for a feature report of `report_len` bytes, starting
+/// with the report ID byte.
+fn feature_buffer(report_id: u8, report_len: usize) -> Vec<u8> {
+  let mut buffer = vec![0u8; report_len + 1];
+  buffer[0] = report_id;
+  buffer
+}
+
+/// Polls the input report `report_id` into `buffer`, which must be at least
+/// one byte long. The report ID is written to the first byte before the
+/// call, as `HidD_GetInputReport` expects.
+pub fn hid_d_get_input_report(
+  handle: &Handle,
+  report_id: u8,
+  buffer: &mut [u8],
+) -> Result<(), io::Error> {
+  match buffer.first_mut() {
+    Some(first) => *first = report_id,
+    None => {
+      return Err(io::Error::new(
+        io::ErrorKind::InvalidInput,
+        "the buffer has no room for the report ID",
+      ))
+    }
+  }
+
+  if unsafe {
+    HidD_GetInputReport(
+      handle.native_handle.unwrap_or(ptr::null_mut()),
+      buffer.as_mut_ptr() as PVOID,
+      buffer.len() as u32,
+    )
+  } == FALSE
+  {
+    return Err(io::Error::last_os_error());
+  }
+
+  Ok(())
+}
+
+/// Sends `data` as the feature report `report_id`. Like for
+/// `hid_d_get_feature`, `data` is the report without its report ID byte,
+/// which is prepended here, as 0 for devices that don't use report IDs.
+pub fn hid_d_set_feature(handle: &Handle, report_id: u8, data: &[u8]) -> Result<(), io::Error> {
+  let mut buffer = feature_buffer(report_id, data.len());
+  buffer[1..].copy_from_slice(data);
+
+  if unsafe {
+    HidD_SetFeature(
+      handle.native_handle.unwrap_or(ptr::null_mut()),
+      buffer.as_mut_ptr() as PVOID,
+      buffer.len() as u32,
+    )
+  } == FALSE
+  {
+    return Err(io::Error::last_os_error());
+  }
+
+  Ok(())
+}
+
+/// Sends the output report in `buffer`, whose first byte is the report ID,
+/// over the control pipe.
+pub fn hid_d_set_output_report(handle: &Handle, buffer: &mut [u8]) -> Result<(), io::Error> {
+  if unsafe {
+    HidD_SetOutputReport(
+      handle.native_handle.unwrap_or(ptr::null_mut()),
+      buffer.as_mut_ptr() as PVOID,
+      buffer.len() as u32,
+    )
+  } == FALSE
+  {
+    return Err(io::Error::last_os_error());
+  }
+
+  Ok(())
+}
+
+pub fn hid_d_get_num_input_buffers(handle: &Handle) -> Result<u32, io::Error> {
+  let mut number_buffers = 0;
+
+  if unsafe {
+    HidD_GetNumInputBuffers(
+      handle.native_handle.unwrap_or(ptr::null_mut()),
+      &mut number_buffers,
+    )
+  } == FALSE
+  {
+    return Err(io::Error::last_os_error());
+  }
+
+  Ok(number_buffers)
+}
+
+/// Sets the number of input reports the HID class driver buffers for
+/// `handle`. The driver may not keep the number asked for, so read back the
+/// one in effect with `hid_d_get_num_input_buffers`.
+pub fn hid_d_set_num_input_buffers(handle: &Handle, number_buffers: u32) -> Result<(), io::Error> {
+  if unsafe {
+    HidD_SetNumInputBuffers(
+      handle.native_handle.unwrap_or(ptr::null_mut()),
+      number_buffers,
+    )
+  } == FALSE
+  {
+    return Err(io::Error::last_os_error());
+  }
+
+  Ok(())
+}
+
+/// Reads the physical descriptor sets, or `None` if the device has none.
+///
+/// `HidD_GetPhysicalDescriptor` doesn't report the size it needs, so it is
+/// read into a buffer of the largest size a HID class descriptor can have,
+/// then cut to the length given by its header, see
+/// `physical_descriptor_length`.
+pub fn hid_d_get_physical_descriptor(handle: &Handle) -> Option<Vec<u8>> {
+  let mut buffer = vec![0u8; u16::MAX as usize];
+  if unsafe {
+    HidD_GetPhysicalDescriptor(
+      handle.native_handle.unwrap_or(ptr::null_mut()),
+      buffer.as_mut_ptr() as PVOID,
+      buffer.len() as u32,
+    )
+  } == FALSE
+  {
+    return None;
+  }
+
+  buffer.truncate(physical_descriptor_length(&buffer)?);
+  Some(buffer)
+}
+
+/// The length of the physical descriptor sets at the start of `data`, from
+/// descriptor set 0: the number of sets in its first byte and the length of
+/// each in the next two. `None` without any sets.
+fn physical_descriptor_length(data: &[u8]) -> Option<usize> {
+  const HEADER_LENGTH: usize = 3;
+
+  let header = data.get(..HEADER_LENGTH)?;
+  let count = header[0] as usize;
+  let set_length = u16::from_le_bytes([header[1], header[2]]) as usize;
+  if count == 0 || set_length == 0 {
+    return None;
+  }
+  Some((HEADER_LENGTH + count * set_length).min(data.len()))
+}
+
+/// Reads the device's serial number string. Fails with the OS error if the call
+/// does, which is also how many devices without the string answer; `Ok(None)`
+/// only if the device returned it empty.
+pub fn hid_d_get_serial_number_string_result(handle: &Handle) -> Result<Option<String>, io::Error> {
+  hid_d_get_string(handle, HidD_GetSerialNumberString)
+}
+
+/// Same as `hid_d_get_serial_number_string_result`, with a failure read as no string.
+pub fn hid_d_get_serial_number_string(handle: &Handle) -> Option<String> {
+  hid_d_get_serial_number_string_result(handle).ok().flatten()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use winapi::shared::winerror::ERROR_INVALID_DATA;
+
+  fn wide(s: &str) -> Vec<WCHAR> {
+    OsStr::new(s).encode_wide().collect()
+  }
+
+  #[test]
+  fn lpcwstr_to_string_stops_at_the_first_nul() {
+    let mut buffer = wide("Keyboard");
+    buffer.extend_from_slice(&[0, 'X' as WCHAR, 'Y' as WCHAR]);
+    assert_eq!(lpcwstr_to_string(buffer.as_ptr(), buffer.len()), "Keyboard");
+  }
+
+  #[test]
+  fn lpcwstr_to_string_takes_an_unterminated_buffer_whole() {
+    let buffer = wide("Keyboard");
+    assert_eq!(lpcwstr_to_string(buffer.as_ptr(), buffer.len()), "Keyboard");
+  }
+
+  #[test]
+  fn lpcwstr_to_string_reads_empty_buffers_as_empty() {
+    let buffer: [WCHAR; 4] = [0; 4];
+    assert_eq!(lpcwstr_to_string(buffer.as_ptr(), buffer.len()), "");
+    assert_eq!(lpcwstr_to_string(buffer.as_ptr(), 0), "");
+  }
+
+  #[test]
+  fn lpcwstr_to_string_replaces_invalid_utf16() {
+    let buffer: [WCHAR; 3] = ['A' as WCHAR, 0xd800, 'B' as WCHAR];
+    assert_eq!(
+      lpcwstr_to_string(buffer.as_ptr(), buffer.len()),
+      "A\u{fffd}B"
+    );
+  }
+
+  #[test]
+  fn filetime_to_system_time_converts_known_values() {
+    // 2021-01-01T00:00:00Z, 1_609_459_200 seconds after the Unix epoch.
+    let intervals: u64 = 132_539_328_000_000_000 + 5;
+    let ft = FILETIME {
+      dwLowDateTime: intervals as u32,
+      dwHighDateTime: (intervals >> 32) as u32,
+    };
+    assert_eq!(
+      filetime_to_system_time(ft),
+      UNIX_EPOCH + Duration::new(1_609_459_200, 500)
+    );
+  }
+
+  #[test]
+  fn filetime_to_system_time_handles_times_before_the_unix_epoch() {
+    let zero = FILETIME {
+      dwLowDateTime: 0,
+      dwHighDateTime: 0,
+    };
+    assert_eq!(
+      filetime_to_system_time(zero),
+      UNIX_EPOCH - Duration::from_secs(11_644_473_600)
+    );
+  }
+
+  #[test]
+  fn device_path_capacity_rejects_sizes_below_the_struct() {
+    let struct_size = mem::size_of::<SP_DEVICE_INTERFACE_DETAIL_DATA_W>() as u32;
+    for required_size in [0, 1, mem::size_of::<DWORD>() as u32, struct_size - 1] {
+      let error = device_path_capacity(required_size).unwrap_err();
+      assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+  }
+
+  #[test]
+  fn device_path_capacity_counts_the_wchars_after_cb_size() {
+    let struct_size = mem::size_of::<SP_DEVICE_INTERFACE_DETAIL_DATA_W>();
+    assert_eq!(
+      device_path_capacity(struct_size as u32).unwrap(),
+      (struct_size - mem::size_of::<DWORD>()) / mem::size_of::<WCHAR>()
+    );
+    // A 40-character path and its NUL after the 4-byte cbSize.
+    assert_eq!(device_path_capacity(4 + 41 * 2).unwrap(), 41);
+  }
+
+  /// A property of `sizes[n]` bytes on the `n`th read, filled with `0xab`.
+  fn growing_property(
+    sizes: &[u32],
+  ) -> impl FnMut(&mut [u8], &mut DWORD) -> Result<(), io::Error> + '_ {
+    let mut calls = 0;
+    move |buffer, required_size| {
+      let size = sizes[calls.min(sizes.len() - 1)];
+      calls += 1;
+      *required_size = size;
+      if buffer.len() < size as usize {
+        return Err(io::Error::from_raw_os_error(
+          ERROR_INSUFFICIENT_BUFFER as i32,
+        ));
+      }
+      buffer[..size as usize].fill(0xab);
+      Ok(())
+    }
+  }
+
+  #[test]
+  fn read_into_growing_buffer_grows_when_the_property_grows() {
+    let mut buffer = Vec::new();
+    read_into_growing_buffer(&mut buffer, growing_property(&[300, 400])).unwrap();
+    assert_eq!(buffer, vec![0xab; 400]);
+  }
+
+  #[test]
+  fn read_into_growing_buffer_reads_small_properties_in_one_call() {
+    let mut buffer = Vec::new();
+    read_into_growing_buffer(&mut buffer, growing_property(&[10])).unwrap();
+    assert_eq!(buffer, vec![0xab; 10]);
+  }
+
+  #[test]
+  fn read_into_growing_buffer_gives_up_on_a_property_that_keeps_growing() {
+    let mut buffer = Vec::new();
+    let error = read_into_growing_buffer(&mut buffer, growing_property(&[300, 400, 500, 600, 700]))
+      .unwrap_err();
+    assert_eq!(error.raw_os_error(), Some(ERROR_INSUFFICIENT_BUFFER as i32));
+  }
+
+  #[test]
+  fn read_into_growing_buffer_passes_other_errors_through() {
+    let mut buffer = Vec::new();
+    let error = read_into_growing_buffer(&mut buffer, |_, _| {
+      Err(io::Error::from_raw_os_error(ERROR_INVALID_DATA as i32))
+    })
+    .unwrap_err();
+    assert_eq!(error.raw_os_error(), Some(ERROR_INVALID_DATA as i32));
+  }
+
+  #[test]
+  fn feature_buffer_starts_with_the_report_id() {
+    let mut buffer = feature_buffer(5, 3);
+    assert_eq!(buffer, [5, 0, 0, 0]);
+    buffer[1..].copy_from_slice(&[0x10, 0x20, 0x30]);
+    assert_eq!(buffer, [5, 0x10, 0x20, 0x30]);
+  }
+
+  #[test]
+  fn feature_buffer_starts_with_zero_without_report_ids() {
+    // The device's FeatureReportByteLength counts the zero byte too.
+    assert_eq!(feature_buffer(0, 3), [0, 0, 0, 0]);
+    assert_eq!(feature_buffer(0, 0), [0]);
+  }
+
+  #[test]
+  fn physical_descriptor_length_follows_the_header() {
+    // Two sets of four bytes after the three-byte header, then unused bytes.
+    let mut data = vec![0x02, 0x04, 0x00];
+    data.extend_from_slice(&[0x11; 8]);
+    data.resize(256, 0);
+    assert_eq!(physical_descriptor_length(&data), Some(11));
+  }
+
+  #[test]
+  fn physical_descriptor_length_is_capped_by_the_data() {
+    assert_eq!(
+      physical_descriptor_length(&[0xff, 0xff, 0xff, 0x00]),
+      Some(4)
+    );
+  }
+
+  #[test]
+  fn physical_descriptor_length_is_none_without_sets() {
+    assert_eq!(physical_descriptor_length(&[]), None);
+    assert_eq!(physical_descriptor_length(&[0x01, 0x04]), None);
+    assert_eq!(physical_descriptor_length(&[0x00, 0x04, 0x00, 0x00]), None);
+    assert_eq!(physical_descriptor_length(&[0x01, 0x00, 0x00, 0x00]), None);
+  }
+
+  #[test]
+  fn decodes_string_properties_up_to_the_nul() {
+    let buffer: Vec<u8> = wide("USB\\VID_046D\0junk")
+      .iter()
+      .flat_map(|c| c.to_le_bytes())
+      .collect();
+    assert!(matches!(
+      decode_device_property(DEVPROP_TYPE_STRING, &buffer),
+      Some(DevPropValue::String(s)) if s == "USB\\VID_046D"
+    ));
+  }
+
+  #[test]
+  fn decodes_uint32_properties() {
+    let buffer = [0x78, 0x56, 0x34, 0x12];
+    assert!(matches!(
+      decode_device_property(DEVPROP_TYPE_UINT32, &buffer),
+      Some(DevPropValue::UInt32(0x1234_5678))
+    ));
+  }
+
+  #[test]
+  fn decodes_guid_properties() {
+    let buffer: Vec<u8> = (0..16).collect();
+    assert!(matches!(
+      decode_device_property(DEVPROP_TYPE_GUID, &buffer),
+      Some(DevPropValue::Guid(guid)) if guid[..] == buffer[..]
+    ));
+  }
+
+  #[test]
+  fn decodes_filetime_properties() {
+    let buffer = [0x01, 0x00, 0x00, 0x00, 0x02, 0x00, 0x00, 0x00];
+    assert!(matches!(
+      decode_device_property(DEVPROP_TYPE_FILETIME, &buffer),
+      Some(DevPropValue::FileTime(FILETIME {
+        dwLowDateTime: 1,
+        dwHighDateTime: 2,
+      }))
+    ));
+  }
+
+  #[test]
+  fn short_buffers_decode_to_none() {
+    assert!(decode_device_property(DEVPROP_TYPE_UINT16, &[0x01]).is_none());
+    assert!(decode_device_property(DEVPROP_TYPE_UINT32, &[0x01, 0x02, 0x03]).is_none());
+    assert!(decode_device_property(DEVPROP_TYPE_GUID, &[0; 15]).is_none());
+    assert!(decode_device_property(DEVPROP_TYPE_FILETIME, &[0; 7]).is_none());
+  }
+
+  #[test]
+  fn get_input_report_rejects_an_empty_buffer() {
+    let handle = Handle {
+      native_handle: None,
+    };
+    let error = hid_d_get_input_report(&handle, 1, &mut []).unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
   }
 }
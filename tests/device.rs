@@ -0,0 +1,59 @@
+//! Runs against a real device, named by `LISTHID_TEST_DEVICE` as its vendor
+//! and product IDs in hex, e.g. `LISTHID_TEST_DEVICE=046d:c077 cargo test
+//! -- --ignored`. The tests return early if it isn't set.
+#![cfg(windows)]
+
+use listhid::OpenHidDevice;
+
+fn test_device() -> Option<(u16, u16)> {
+  let ids = std::env::var("LISTHID_TEST_DEVICE").ok()?;
+  let (vendor_id, product_id) = ids
+    .split_once(':')
+    .expect("LISTHID_TEST_DEVICE is not VID:PID");
+  Some((
+    u16::from_str_radix(vendor_id, 16).expect("bad vendor ID"),
+    u16::from_str_radix(product_id, 16).expect("bad product ID"),
+  ))
+}
+
+fn open_test_device() -> Option<OpenHidDevice> {
+  let (vendor_id, product_id) = test_device()?;
+  Some(listhid::open_by_ids(vendor_id, product_id, None).expect("test device not found"))
+}
+
+#[test]
+#[ignore = "needs LISTHID_TEST_DEVICE, sending an input report"]
+fn reads_a_whole_input_report() {
+  let mut device = match open_test_device() {
+    Some(device) => device,
+    None => return,
+  };
+  println!("Use the test device to have it send an input report.");
+  let mut report = vec![0; device.input_report_byte_length()];
+  let length = device.read(&mut report).unwrap();
+  assert_eq!(length, device.input_report_byte_length());
+}
+
+#[test]
+#[ignore = "needs LISTHID_TEST_DEVICE"]
+fn input_buffer_count_round_trips() {
+  let device = match open_test_device() {
+    Some(device) => device,
+    None => return,
+  };
+  let original = device.input_buffer_count().unwrap();
+  for count in [2, 64, 512] {
+    device.set_input_buffer_count(count).unwrap();
+    assert_eq!(device.input_buffer_count().unwrap(), count);
+  }
+
+  for count in [1, 513] {
+    assert!(matches!(
+      device.set_input_buffer_count(count),
+      Err(listhid::Error::InvalidInputBufferCount(attempted)) if attempted == count
+    ));
+  }
+  // Rejected counts leave the setting alone.
+  assert_eq!(device.input_buffer_count().unwrap(), 512);
+  device.set_input_buffer_count(original).unwrap();
+}
@@ -0,0 +1,51 @@
+//! Runs the `listhid` command on the build machine and checks its output.
+
+use std::process::{Command, Output};
+
+fn listhid(args: &[&str]) -> Output {
+  Command::new(env!("CARGO_BIN_EXE_listhid"))
+    .args(args)
+    .output()
+    .expect("failed to run listhid")
+}
+
+#[cfg(feature = "json")]
+fn parse_devices(output: &Output) -> Vec<serde_json::Value> {
+  assert!(
+    output.status.success(),
+    "listhid failed: {}",
+    String::from_utf8_lossy(&output.stderr)
+  );
+  match serde_json::from_slice(&output.stdout).expect("output is not JSON") {
+    serde_json::Value::Array(devices) => devices,
+    other => panic!("expected an array, got {}", other),
+  }
+}
+
+#[cfg(feature = "json")]
+#[test]
+fn json_is_an_array_of_devices() {
+  for device in parse_devices(&listhid(&["--json"])) {
+    assert!(device["path"].is_string(), "{}", device);
+    assert!(device["vendor_id"].is_number() || device["vendor_id"].is_string());
+    assert!(device.get("product_string").is_some(), "{}", device);
+  }
+}
+
+#[cfg(feature = "json")]
+#[test]
+fn compact_json_is_one_line() {
+  let output = listhid(&["--json", "--compact"]);
+  parse_devices(&output);
+  assert_eq!(output.stdout.iter().filter(|&&c| c == b'\n').count(), 1);
+}
+
+#[test]
+fn bad_options_fail_with_usage() {
+  for args in [&["--bogus"][..], &["--compact"][..]] {
+    let output = listhid(args);
+    assert_eq!(output.status.code(), Some(2), "{:?}", args);
+    assert!(output.stdout.is_empty());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("usage: listhid"));
+  }
+}
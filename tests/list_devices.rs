@@ -0,0 +1,8 @@
+//! Lists the devices of the machine the tests run on.
+
+#[test]
+#[ignore = "needs a machine with a HID stack"]
+fn lists_the_devices() {
+  let devices = listhid::list_hid_device();
+  assert!(devices.is_ok(), "{:?}", devices.err());
+}